@@ -0,0 +1,120 @@
+// Speed on 8080EXM.COM, with the bus shared behind a lock or owned by the interpreter, stepping or with run_for_cycles(),
+// and of the block cache engine.
+// Usage: cargo bench --bench interpreter [cycles]
+
+use std::{env, fs, hint::black_box, sync::{Arc, RwLock}, time::Instant};
+
+use r8080::{asm::assemble, cpu::{BlockCache8080, BusStorage, Interpreter8080, Registers, CPU8080}, Bus8080};
+
+// Just enough CP/M for the exerciser: port 0 stops, port 1 is BDOS with the output thrown away.
+struct BenchBus
+{
+    ram: Box<[u8; 0x10000]>
+}
+
+impl BenchBus
+{
+    fn new(program: &[u8]) -> Self {
+        let mut bus = Self { ram: Box::new([0x00; 0x10000]) };
+        let bios = assemble("
+            ORG 0000H
+            OUT 0
+            ORG 0005H
+            OUT 1
+            RET
+        ").unwrap();
+        bus.write_buffer(bios.origin, bios.bytes);
+        bus.write_buffer(0x0100, program.to_vec());
+        bus
+    }
+}
+
+impl Bus8080 for BenchBus
+{
+    fn read_b(&self, a: u16) -> u8 {
+        self.ram[a as usize]
+    }
+
+    fn read_w(&self, a: u16) -> u16 {
+        u16::from_le_bytes([self.read_b(a), self.read_b(a.wrapping_add(1))])
+    }
+
+    fn has_interrupt(&self) -> bool {
+        false
+    }
+
+    fn get_interrupt(&mut self) -> u8 {
+        0x00
+    }
+
+    fn push_interrupt(&mut self, _: u8) {}
+
+    fn write_b(&mut self, a: u16, b: u8) {
+        self.ram[a as usize] = b;
+    }
+
+    fn write_w(&mut self, a: u16, w: u16) {
+        let [low, high] = w.to_le_bytes();
+        self.write_b(a, low);
+        self.write_b(a.wrapping_add(1), high);
+    }
+
+    fn in_b(&mut self, _: &mut Registers, _: u8) -> u8 {
+        0xFF
+    }
+
+    fn out_b(&mut self, regs: &mut Registers, b: u8, _: u8) {
+        if b == 0x00 {
+            regs.running = false;
+        }
+        black_box(regs.e);
+    }
+
+    fn write_buffer(&mut self, a: u16, data: Vec<u8>) {
+        self.ram[a as usize..a as usize + data.len()].copy_from_slice(&data);
+    }
+}
+
+fn measure<B: BusStorage>(name: &str, mut cpu: Interpreter8080<B>, cycles: u64, run_for: bool) -> f64 {
+    cpu.force_jump(0x0100);
+    let start = Instant::now();
+    let mut instructions = 0u64;
+    if run_for {
+        cpu.run_for_cycles(cycles).unwrap();
+    } else {
+        while cpu.get_executed_cycles() < cycles && cpu.is_running() {
+            cpu.step().unwrap();
+            instructions += 1;
+        }
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    let mhz = cpu.get_executed_cycles() as f64 / elapsed / 1e6;
+    let rate = if run_for { String::new() } else { format!(", {:.1}M instructions/s", instructions as f64 / elapsed / 1e6) };
+    println!("{:<16} {:>11} cycles in {:>6.3}s, {:>7.1} MHz equivalent{}", name, cpu.get_executed_cycles(), elapsed, mhz, rate);
+    mhz
+}
+
+fn main() {
+    // cargo bench passes --bench, anything numeric is the cycle budget.
+    let cycles = env::args().skip(1).find_map(|argument| argument.parse().ok()).unwrap_or(1_000_000_000);
+    let program = fs::read("test_roms/8080EXM.COM").unwrap();
+    let shared = || {
+        let mut cpu = Interpreter8080::new();
+        cpu.set_bus(Arc::new(RwLock::new(Box::new(BenchBus::new(&program)))));
+        cpu
+    };
+    let owned = || Interpreter8080::with_bus(BenchBus::new(&program));
+
+    let baseline = measure("shared step", shared(), cycles, false);
+    measure("owned step", owned(), cycles, false);
+    measure("shared run_for", shared(), cycles, true);
+    let fastest = measure("owned run_for", owned(), cycles, true);
+    println!("owned run_for is {:.2}x shared step", fastest / baseline);
+
+    let mut cached = BlockCache8080::with_bus(BenchBus::new(&program));
+    cached.force_jump(0x0100);
+    let start = Instant::now();
+    cached.run_for_cycles(cycles).unwrap();
+    let elapsed = start.elapsed().as_secs_f64();
+    println!("{:<16} {:>11} cycles in {:>6.3}s, {:>7.1} MHz equivalent", "owned cache", cached.get_executed_cycles(), elapsed, cached.get_executed_cycles() as f64 / elapsed / 1e6);
+}
@@ -0,0 +1,562 @@
+use std::{collections::{BTreeMap, HashMap}, fmt, sync::OnceLock};
+
+use crate::cpu::Model;
+use crate::disassembler::{format_instruction, Syntax};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsmError
+{
+    pub line: usize,
+    pub column: usize,
+    pub message: String
+}
+
+impl fmt::Display for AsmError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program
+{
+    // Address of the first byte, gaps left by ORG and DS are zero filled.
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+    pub symbols: BTreeMap<String, u16>
+}
+
+// Assembles for the 8080, see assemble_for() for the 8085.
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    assemble_for(source, Model::Intel8080)
+}
+
+// The 8085 adds RIM, SIM and its undocumented instructions, they are unknown instructions for the 8080.
+pub fn assemble_for(source: &str, model: Model) -> Result<Program, AsmError> {
+    let statements = source.lines().enumerate()
+        .map(|(index, line)| parse_line(index + 1, line))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut assembler = Assembler { model, symbols: HashMap::new(), address: 0, line: 0 };
+    assembler.first_pass(&statements)?;
+    assembler.second_pass(&statements)
+}
+
+// Tokenizer.
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind
+{
+    Identifier(String),
+    Number(i64),
+    Text(Vec<u8>),
+    Dollar,
+    Symbol(char)
+}
+
+#[derive(Debug, Clone)]
+struct Token
+{
+    kind: TokenKind,
+    column: usize
+}
+
+fn error<T>(line: usize, column: usize, message: impl Into<String>) -> Result<T, AsmError> {
+    Err(AsmError { line, column, message: message.into() })
+}
+
+fn tokenize(line: usize, text: &str) -> Result<Vec<Token>, AsmError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+
+    while index < chars.len() {
+        let c = chars[index];
+        let column = index + 1;
+
+        if c == ';' { break }
+        if c.is_whitespace() { index += 1; continue }
+
+        if c == '\'' {
+            let mut value = Vec::new();
+            index += 1;
+            loop {
+                match chars.get(index) {
+                    None => return error(line, column, "unterminated string"),
+                    // Two quotes in a row stand for a single one.
+                    Some('\'') if chars.get(index + 1) == Some(&'\'') => { value.push(b'\''); index += 2; }
+                    Some('\'') => { index += 1; break }
+                    Some(c) => { value.push(*c as u8); index += 1; }
+                }
+            }
+            tokens.push(Token { kind: TokenKind::Text(value), column });
+        }
+        else if c.is_ascii_alphanumeric() || c == '_' || c == '?' || c == '@' || c == '.' {
+            let start = index;
+            while index < chars.len() && (chars[index].is_ascii_alphanumeric() || "_?@.".contains(chars[index])) {
+                index += 1;
+            }
+            let word: String = chars[start..index].iter().collect();
+            let kind = if c.is_ascii_digit() {
+                TokenKind::Number(parse_number(&word).ok_or_else(|| AsmError { line, column, message: format!("invalid number '{}'", word) })?)
+            } else {
+                TokenKind::Identifier(word.to_ascii_uppercase())
+            };
+            tokens.push(Token { kind, column });
+        }
+        else if c == '$' {
+            tokens.push(Token { kind: TokenKind::Dollar, column });
+            index += 1;
+        }
+        else if "+-*/(),:".contains(c) {
+            tokens.push(Token { kind: TokenKind::Symbol(c), column });
+            index += 1;
+        }
+        else {
+            return error(line, column, format!("unexpected character '{}'", c));
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_number(word: &str) -> Option<i64> {
+    let word = word.to_ascii_uppercase().replace('_', "");
+    if let Some(hex) = word.strip_prefix("0X") {
+        return i64::from_str_radix(hex, 16).ok();
+    }
+    let (digits, radix) = match word.chars().last()? {
+        'H' => (&word[..word.len() - 1], 16),
+        'B' => (&word[..word.len() - 1], 2),
+        'O' | 'Q' => (&word[..word.len() - 1], 8),
+        'D' => (&word[..word.len() - 1], 10),
+        _ => (&word[..], 10)
+    };
+    i64::from_str_radix(digits, radix).ok()
+}
+
+// Statements.
+
+const DIRECTIVES: [&str; 6] = ["ORG", "DB", "DW", "DS", "EQU", "END"];
+
+struct Statement
+{
+    line: usize,
+    label: Option<(String, usize)>,
+    operation: Option<(String, usize)>,
+    operands: Vec<Vec<Token>>
+}
+
+// The 8085 mnemonics are a superset, so a line parses the same whatever the CPU.
+fn is_operation(word: &str) -> bool {
+    DIRECTIVES.contains(&word) || opcode_table(Model::Intel8085).contains_key(word) || word == "RST"
+}
+
+fn parse_line(line: usize, text: &str) -> Result<Statement, AsmError> {
+    let mut tokens = tokenize(line, text)?.into_iter().peekable();
+    let mut statement = Statement { line, label: None, operation: None, operands: Vec::new() };
+
+    let mut first = tokens.next();
+    if let Some(Token { kind: TokenKind::Identifier(name), column }) = &first {
+        let colon = matches!(tokens.peek(), Some(Token { kind: TokenKind::Symbol(':'), .. }));
+        let equ = matches!(tokens.peek(), Some(Token { kind: TokenKind::Identifier(word), .. }) if word == "EQU");
+
+        // Labels either end with a colon, start at the first column or name an EQU.
+        if colon || equ || (*column == 1 && !is_operation(name)) {
+            statement.label = Some((name.clone(), *column));
+            if colon { tokens.next(); }
+            first = tokens.next();
+        }
+    }
+
+    match first {
+        None => return Ok(statement),
+        Some(Token { kind: TokenKind::Identifier(name), column }) => statement.operation = Some((name, column)),
+        Some(token) => return error(line, token.column, "expected an instruction or directive")
+    }
+
+    let mut operand = Vec::new();
+    for token in tokens {
+        if token.kind == TokenKind::Symbol(',') {
+            statement.operands.push(std::mem::take(&mut operand));
+        } else {
+            operand.push(token);
+        }
+    }
+    if !operand.is_empty() || !statement.operands.is_empty() {
+        statement.operands.push(operand);
+    }
+    Ok(statement)
+}
+
+// Opcode lookup, built from the decoder and the disassembler so both always agree with the assembler.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Immediate
+{
+    None,
+    Byte,
+    Word
+}
+
+type OpcodeTable = HashMap<String, Vec<(Vec<String>, u8, Immediate)>>;
+
+fn opcode_table(model: Model) -> &'static OpcodeTable {
+    static TABLE_8080: OnceLock<OpcodeTable> = OnceLock::new();
+    static TABLE_8085: OnceLock<OpcodeTable> = OnceLock::new();
+    let table = match model {
+        Model::Intel8080 => &TABLE_8080,
+        Model::Intel8085 => &TABLE_8085
+    };
+    table.get_or_init(|| {
+        let mut table: OpcodeTable = HashMap::new();
+
+        // Lower opcodes win, so undocumented aliases never shadow the documented encoding.
+        // The 8085 table is the 8080 one plus RIM, SIM and the undocumented 8085 instructions.
+        for opcode in 0x00..=0xFF {
+            let Ok((instruction, length)) = model.decode(&[opcode, 0x00, 0x00]) else { continue };
+            let text = format_instruction(&instruction, Syntax::Intel);
+            let (mnemonic, operands) = text.split_once(' ').unwrap_or((&text, ""));
+            if mnemonic == "RST" || mnemonic == "DB" { continue }
+
+            let mut registers: Vec<String> = operands.split(',').filter(|s| !s.is_empty()).map(str::to_string).collect();
+            let immediate = match length {
+                2 => Immediate::Byte,
+                3 => Immediate::Word,
+                _ => Immediate::None
+            };
+            if immediate != Immediate::None { registers.pop(); }
+
+            let forms = table.entry(mnemonic.to_string()).or_default();
+            if !forms.iter().any(|(existing, _, _)| *existing == registers) {
+                forms.push((registers, opcode, immediate));
+            }
+        }
+        table
+    })
+}
+
+const REGISTERS: [&str; 10] = ["A", "B", "C", "D", "E", "H", "L", "M", "SP", "PSW"];
+
+fn register_name(operand: &[Token]) -> Option<&str> {
+    match operand {
+        [Token { kind: TokenKind::Identifier(name), .. }] if REGISTERS.contains(&name.as_str()) => Some(name),
+        _ => None
+    }
+}
+
+// Assembler passes.
+
+struct Assembler
+{
+    model: Model,
+    symbols: HashMap<String, i64>,
+    address: i64,
+    line: usize
+}
+
+enum Output<'a>
+{
+    Sizing,
+    Emit(&'a mut BTreeMap<u16, u8>)
+}
+
+impl Assembler
+{
+    fn first_pass(&mut self, statements: &[Statement]) -> Result<(), AsmError> {
+        let mut pending_equ = Vec::new();
+        self.address = 0;
+
+        for statement in statements {
+            self.line = statement.line;
+            let operation = statement.operation.as_ref().map(|(name, _)| name.as_str());
+
+            if operation == Some("EQU") {
+                let (name, column) = statement.label.clone().unwrap_or_default();
+                self.check_redefinition(&name, column)?;
+                // EQU may reference symbols defined further down, those get resolved after this pass.
+                match self.expression_operand(statement, 0) {
+                    Ok(value) => { self.symbols.insert(name, value); }
+                    Err(_) => pending_equ.push(statement)
+                }
+                continue;
+            }
+
+            if let Some((name, column)) = &statement.label {
+                self.check_redefinition(name, *column)?;
+                self.symbols.insert(name.clone(), self.address);
+            }
+            if operation == Some("END") { break }
+            self.statement(statement, &mut Output::Sizing)?;
+        }
+
+        while !pending_equ.is_empty() {
+            let before = pending_equ.len();
+            let mut last_error = None;
+            pending_equ.retain(|statement| {
+                self.line = statement.line;
+                match self.expression_operand(statement, 0) {
+                    Ok(value) => {
+                        self.symbols.insert(statement.label.clone().unwrap_or_default().0, value);
+                        false
+                    }
+                    Err(error) => { last_error = Some(error); true }
+                }
+            });
+            if pending_equ.len() == before {
+                return Err(last_error.unwrap());
+            }
+        }
+        Ok(())
+    }
+
+    fn second_pass(&mut self, statements: &[Statement]) -> Result<Program, AsmError> {
+        let mut memory = BTreeMap::new();
+        self.address = 0;
+
+        for statement in statements {
+            self.line = statement.line;
+            match statement.operation.as_ref().map(|(name, _)| name.as_str()) {
+                Some("EQU") => continue,
+                Some("END") => break,
+                _ => self.statement(statement, &mut Output::Emit(&mut memory))?
+            }
+        }
+
+        let symbols = self.symbols.iter().map(|(name, value)| (name.clone(), *value as u16)).collect();
+        let (Some((&first, _)), Some((&last, _))) = (memory.first_key_value(), memory.last_key_value()) else {
+            return Ok(Program { origin: 0, bytes: Vec::new(), symbols });
+        };
+
+        let mut bytes = vec![0x00; (last - first) as usize + 1];
+        for (address, value) in memory {
+            bytes[(address - first) as usize] = value;
+        }
+        Ok(Program { origin: first, bytes, symbols })
+    }
+
+    fn check_redefinition(&self, name: &str, column: usize) -> Result<(), AsmError> {
+        if name.is_empty() {
+            return error(self.line, column.max(1), "EQU needs a name");
+        }
+        if self.symbols.contains_key(name) {
+            return error(self.line, column, format!("symbol '{}' is already defined", name));
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, output: &mut Output, bytes: &[u8]) {
+        if let Output::Emit(memory) = output {
+            for (offset, byte) in bytes.iter().enumerate() {
+                memory.insert((self.address + offset as i64) as u16, *byte);
+            }
+        }
+        self.address += bytes.len() as i64;
+    }
+
+    fn statement(&mut self, statement: &Statement, output: &mut Output) -> Result<(), AsmError> {
+        let Some((operation, column)) = &statement.operation else { return Ok(()) };
+        let emitting = matches!(output, Output::Emit(_));
+
+        match operation.as_str() {
+            "ORG" => {
+                self.address = self.expression_operand(statement, 0)?;
+                self.expect_operands(statement, 1, *column)?;
+            }
+            "DS" => {
+                let size = self.expression_operand(statement, 0)?;
+                self.expect_operands(statement, 1, *column)?;
+                self.emit(output, &vec![0x00; size.max(0) as usize]);
+            }
+            "DB" => {
+                for operand in &statement.operands {
+                    match operand.as_slice() {
+                        [Token { kind: TokenKind::Text(text), .. }] => self.emit(output, text),
+                        _ => {
+                            let value = if emitting { self.byte(operand)? } else { 0 };
+                            self.emit(output, &[value]);
+                        }
+                    }
+                }
+            }
+            "DW" => {
+                for operand in &statement.operands {
+                    let value = if emitting { self.word(operand)? } else { 0 };
+                    self.emit(output, &value.to_le_bytes());
+                }
+            }
+            "RST" => {
+                self.expect_operands(statement, 1, *column)?;
+                let vector = if emitting { self.expression_operand(statement, 0)? } else { 0 };
+                if !(0..=7).contains(&vector) {
+                    return error(self.line, statement.operands[0][0].column, format!("RST vector {} is out of range", vector));
+                }
+                self.emit(output, &[0xC7 | (vector as u8) << 3]);
+            }
+            mnemonic => {
+                let Some(forms) = opcode_table(self.model).get(mnemonic) else {
+                    if opcode_table(Model::Intel8085).contains_key(mnemonic) {
+                        return error(self.line, *column, format!("'{}' is an 8085 instruction", mnemonic));
+                    }
+                    return error(self.line, *column, format!("unknown instruction '{}'", mnemonic));
+                };
+
+                let registers: Vec<String> = statement.operands.iter().map_while(|o| register_name(o).map(str::to_string)).collect();
+                let expressions = &statement.operands[registers.len()..];
+                let form = forms.iter().find(|(form_registers, _, immediate)| {
+                    *form_registers == registers && expressions.len() == (*immediate != Immediate::None) as usize
+                });
+                let Some(&(_, opcode, immediate)) = form else {
+                    let column = statement.operands.first().and_then(|o| o.first()).map_or(*column, |t| t.column);
+                    return error(self.line, column, format!("invalid operands for {}", mnemonic));
+                };
+
+                match immediate {
+                    Immediate::None => self.emit(output, &[opcode]),
+                    Immediate::Byte => {
+                        let value = if emitting { self.byte(&expressions[0])? } else { 0 };
+                        self.emit(output, &[opcode, value]);
+                    }
+                    Immediate::Word => {
+                        let [low, high] = if emitting { self.word(&expressions[0])?.to_le_bytes() } else { [0, 0] };
+                        self.emit(output, &[opcode, low, high]);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn expect_operands(&self, statement: &Statement, count: usize, column: usize) -> Result<(), AsmError> {
+        if statement.operands.len() != count {
+            return error(self.line, column, format!("expected {} operand(s)", count));
+        }
+        Ok(())
+    }
+
+    fn expression_operand(&self, statement: &Statement, index: usize) -> Result<i64, AsmError> {
+        match statement.operands.get(index) {
+            Some(operand) => self.evaluate(operand),
+            None => error(self.line, statement.operation.as_ref().map_or(1, |(_, column)| *column), "missing operand")
+        }
+    }
+
+    fn byte(&self, operand: &[Token]) -> Result<u8, AsmError> {
+        let value = self.evaluate(operand)?;
+        if !(-128..=255).contains(&value) {
+            return error(self.line, operand[0].column, format!("value {} does not fit in a byte", value));
+        }
+        Ok(value as u8)
+    }
+
+    fn word(&self, operand: &[Token]) -> Result<u16, AsmError> {
+        let value = self.evaluate(operand)?;
+        if !(-32768..=65535).contains(&value) {
+            return error(self.line, operand[0].column, format!("value {} does not fit in a word", value));
+        }
+        Ok(value as u16)
+    }
+
+    fn evaluate(&self, tokens: &[Token]) -> Result<i64, AsmError> {
+        if tokens.is_empty() {
+            return error(self.line, 1, "missing operand");
+        }
+        let mut parser = ExpressionParser { assembler: self, tokens, position: 0 };
+        let value = parser.expression()?;
+        if let Some(token) = tokens.get(parser.position) {
+            return error(self.line, token.column, "unexpected token in expression");
+        }
+        Ok(value)
+    }
+}
+
+// Expressions: + - * / MOD, unary - + HIGH LOW, parentheses, $ for the current address.
+
+struct ExpressionParser<'a>
+{
+    assembler: &'a Assembler,
+    tokens: &'a [Token],
+    position: usize
+}
+
+impl ExpressionParser<'_>
+{
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.position).map(|token| &token.kind)
+    }
+
+    fn column(&self) -> usize {
+        self.tokens.get(self.position).or(self.tokens.last()).map_or(1, |token| token.column)
+    }
+
+    fn expression(&mut self) -> Result<i64, AsmError> {
+        let mut value = self.term()?;
+        loop {
+            match self.peek() {
+                Some(TokenKind::Symbol('+')) => { self.position += 1; value = value.wrapping_add(self.term()?); }
+                Some(TokenKind::Symbol('-')) => { self.position += 1; value = value.wrapping_sub(self.term()?); }
+                _ => return Ok(value)
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<i64, AsmError> {
+        let mut value = self.unary()?;
+        loop {
+            let operator = match self.peek() {
+                Some(TokenKind::Symbol('*')) => '*',
+                Some(TokenKind::Symbol('/')) => '/',
+                Some(TokenKind::Identifier(word)) if word == "MOD" => '%',
+                _ => return Ok(value)
+            };
+            self.position += 1;
+            let column = self.column();
+            let rhs = self.unary()?;
+            value = match operator {
+                '*' => value.wrapping_mul(rhs),
+                _ if rhs == 0 => return error(self.assembler.line, column, "division by zero"),
+                '/' => value / rhs,
+                _ => value % rhs
+            };
+        }
+    }
+
+    fn unary(&mut self) -> Result<i64, AsmError> {
+        match self.peek() {
+            Some(TokenKind::Symbol('-')) => { self.position += 1; Ok(self.unary()?.wrapping_neg()) }
+            Some(TokenKind::Symbol('+')) => { self.position += 1; self.unary() }
+            Some(TokenKind::Identifier(word)) if word == "HIGH" => { self.position += 1; Ok((self.unary()? >> 8) & 0xFF) }
+            Some(TokenKind::Identifier(word)) if word == "LOW" => { self.position += 1; Ok(self.unary()? & 0xFF) }
+            _ => self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<i64, AsmError> {
+        let line = self.assembler.line;
+        let Some(token) = self.tokens.get(self.position) else {
+            return error(line, self.column(), "expression ends unexpectedly");
+        };
+        self.position += 1;
+
+        match &token.kind {
+            TokenKind::Number(value) => Ok(*value),
+            TokenKind::Dollar => Ok(self.assembler.address),
+            TokenKind::Text(text) if text.len() == 1 => Ok(text[0] as i64),
+            TokenKind::Text(text) if text.len() == 2 => Ok(((text[0] as i64) << 8) | text[1] as i64),
+            TokenKind::Identifier(name) => match self.assembler.symbols.get(name) {
+                Some(value) => Ok(*value),
+                None => error(line, token.column, format!("undefined symbol '{}'", name))
+            },
+            TokenKind::Symbol('(') => {
+                let value = self.expression()?;
+                match self.peek() {
+                    Some(TokenKind::Symbol(')')) => { self.position += 1; Ok(value) }
+                    _ => error(line, self.column(), "expected ')'")
+                }
+            }
+            _ => error(line, token.column, "expected a value")
+        }
+    }
+}
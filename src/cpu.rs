@@ -1,11 +1,26 @@
+pub mod alu;
+mod block_cache;
+mod encoder;
+mod error;
+mod fault;
+mod history;
+mod i8085;
+// Upstream code, its casts, acronyms and parity check are left as written.
+#[allow(clippy::upper_case_acronyms, clippy::manual_is_multiple_of, clippy::unnecessary_cast)]
 mod instruction;
 mod interpreter;
+mod opcodes;
+mod step;
+mod storage;
+mod z80;
+mod z80_alu;
 
 use std::{any::Any, sync::{Arc, RwLock}};
 
 use crate::Bus8080;
 
-pub type Interpreter8080 = interpreter::Interpreter8080;
+pub type Interpreter8080<B = SharedBus> = interpreter::Interpreter8080<B>;
+pub type BlockCache8080<B = SharedBus> = block_cache::BlockCache8080<B>;
 pub type Registers = instruction::Registers;
 pub type Register8 = instruction::Register8;
 pub type Register16 = instruction::Register16;
@@ -14,15 +29,61 @@ pub type InstructionAction = instruction::InstructionAction;
 pub type InstructionType = instruction::InstructionTarget;
 pub type Condition = instruction::Condition;
 pub type RegisterFlags = instruction::RegisterFlags;
+pub type CpuError = error::CpuError;
+pub type DecodeError = error::DecodeError;
+pub type EncodeError = error::EncodeError;
+pub type FaultPolicy = fault::FaultPolicy;
+pub type FaultCallback = fault::FaultCallback;
+pub type StepInfo = step::StepInfo;
+pub type RunInfo = step::RunInfo;
+pub type RunExit = step::RunExit;
+pub type SharedBus = storage::SharedBus;
+pub type History = history::History;
+pub type JournalEntry = history::JournalEntry;
+pub type OpcodeInfo = opcodes::OpcodeInfo;
+pub type Access = opcodes::Access;
+pub type BusAccess = opcodes::BusAccess;
+pub type Model = opcodes::Model;
+pub type Pins8085 = i8085::Pins8085;
+pub type InterpreterZ80<B = SharedBus> = z80::InterpreterZ80<B>;
+pub type RegistersZ80 = z80::RegistersZ80;
+
+pub use storage::BusStorage;
+pub use opcodes::{OPCODES, OPCODES_8085, FLAG_CARRY, FLAG_PARITY, FLAG_HALF_CARRY, FLAG_ZERO, FLAG_SIGN, FLAGS_ALL};
+pub use i8085::{FLAG_OVERFLOW, FLAG_UNDERFLOW, LINE_RST55, LINE_RST65, LINE_RST75, LINE_TRAP};
+pub use z80::{FLAG_SUBTRACT, FLAG_X3, FLAG_X5, LINE_NMI};
 
 pub trait CPU8080: Any + Send + Sync
 {
-    fn get_executed_cycles(&mut self) -> u32;
+    fn get_executed_cycles(&mut self) -> u64;
+    fn set_executed_cycles(&mut self, cycles: u64);
     fn force_jump(&mut self, a: u16);
+    fn registers(&self) -> &Registers;
+    fn registers_mut(&mut self) -> &mut Registers;
     fn set_bus(&mut self, b: Arc<RwLock<Box<dyn Bus8080>>>);
     fn get_bus(&self) -> Arc<RwLock<Box<dyn Bus8080>>>;
     fn stop(&mut self);
     fn is_running(&mut self) -> bool;
-    fn step(&mut self);
-    fn run(&mut self);
+    fn step(&mut self) -> Result<StepInfo, CpuError>;
+    fn run(&mut self) -> Result<(), CpuError>;
+
+    // The instruction set to disassemble what the CPU runs with, only Interpreter8080 can be an 8085.
+    fn model(&self) -> Model {
+        Model::Intel8080
+    }
+
+    // The RESET pin, see Registers::reset. Memory, the bus and the cycle count are left alone.
+    fn reset(&mut self) {
+        self.registers_mut().reset()
+    }
+
+    // Runs until at least `cycles` cycles have passed or the CPU stops, a halted CPU idles through the budget.
+    fn run_for_cycles(&mut self, cycles: u64) -> Result<RunInfo, CpuError> {
+        step::run_stepping(self, Some(cycles), &mut |_| false)
+    }
+
+    // Runs until the predicate holds after an instruction, the CPU stops or halts with nothing to wake it.
+    fn run_until(&mut self, predicate: &mut dyn FnMut(&Registers) -> bool) -> Result<RunInfo, CpuError> {
+        step::run_stepping(self, None, predicate)
+    }
 }
\ No newline at end of file
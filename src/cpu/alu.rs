@@ -0,0 +1,115 @@
+// The 8080 arithmetic and logic operations as pure functions.
+// Each takes the current F and returns the result with the new F, flags an operation does not
+// affect (and the unused bits) are passed through unchanged.
+
+use crate::cpu::{FLAG_CARRY, FLAG_HALF_CARRY, FLAG_PARITY, FLAG_SIGN, FLAG_ZERO};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AluOutput
+{
+    pub result: u8,
+    pub flags: u8
+}
+
+fn set(flags: u8, flag: u8, value: bool) -> u8 {
+    if value { flags | flag } else { flags & !flag }
+}
+
+// Zero, sign and parity (set when the number of 1 bits is even) from the result.
+fn zsp(flags: u8, result: u8) -> u8 {
+    let flags = set(flags, FLAG_ZERO, result == 0);
+    let flags = set(flags, FLAG_SIGN, result & 0x80 != 0);
+    set(flags, FLAG_PARITY, result.count_ones().is_multiple_of(2))
+}
+
+// ADD / ADC / ADI / ACI, AC is the carry out of bit 3.
+pub fn add(a: u8, value: u8, carry: bool, flags: u8) -> AluOutput {
+    let sum = a as u16 + value as u16 + carry as u16;
+    let carries = sum ^ a as u16 ^ value as u16;
+    let flags = set(flags, FLAG_CARRY, carries & 0x100 != 0);
+    let flags = set(flags, FLAG_HALF_CARRY, carries & 0x10 != 0);
+    AluOutput { result: sum as u8, flags: zsp(flags, sum as u8) }
+}
+
+// SUB / SBB / SUI / SBI, done as A + !value + !borrow like the hardware.
+// CY is the inverted carry (set on borrow), AC is left as the carry out of bit 3 of that addition.
+pub fn sub(a: u8, value: u8, borrow: bool, flags: u8) -> AluOutput {
+    let output = add(a, !value, !borrow, flags);
+    AluOutput { result: output.result, flags: output.flags ^ FLAG_CARRY }
+}
+
+// CMP / CPI, a subtraction that only keeps the flags. The result is A, unchanged.
+pub fn compare(a: u8, value: u8, flags: u8) -> AluOutput {
+    AluOutput { result: a, flags: sub(a, value, false, flags).flags }
+}
+
+// ANA / ANI, the 8080 sets AC from bit 3 of the OR of the operands.
+pub fn and(a: u8, value: u8, flags: u8) -> AluOutput {
+    let result = a & value;
+    let flags = set(flags, FLAG_CARRY, false);
+    let flags = set(flags, FLAG_HALF_CARRY, (a | value) & 0x08 != 0);
+    AluOutput { result, flags: zsp(flags, result) }
+}
+
+// ORA / ORI, clears CY and AC.
+pub fn or(a: u8, value: u8, flags: u8) -> AluOutput {
+    let result = a | value;
+    let flags = set(flags, FLAG_CARRY | FLAG_HALF_CARRY, false);
+    AluOutput { result, flags: zsp(flags, result) }
+}
+
+// XRA / XRI, clears CY and AC.
+pub fn xor(a: u8, value: u8, flags: u8) -> AluOutput {
+    let result = a ^ value;
+    let flags = set(flags, FLAG_CARRY | FLAG_HALF_CARRY, false);
+    AluOutput { result, flags: zsp(flags, result) }
+}
+
+// INR, CY is not affected.
+pub fn increment(value: u8, flags: u8) -> AluOutput {
+    let result = value.wrapping_add(1);
+    let flags = set(flags, FLAG_HALF_CARRY, result & 0x0F == 0x00);
+    AluOutput { result, flags: zsp(flags, result) }
+}
+
+// DCR, CY is not affected. AC is the carry out of bit 3 of value + 0xFF, so clear only when the low nibble borrows.
+pub fn decrement(value: u8, flags: u8) -> AluOutput {
+    let result = value.wrapping_sub(1);
+    let flags = set(flags, FLAG_HALF_CARRY, result & 0x0F != 0x0F);
+    AluOutput { result, flags: zsp(flags, result) }
+}
+
+// DAA: adds 06H if the low nibble is above 9 or AC is set, then 60H if the high nibble
+// (after the first correction) is above 9 or CY is set. CY is only ever set, never cleared.
+pub fn daa(a: u8, flags: u8) -> AluOutput {
+    let (low, high) = (a & 0x0F, a >> 4);
+    let mut correction = 0x00;
+    let mut carry = flags & FLAG_CARRY != 0;
+    if flags & FLAG_HALF_CARRY != 0 || low > 9 {
+        correction |= 0x06;
+    }
+    if carry || high > 9 || (high >= 9 && low > 9) {
+        correction |= 0x60;
+        carry = true;
+    }
+
+    let output = add(a, correction, false, flags);
+    AluOutput { result: output.result, flags: set(output.flags, FLAG_CARRY, carry) }
+}
+
+// RLC / RRC rotate through bit 0 / 7, RAL / RAR through CY. Only CY is affected.
+pub fn rotate(value: u8, right: bool, through_carry: bool, flags: u8) -> AluOutput {
+    let carry_in = if through_carry { flags & FLAG_CARRY != 0 } else if right { value & 0x01 != 0 } else { value & 0x80 != 0 };
+    let (result, carry_out) = if right {
+        ((value >> 1) | ((carry_in as u8) << 7), value & 0x01 != 0)
+    } else {
+        ((value << 1) | carry_in as u8, value & 0x80 != 0)
+    };
+    AluOutput { result, flags: set(flags, FLAG_CARRY, carry_out) }
+}
+
+// DAD, only CY is affected.
+pub fn add16(a: u16, value: u16, flags: u8) -> (u16, u8) {
+    let (result, carry) = a.overflowing_add(value);
+    (result, set(flags, FLAG_CARRY, carry))
+}
@@ -0,0 +1,340 @@
+use std::sync::{Arc, RwLock};
+
+use crate::{Bus8080, ErrorBus};
+use crate::cpu::{BusStorage, CPU8080, CpuError, Instruction8080, InstructionAction, Model, Registers, RunExit, RunInfo, SharedBus, StepInfo};
+use crate::cpu::interpreter::{execute_decoded, execute_instruction, fetch};
+use crate::cpu::step::{budget_exit, halt_fast_forward};
+
+// Longest run of code a block covers, also bounds the search for blocks hit by a write.
+const MAX_BLOCK_BYTES: usize = 64;
+
+struct Block
+{
+    instructions: Vec<Instruction8080>,
+    // Bytes of code covered, starting at the block address.
+    length: usize
+}
+
+struct Cache
+{
+    // Indexed by the address the block starts at.
+    blocks: Vec<Option<Block>>,
+    // How many blocks cover each byte of memory.
+    coverage: Vec<u8>
+}
+
+impl Cache
+{
+    fn new() -> Self {
+        Self {
+            blocks: (0..0x10000).map(|_| None).collect(),
+            coverage: vec![0; 0x10000]
+        }
+    }
+
+    // Straight-line code from start up to the first instruction that can change PC.
+    fn compile<T: Bus8080 + ?Sized>(bus: &T, table: &[Instruction8080; 256], start: u16) -> Block {
+        let mut instructions = Vec::new();
+        let mut length = 0;
+        loop {
+            let address = start as usize + length;
+            let instruction = fetch(bus, table, address as u16);
+            let size = instruction.length as usize;
+            // Code that wraps around the end of memory is left to the plain interpreter path.
+            if address + size > 0x10000 || length + size > MAX_BLOCK_BYTES {
+                break;
+            }
+            instructions.push(instruction);
+            length += size;
+
+            let branches = matches!(instruction.action,
+                InstructionAction::Jump { .. } | InstructionAction::Call { .. } | InstructionAction::Return { .. } |
+                InstructionAction::Restart { .. } | InstructionAction::Halt);
+            if branches {
+                break;
+            }
+        }
+        Block { instructions, length }
+    }
+
+    fn insert(&mut self, start: u16, block: Block) {
+        let start = start as usize;
+        for count in &mut self.coverage[start..start + block.length] {
+            *count += 1;
+        }
+        self.blocks[start] = Some(block);
+    }
+
+    fn remove(&mut self, start: usize) {
+        if let Some(block) = self.blocks[start].take() {
+            for count in &mut self.coverage[start..start + block.length] {
+                *count -= 1;
+            }
+        }
+    }
+
+    // Drops every block covering the written bytes, true if there were any.
+    fn invalidate(&mut self, address: u16, size: u16) -> bool {
+        let mut hit = false;
+        for offset in 0..size {
+            let address = address.wrapping_add(offset) as usize;
+            if self.coverage[address] == 0 {
+                continue;
+            }
+            for start in address.saturating_sub(MAX_BLOCK_BYTES - 1)..=address {
+                if self.blocks[start].as_ref().is_some_and(|block| start + block.length > address) {
+                    self.remove(start);
+                    hit = true;
+                }
+            }
+        }
+        hit
+    }
+}
+
+// A second CPU8080 that decodes straight-line runs of code once into blocks and runs them from a cache.
+// Writes the CPU makes into cached code drop the blocks covering it, so self-modifying programs work,
+// memory changed behind its back (by a device or the host) needs flush_cache().
+// Faults always stop it, there is no fault policy or history like Interpreter8080 has.
+pub struct BlockCache8080<B: BusStorage = SharedBus>
+{
+    cycles: u64,
+    registers: Registers,
+    cache: Cache,
+    decode_table: &'static [Instruction8080; 256],
+    bus: B
+}
+
+impl BlockCache8080
+{
+    pub fn new() -> Self {
+        Self::with_bus(Arc::new(RwLock::new(Box::new(ErrorBus::new()))))
+    }
+}
+
+impl<B: BusStorage> BlockCache8080<B>
+{
+    pub fn with_bus(bus: B) -> Self {
+        Self {
+            cycles: 0x00,
+            registers: Registers::new(),
+            cache: Cache::new(),
+            decode_table: Instruction8080::decode_table(),
+            bus
+        }
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    // Anything written through this is invisible to the cache, flush it afterwards.
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+
+    pub fn flush_cache(&mut self) {
+        self.cache = Cache::new();
+    }
+
+    pub fn get_executed_cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn set_executed_cycles(&mut self, cycles: u64) {
+        self.cycles = cycles;
+    }
+
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut Registers {
+        &mut self.registers
+    }
+
+    pub fn force_jump(&mut self, a: u16) {
+        self.registers.pc = a;
+    }
+
+    pub fn stop(&mut self) {
+        self.registers.running = false;
+    }
+
+    pub fn reset(&mut self) {
+        self.registers.reset();
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.registers.running
+    }
+
+    // Every instruction takes at least a cycle, so a budget of one is a single step.
+    pub fn step(&mut self) -> Result<StepInfo, CpuError> {
+        self.execute(Some(1), |_| false).map(|(info, _)| info)
+    }
+
+    pub fn run(&mut self) -> Result<(), CpuError> {
+        while self.registers.running {
+            self.execute(None, |_| false)?;
+        }
+        Ok(())
+    }
+
+    // Same contract as Interpreter8080::run_for_cycles().
+    pub fn run_for_cycles(&mut self, cycles: u64) -> Result<RunInfo, CpuError> {
+        let start = self.cycles;
+        let (_, exit) = self.execute(Some(cycles), |_| false)?;
+        Ok(RunInfo { cycles: self.cycles.wrapping_sub(start), exit })
+    }
+
+    // Same contract as Interpreter8080::run_until().
+    pub fn run_until(&mut self, predicate: impl FnMut(&Registers) -> bool) -> Result<RunInfo, CpuError> {
+        let start = self.cycles;
+        let (_, exit) = self.execute(None, predicate)?;
+        Ok(RunInfo { cycles: self.cycles.wrapping_sub(start), exit })
+    }
+
+    // Runs until `cycles` cycles have passed, `done` holds after an instruction, the CPU stops
+    // or halts with nothing to wake it (idling through the budget if there is one).
+    // Returns what the last instruction did and why it returned.
+    fn execute(&mut self, cycles: Option<u64>, done: impl FnMut(&Registers) -> bool) -> Result<(StepInfo, RunExit), CpuError> {
+        let result = self.execute_locked(cycles, done);
+        if result.is_err() {
+            self.registers.running = false;
+        }
+        result
+    }
+
+    fn execute_locked(&mut self, cycles: Option<u64>, mut done: impl FnMut(&Registers) -> bool) -> Result<(StepInfo, RunExit), CpuError> {
+        let start = self.cycles;
+        let pc = self.registers.pc;
+        let mut bus = self.bus.lock().ok_or(CpuError::BusFault { pc })?;
+        let mut last = StepInfo { pc, opcode: 0x76, cycles: 0 };
+        let budget = cycles.unwrap_or(u64::MAX);
+
+        let exit = 'run: loop {
+            if !self.registers.running {
+                break RunExit::Stopped;
+            }
+            let spent = self.cycles.wrapping_sub(start);
+            if spent >= budget {
+                break budget_exit(&self.registers);
+            }
+            let pc = self.registers.pc;
+            let pending = self.registers.accepts_interrupt() && bus.has_interrupt();
+            if self.registers.halting && !pending {
+                if cycles.is_some() {
+                    let idle = halt_fast_forward(budget - spent);
+                    self.cycles = self.cycles.wrapping_add(idle);
+                    last = StepInfo { pc, opcode: 0x76, cycles: u32::try_from(idle).unwrap_or(u32::MAX) };
+                }
+                break RunExit::Halted;
+            }
+
+            if self.cache.blocks[pc as usize].is_none() && !pending {
+                let block = Cache::compile(&*bus, self.decode_table, pc);
+                self.cache.insert(pc, block);
+            }
+            let empty = self.cache.blocks[pc as usize].as_ref().is_none_or(|block| block.instructions.is_empty());
+
+            // Interrupts and code the cache can not hold go through the interpreter, accepting an interrupt pushes PC.
+            if pending || empty {
+                let stack = self.registers.sp.wrapping_sub(2);
+                last = execute_instruction(&mut self.registers, &mut *bus, self.decode_table, Model::Intel8080)?;
+                self.cycles = self.cycles.wrapping_add(last.cycles as u64);
+                if pending {
+                    self.cache.invalidate(stack, 2);
+                }
+                if done(&self.registers) {
+                    break RunExit::Condition;
+                }
+                continue;
+            }
+
+            let mut address = pc;
+            for index in 0.. {
+                let Some(instruction) = self.cache.blocks[pc as usize].as_ref().and_then(|block| block.instructions.get(index)).copied() else {
+                    break;
+                };
+                let write = instruction.memory_write(&self.registers);
+                self.registers.pc = address.wrapping_add(instruction.length as u16);
+                last = execute_decoded(&mut self.registers, &mut *bus, instruction, address, Model::Intel8080)?;
+                self.cycles = self.cycles.wrapping_add(last.cycles as u64);
+
+                // Anything that may have changed the code or needs the per instruction checks ends the block early.
+                let modified = write.is_some_and(|(address, size)| self.cache.invalidate(address, size));
+                if done(&self.registers) {
+                    break 'run RunExit::Condition;
+                }
+                if modified || !self.registers.running || self.cycles.wrapping_sub(start) >= budget
+                    || (self.registers.accepts_interrupt() && bus.has_interrupt()) {
+                    break;
+                }
+                address = self.registers.pc;
+            }
+        };
+        Ok((last, exit))
+    }
+}
+
+// Inherent methods take precedence, so these forward to the ones above.
+impl CPU8080 for BlockCache8080
+{
+    fn get_executed_cycles(&mut self) -> u64 {
+        Self::get_executed_cycles(self)
+    }
+
+    fn set_executed_cycles(&mut self, cycles: u64) {
+        Self::set_executed_cycles(self, cycles)
+    }
+
+    fn registers(&self) -> &Registers {
+        Self::registers(self)
+    }
+
+    fn registers_mut(&mut self) -> &mut Registers {
+        Self::registers_mut(self)
+    }
+
+    fn force_jump(&mut self, a: u16) {
+        Self::force_jump(self, a)
+    }
+
+    fn reset(&mut self) {
+        Self::reset(self)
+    }
+
+    fn set_bus(&mut self, b: Arc<RwLock<Box<dyn Bus8080>>>) {
+        self.bus = b;
+        self.flush_cache();
+    }
+
+    fn get_bus(&self) -> Arc<RwLock<Box<dyn Bus8080>>> {
+        Arc::clone(&self.bus)
+    }
+
+    fn stop(&mut self) {
+        Self::stop(self)
+    }
+
+    fn is_running(&mut self) -> bool {
+        Self::is_running(self)
+    }
+
+    fn step(&mut self) -> Result<StepInfo, CpuError> {
+        Self::step(self)
+    }
+
+    fn run(&mut self) -> Result<(), CpuError> {
+        Self::run(self)
+    }
+
+    fn run_for_cycles(&mut self, cycles: u64) -> Result<RunInfo, CpuError> {
+        Self::run_for_cycles(self, cycles)
+    }
+
+    fn run_until(&mut self, predicate: &mut dyn FnMut(&Registers) -> bool) -> Result<RunInfo, CpuError> {
+        Self::run_until(self, predicate)
+    }
+}
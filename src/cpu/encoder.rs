@@ -0,0 +1,132 @@
+use crate::cpu::{Condition, EncodeError, Instruction8080, InstructionAction, Register16, Register8};
+use crate::cpu::InstructionType as InstructionTarget;
+
+// Field encodings, these are deliberately kept separate from the decoder tables so each one checks the other.
+fn register8_code(register: &Register8) -> Option<u8> {
+    match register {
+        Register8::B => Some(0), Register8::C => Some(1), Register8::D => Some(2), Register8::E => Some(3),
+        Register8::H => Some(4), Register8::L => Some(5), Register8::M => Some(6), Register8::A => Some(7),
+        Register8::F => None
+    }
+}
+
+fn register16_sp_code(register: &Register16) -> Option<u8> {
+    match register {
+        Register16::BC => Some(0), Register16::DE => Some(1), Register16::HL => Some(2), Register16::SP => Some(3),
+        Register16::PSW => None
+    }
+}
+
+fn register16_psw_code(register: &Register16) -> Option<u8> {
+    match register {
+        Register16::BC => Some(0), Register16::DE => Some(1), Register16::HL => Some(2), Register16::PSW => Some(3),
+        Register16::SP => None
+    }
+}
+
+fn condition_code(condition: &Condition) -> Option<u8> {
+    match condition {
+        Condition::NotZero => Some(0), Condition::Zero => Some(1),
+        Condition::NotCarry => Some(2), Condition::Carry => Some(3),
+        Condition::PairtyOdd => Some(4), Condition::ParityEven => Some(5),
+        Condition::Plus => Some(6), Condition::Minus => Some(7),
+        Condition::None | Condition::NotUnderflow | Condition::Underflow => None
+    }
+}
+
+impl Instruction8080
+{
+    pub fn to_bytes(self) -> Result<Vec<u8>, EncodeError> {
+        Self::encode(&self.action, &self.target)
+    }
+
+    // Inverse of decode(), always picks the documented opcode when several decode to the same instruction.
+    pub fn encode(action: &InstructionAction, target: &InstructionTarget) -> Result<Vec<u8>, EncodeError> {
+        let unencodable = EncodeError::Unencodable { action: *action, target: *target };
+        let r8 = |register: &Register8| register8_code(register).ok_or(unencodable);
+        let rp = |register: &Register16| register16_sp_code(register).ok_or(unencodable);
+        let rp_psw = |register: &Register16| register16_psw_code(register).ok_or(unencodable);
+
+        // Register / immediate forms of the accumulator operations.
+        let alu = |register_base: u8, immediate_opcode: u8| match target {
+            InstructionTarget::Register8 { register } => Ok(vec![register_base | r8(register)?]),
+            InstructionTarget::Immediate8 { value } => Ok(vec![immediate_opcode, *value]),
+            _ => Err(unencodable)
+        };
+        let with16 = |opcode: u8, value: u16| {
+            let [low, high] = value.to_le_bytes();
+            vec![opcode, low, high]
+        };
+
+        match (action, target) {
+            (InstructionAction::Nothing, InstructionTarget::None) => Ok(vec![0x00]),
+            (InstructionAction::Halt, InstructionTarget::None) => Ok(vec![0x76]),
+
+            (InstructionAction::Jump { condition: Condition::None }, InstructionTarget::Immediate16 { value }) => Ok(with16(0xC3, *value)),
+            (InstructionAction::Jump { condition: Condition::None }, InstructionTarget::Register16 { register: Register16::HL }) => Ok(vec![0xE9]),
+            (InstructionAction::Jump { condition }, InstructionTarget::Immediate16 { value }) => {
+                Ok(with16(0xC2 | condition_code(condition).ok_or(unencodable)? << 3, *value))
+            }
+            (InstructionAction::Call { condition: Condition::None }, InstructionTarget::Immediate16 { value }) => Ok(with16(0xCD, *value)),
+            (InstructionAction::Call { condition }, InstructionTarget::Immediate16 { value }) => {
+                Ok(with16(0xC4 | condition_code(condition).ok_or(unencodable)? << 3, *value))
+            }
+            (InstructionAction::Return { condition: Condition::None }, InstructionTarget::None) => Ok(vec![0xC9]),
+            (InstructionAction::Return { condition }, InstructionTarget::None) => {
+                Ok(vec![0xC0 | condition_code(condition).ok_or(unencodable)? << 3])
+            }
+            (InstructionAction::Restart { vector }, InstructionTarget::None) => {
+                if *vector > 7 { return Err(EncodeError::InvalidRestart { vector: *vector }) }
+                Ok(vec![0xC7 | vector << 3])
+            }
+
+            (InstructionAction::Increment16 { register }, InstructionTarget::None) => Ok(vec![0x03 | rp(register)? << 4]),
+            (InstructionAction::Decrement16 { register }, InstructionTarget::None) => Ok(vec![0x0B | rp(register)? << 4]),
+            (InstructionAction::Add16 { register: Register16::HL }, InstructionTarget::Register16 { register }) => Ok(vec![0x09 | rp(register)? << 4]),
+            (InstructionAction::Load16 { register: Register16::SP }, InstructionTarget::Register16 { register: Register16::HL }) => Ok(vec![0xF9]),
+            (InstructionAction::Load16 { register }, InstructionTarget::Immediate16 { value }) => Ok(with16(0x01 | rp(register)? << 4, *value)),
+            (InstructionAction::Push16 { register }, InstructionTarget::None) => Ok(vec![0xC5 | rp_psw(register)? << 4]),
+            (InstructionAction::Pop16 { register }, InstructionTarget::None) => Ok(vec![0xC1 | rp_psw(register)? << 4]),
+
+            (InstructionAction::MovReg { register: Register8::M }, InstructionTarget::Register8 { register: Register8::M }) => Err(unencodable),
+            (InstructionAction::MovReg { register }, InstructionTarget::Register8 { register: source }) => Ok(vec![0x40 | r8(register)? << 3 | r8(source)?]),
+            (InstructionAction::MovReg { register }, InstructionTarget::Immediate8 { value }) => Ok(vec![0x06 | r8(register)? << 3, *value]),
+            (InstructionAction::AddReg { register: Register8::A, carry: false }, _) => alu(0x80, 0xC6),
+            (InstructionAction::AddReg { register: Register8::A, carry: true }, _) => alu(0x88, 0xCE),
+            (InstructionAction::SubReg { register: Register8::A, borrow: false }, _) => alu(0x90, 0xD6),
+            (InstructionAction::SubReg { register: Register8::A, borrow: true }, _) => alu(0x98, 0xDE),
+            (InstructionAction::AndReg { register: Register8::A }, _) => alu(0xA0, 0xE6),
+            (InstructionAction::XorReg { register: Register8::A }, _) => alu(0xA8, 0xEE),
+            (InstructionAction::OrReg { register: Register8::A }, _) => alu(0xB0, 0xF6),
+            (InstructionAction::CompareReg { register: Register8::A }, _) => alu(0xB8, 0xFE),
+            (InstructionAction::IncrementReg { register }, InstructionTarget::None) => Ok(vec![0x04 | r8(register)? << 3]),
+            (InstructionAction::DecrementReg { register }, InstructionTarget::None) => Ok(vec![0x05 | r8(register)? << 3]),
+            (InstructionAction::DAAReg { register: Register8::A }, InstructionTarget::None) => Ok(vec![0x27]),
+            (InstructionAction::ComplementReg { register: Register8::A }, InstructionTarget::None) => Ok(vec![0x2F]),
+            (InstructionAction::RotateReg { register: Register8::A, right, arithmetic }, InstructionTarget::None) => {
+                Ok(vec![0x07 | (*right as u8) << 3 | (*arithmetic as u8) << 4])
+            }
+
+            (InstructionAction::StoreRegToMemory { register: Register8::A }, InstructionTarget::Register16 { register: register @ (Register16::BC | Register16::DE) }) => {
+                Ok(vec![0x02 | rp(register)? << 4])
+            }
+            (InstructionAction::StoreRegToMemory { register: Register8::A }, InstructionTarget::Immediate16 { value }) => Ok(with16(0x32, *value)),
+            (InstructionAction::LoadRegFromMemory { register: Register8::A }, InstructionTarget::Register16 { register: register @ (Register16::BC | Register16::DE) }) => {
+                Ok(vec![0x0A | rp(register)? << 4])
+            }
+            (InstructionAction::LoadRegFromMemory { register: Register8::A }, InstructionTarget::Immediate16 { value }) => Ok(with16(0x3A, *value)),
+            (InstructionAction::StoreReg16ToMemory { register: Register16::HL }, InstructionTarget::Immediate16 { value }) => Ok(with16(0x22, *value)),
+            (InstructionAction::LoadReg16FromMemory { register: Register16::HL }, InstructionTarget::Immediate16 { value }) => Ok(with16(0x2A, *value)),
+
+            (InstructionAction::SetInterrupts { enabled }, InstructionTarget::None) => Ok(vec![if *enabled { 0xFB } else { 0xF3 }]),
+            (InstructionAction::SetCarry { value: true }, InstructionTarget::None) => Ok(vec![0x37]),
+            (InstructionAction::ComplementCarry, InstructionTarget::None) => Ok(vec![0x3F]),
+            (InstructionAction::ExchangeToStack, InstructionTarget::None) => Ok(vec![0xE3]),
+            (InstructionAction::Exchange, InstructionTarget::None) => Ok(vec![0xEB]),
+            (InstructionAction::In8, InstructionTarget::Immediate8 { value }) => Ok(vec![0xDB, *value]),
+            (InstructionAction::Out8, InstructionTarget::Immediate8 { value }) => Ok(vec![0xD3, *value]),
+
+            _ => Err(unencodable)
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::fmt;
+
+use crate::cpu::{InstructionAction, InstructionType};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CpuError
+{
+    // The decoder did not recognize the fetched opcode.
+    UnknownOpcode { pc: u16, opcode: u8 },
+    // The decoded instruction carries an operand its action can not use.
+    InvalidOperand { pc: u16, opcode: u8 },
+    // The bus could not be accessed (e.g. its lock was poisoned by a panicking device).
+    BusFault { pc: u16 },
+}
+
+impl fmt::Display for CpuError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownOpcode { pc, opcode } => write!(f, "unknown opcode 0x{:02X} at PC 0x{:04X}", opcode, pc),
+            Self::InvalidOperand { pc, opcode } => write!(f, "invalid operand for opcode 0x{:02X} at PC 0x{:04X}", opcode, pc),
+            Self::BusFault { pc } => write!(f, "bus fault at PC 0x{:04X}", pc),
+        }
+    }
+}
+
+impl std::error::Error for CpuError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecodeError
+{
+    // There was no opcode to decode.
+    Empty,
+    // The opcode needs more operand bytes than were given.
+    Truncated { opcode: u8, needed: usize, available: usize },
+}
+
+impl fmt::Display for DecodeError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "no bytes to decode"),
+            Self::Truncated { opcode, needed, available } => {
+                write!(f, "opcode 0x{:02X} needs {} bytes but only {} are available", opcode, needed, available)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EncodeError
+{
+    // No 8080 opcode performs this action on this target.
+    Unencodable { action: InstructionAction, target: InstructionType },
+    // RST only has vectors 0 to 7.
+    InvalidRestart { vector: u8 },
+}
+
+impl fmt::Display for EncodeError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unencodable { action, target } => write!(f, "no opcode encodes {:?} with {:?}", action, target),
+            Self::InvalidRestart { vector } => write!(f, "RST vector {} is out of range", vector),
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
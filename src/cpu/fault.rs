@@ -0,0 +1,18 @@
+use crate::cpu::{CpuError, Registers};
+
+pub type FaultCallback = Box<dyn FnMut(&CpuError, &mut Registers) -> bool + Send + Sync>;
+
+#[derive(Default)]
+pub enum FaultPolicy
+{
+    // Stop the CPU and return the error from step().
+    #[default]
+    Stop,
+    // Skip the faulting instruction as if it was a NOP, a poisoned bus is recovered.
+    Nop,
+    // Execute RST vector in place of the faulting instruction. Only the low 3 bits are used, as in the
+    // RST opcode, so Trap { vector: 9 } is RST 1.
+    Trap { vector: u8 },
+    // Let the host decide, the callback returns true to keep the CPU running.
+    Callback(FaultCallback)
+}
@@ -0,0 +1,115 @@
+use std::collections::VecDeque;
+
+use crate::{cpu::Registers, state::SaveState};
+
+// What an instruction changed: the registers and cycle counter before it ran and the old value of every byte it wrote.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournalEntry
+{
+    pub registers: Registers,
+    pub cycles: u64,
+    pub writes: Vec<(u16, u8)>
+}
+
+// A bounded record of the last instructions, used to step backwards.
+// Undoing walks the journal, the periodic snapshots (full save states) cut long rewinds short
+// and also bring back device state the journal can not see.
+pub struct History
+{
+    capacity: usize,
+    snapshot_interval: u64,
+    journal: VecDeque<JournalEntry>,
+    // Snapshot of the machine before the instruction with that index ran.
+    snapshots: VecDeque<(u64, SaveState)>,
+    // Index of the next instruction to be recorded.
+    executed: u64
+}
+
+impl History
+{
+    pub(crate) fn new(capacity: usize, snapshot_interval: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            snapshot_interval: snapshot_interval as u64,
+            journal: VecDeque::new(),
+            snapshots: VecDeque::new(),
+            executed: 0
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // Number of instructions that can be undone.
+    pub fn len(&self) -> usize {
+        self.journal.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.journal.is_empty()
+    }
+
+    // Oldest first.
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &JournalEntry> + ExactSizeIterator {
+        self.journal.iter()
+    }
+
+    // The registers before the instruction `back` steps ago ran, 0 being the last one executed.
+    pub fn registers_before(&self, back: usize) -> Option<&Registers> {
+        self.journal.iter().rev().nth(back).map(|entry| &entry.registers)
+    }
+
+    fn oldest(&self) -> u64 {
+        self.executed - self.journal.len() as u64
+    }
+
+    pub(crate) fn wants_snapshot(&self) -> bool {
+        self.snapshot_interval != 0 && self.executed.is_multiple_of(self.snapshot_interval)
+    }
+
+    pub(crate) fn push_snapshot(&mut self, state: SaveState) {
+        // Coming back to an index after a rewind, the snapshot taken there the first time is still good.
+        if self.snapshots.back().is_some_and(|(index, _)| *index == self.executed) {
+            return;
+        }
+        self.snapshots.push_back((self.executed, state));
+    }
+
+    pub(crate) fn push(&mut self, entry: JournalEntry) {
+        if self.journal.len() == self.capacity {
+            self.journal.pop_front();
+        }
+        self.journal.push_back(entry);
+        self.executed += 1;
+
+        let oldest = self.oldest();
+        while self.snapshots.front().is_some_and(|(index, _)| *index < oldest) {
+            self.snapshots.pop_front();
+        }
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<JournalEntry> {
+        let entry = self.journal.pop_back()?;
+        self.executed -= 1;
+        while self.snapshots.back().is_some_and(|(index, _)| *index > self.executed) {
+            self.snapshots.pop_back();
+        }
+        Some(entry)
+    }
+
+    // Closest snapshot that is no more than `instructions` back, with how many instructions it skips.
+    pub(crate) fn snapshot_within(&self, instructions: usize) -> Option<(usize, &SaveState)> {
+        let target = self.executed - (instructions.min(self.journal.len()) as u64);
+        self.snapshots.iter()
+            .find(|(index, _)| *index >= target && *index < self.executed)
+            .map(|(index, state)| ((self.executed - index) as usize, state))
+    }
+
+    // Forgets the last `instructions` entries, after a snapshot already restored the state before them.
+    pub(crate) fn discard(&mut self, instructions: usize) {
+        for _ in 0..instructions {
+            self.pop();
+        }
+    }
+}
@@ -0,0 +1,146 @@
+// The parts of the 8085 the 8080 does not have: the RST 5.5 / 6.5 / 7.5 and TRAP inputs, RIM / SIM,
+// the serial pins and the V and K flags. The interpreter runs it with set_model(Model::Intel8085).
+
+use crate::Bus8080;
+use crate::cpu::Registers;
+
+// V, two's complement overflow, and K (also called X5 or UI), the sign the result would have without
+// the overflow. They sit in the bits the 8080 keeps at 1 and 0.
+pub const FLAG_OVERFLOW: u8 = 1 << 1;
+pub const FLAG_UNDERFLOW: u8 = 1 << 5;
+
+// Bits of Bus8080::interrupt_lines().
+pub const LINE_RST55: u8 = 1 << 0;
+pub const LINE_RST65: u8 = 1 << 1;
+pub const LINE_RST75: u8 = 1 << 2;
+pub const LINE_TRAP: u8 = 1 << 3;
+
+// SIM: mask set enable, reset the RST 7.5 latch, serial data enable and the SOD bit.
+const SIM_MASK_ENABLE: u8 = 1 << 3;
+const SIM_RESET_RST75: u8 = 1 << 4;
+const SIM_SERIAL_ENABLE: u8 = 1 << 6;
+const SIM_SERIAL_DATA: u8 = 1 << 7;
+const MASKS: u8 = 0x07;
+
+const TRAP_VECTOR: u16 = 0x0024;
+const RST75_VECTOR: u16 = 0x003C;
+const RST65_VECTOR: u16 = 0x0034;
+const RST55_VECTOR: u16 = 0x002C;
+
+// Accepting TRAP or an RST x.5 is a 12 cycle restart.
+pub(crate) const INTERRUPT_CYCLES: u32 = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pins8085
+{
+    // The SIM masks for RST 5.5, 6.5 and 7.5 in bits 0 to 2, all set by RESET.
+    pub masks: u8,
+    // RST 7.5 and TRAP requests, latched on a rising edge.
+    pub rst75: bool,
+    pub trap: bool,
+    // The SOD output as last set by SIM.
+    pub serial_out: bool,
+    // The interrupt lines when they were last sampled, for the edge detection.
+    pub lines: u8
+}
+
+impl Pins8085
+{
+    pub fn new() -> Self {
+        Self { masks: MASKS, rst75: false, trap: false, serial_out: false, lines: 0 }
+    }
+}
+
+// Latches the edge triggered inputs, before every instruction. TRAP needs the edge and the line still high.
+pub(crate) fn sample<T: Bus8080 + ?Sized>(registers: &mut Registers, bus: &T) {
+    let lines = bus.interrupt_lines();
+    let rising = lines & !registers.pins.lines;
+    let pins = &mut registers.pins;
+    pins.rst75 |= rising & LINE_RST75 != 0;
+    pins.trap = (pins.trap || rising & LINE_TRAP != 0) && lines & LINE_TRAP != 0;
+    pins.lines = lines;
+}
+
+// The restart address of the vectored interrupt that wins now, TRAP first and then RST 7.5, 6.5 and 5.5.
+// Only TRAP ignores EI and the masks.
+pub(crate) fn vectored(registers: &Registers) -> Option<u16> {
+    let pins = &registers.pins;
+    let unmasked = |line: u8| pins.masks & line == 0;
+    if pins.trap {
+        Some(TRAP_VECTOR)
+    } else if !registers.accepts_interrupt() {
+        None
+    } else if pins.rst75 && unmasked(LINE_RST75) {
+        Some(RST75_VECTOR)
+    } else if pins.lines & LINE_RST65 != 0 && unmasked(LINE_RST65) {
+        Some(RST65_VECTOR)
+    } else if pins.lines & LINE_RST55 != 0 && unmasked(LINE_RST55) {
+        Some(RST55_VECTOR)
+    } else {
+        None
+    }
+}
+
+// Whether anything would interrupt the next instruction, without latching anything.
+pub(crate) fn interrupt_pending<T: Bus8080 + ?Sized>(registers: &Registers, bus: &T) -> bool {
+    let mut sampled = *registers;
+    sample(&mut sampled, bus);
+    vectored(&sampled).is_some() || (registers.accepts_interrupt() && bus.has_interrupt())
+}
+
+// Takes a vectored interrupt: like RST it pushes PC, and like INTR it disables interrupts.
+pub(crate) fn accept<T: Bus8080 + ?Sized>(registers: &mut Registers, bus: &mut T, vector: u16) {
+    match vector {
+        TRAP_VECTOR => registers.pins.trap = false,
+        RST75_VECTOR => registers.pins.rst75 = false,
+        _ => { }
+    }
+    registers.interrupts = false;
+    registers.interrupt_delay = false;
+    registers.halting = false;
+    registers.sp = registers.sp.wrapping_sub(2);
+    bus.write_w(registers.sp, registers.pc);
+    registers.pc = vector;
+}
+
+// RIM: SID, pending RST 7.5 / 6.5 / 5.5, IE and the three masks from bit 7 down.
+pub(crate) fn read_interrupt_mask<T: Bus8080 + ?Sized>(registers: &Registers, bus: &T) -> u8 {
+    let pending = (registers.pins.rst75 as u8) << 2 | bus.interrupt_lines() & (LINE_RST65 | LINE_RST55);
+    (bus.serial_in() as u8) << 7 | pending << 4 | (registers.interrupts as u8) << 3 | registers.pins.masks
+}
+
+// SIM, each part only takes effect when its enable bit is set.
+pub(crate) fn set_interrupt_mask<T: Bus8080 + ?Sized>(registers: &mut Registers, bus: &mut T, value: u8) {
+    if value & SIM_MASK_ENABLE != 0 {
+        registers.pins.masks = value & MASKS;
+    }
+    if value & SIM_RESET_RST75 != 0 {
+        registers.pins.rst75 = false;
+    }
+    if value & SIM_SERIAL_ENABLE != 0 {
+        registers.pins.serial_out = value & SIM_SERIAL_DATA != 0;
+        bus.serial_out(registers.pins.serial_out);
+    }
+}
+
+fn set(flags: u8, flag: u8, value: bool) -> u8 {
+    if value { flags | flag } else { flags & !flag }
+}
+
+// V and K after an 8 bit addition or subtraction of `operand` to / from `a`.
+pub(crate) fn arithmetic_flags(flags: u8, a: u8, operand: u8, result: u8, subtract: bool) -> u8 {
+    let operand = if subtract { !operand } else { operand };
+    let overflow = !(a ^ operand) & (a ^ result) & 0x80 != 0;
+    let flags = set(flags, FLAG_OVERFLOW, overflow);
+    set(flags, FLAG_UNDERFLOW, overflow ^ (result & 0x80 != 0))
+}
+
+// Logical operations clear V and K.
+pub(crate) fn logic_flags(flags: u8) -> u8 {
+    flags & !(FLAG_OVERFLOW | FLAG_UNDERFLOW)
+}
+
+// INX / DCX set K when the register pair wraps around.
+pub(crate) fn counter_flags(flags: u8, result: u16, increment: bool) -> u8 {
+    set(flags, FLAG_UNDERFLOW, result == if increment { 0x0000 } else { 0xFFFF })
+}
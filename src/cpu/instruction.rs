@@ -1,4 +1,7 @@
+use std::sync::OnceLock;
+
 use crate::Bus8080;
+use crate::cpu::{DecodeError, Pins8085, RegistersZ80, FLAG_UNDERFLOW};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Condition
@@ -7,7 +10,9 @@ pub enum Condition
     NotZero, Zero,
     NotCarry, Carry,
     PairtyOdd, ParityEven,
-    Plus, Minus
+    Plus, Minus,
+    // The K flag of the 8085, see i8085.
+    NotUnderflow, Underflow
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -22,7 +27,7 @@ pub enum Register8
     A, B, C, D, E, F, H, L, M
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum InstructionAction
 {
     None,
@@ -30,6 +35,7 @@ pub enum InstructionAction
     Jump { condition: Condition },
     Call { condition: Condition },
     Return { condition: Condition },
+    Restart { vector: u8 },
     Increment16 { register: Register16 },
     Decrement16 { register: Register16 },
     Add16 { register: Register16 },
@@ -60,6 +66,19 @@ pub enum InstructionAction
     Halt,
     In8,
     Out8,
+    // 8085 only, see i8085.
+    ReadInterruptMask,
+    SetInterruptMask,
+    Sub16 { register: Register16 },
+    ShiftRight16 { register: Register16 },
+    RotateLeft16 { register: Register16 },
+    LoadOffset16 { register: Register16, base: Register16 },
+    RestartOnOverflow,
+    // Z80 only, see z80.
+    ExchangeAF,
+    ExchangeAlternates,
+    JumpRelative { condition: Condition },
+    DecrementJump,
 }
 
 #[derive(Debug)]
@@ -72,7 +91,7 @@ pub enum RegisterFlags
     Parity
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum InstructionTarget
 {
     None,
@@ -84,26 +103,26 @@ pub enum InstructionTarget
 
 impl InstructionTarget
 {
-    pub fn get_value_as_u16(&self, registers: &Registers) -> u16
+    pub fn get_value_as_u16(&self, registers: &Registers) -> Option<u16>
     {
         match self {
-            Self::Immediate16 { value } => *value,
-            Self::Register16 { register } => registers.get_16(register),
-            _ => panic!("[EROR]: Unimplemented data source in get_value_as_u16!")
+            Self::Immediate16 { value } => Some(*value),
+            Self::Register16 { register } => Some(registers.get_16(register)),
+            _ => None
         }
     }
 
-    pub fn get_value_as_u8(&self, bus: &mut Box<dyn Bus8080>, registers: &Registers) -> u8
+    pub fn get_value_as_u8<B: Bus8080 + ?Sized>(&self, bus: &mut B, registers: &Registers) -> Option<u8>
     {
         match self {
-            Self::Immediate8 { value } => *value,
-            Self::Register8 { register } => { registers.get_8(bus, register)  }
-            _ => panic!("[EROR]: Unimplemented data source in get_value_as_u8!")
+            Self::Immediate8 { value } => Some(*value),
+            Self::Register8 { register } => Some(registers.get_8(bus, register)),
+            _ => None
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Registers
 {
     pub pc: u16,
@@ -112,8 +131,14 @@ pub struct Registers
     pub a: u8, pub b: u8, pub c: u8,
     pub d: u8, pub e: u8, pub f: u8,
     pub interrupts: bool,
+    // Set by EI, interrupts are only accepted once the instruction after it has run.
+    pub interrupt_delay: bool,
     pub halting: bool,
-    pub running: bool
+    pub running: bool,
+    // Interrupt masks, latches and the SOD output of the 8085, the 8080 leaves them alone.
+    pub pins: Pins8085,
+    // The registers only the Z80 has.
+    pub z80: RegistersZ80
 }
 
 impl Registers
@@ -126,10 +151,31 @@ impl Registers
             a: 0x00, b: 0x00, c: 0x00,
             d: 0x00, e: 0x00, f: 0x02,
             halting: false,
-            interrupts: true, running: true
+            // The 8080 comes out of reset with interrupts disabled.
+            interrupts: false, interrupt_delay: false, running: true,
+            pins: Pins8085::new(),
+            z80: RegistersZ80::new()
         }
     }
 
+    // What the RESET pin does: PC goes to 0, interrupts are disabled and a halt is left, on the 8085 the
+    // RST 5.5 / 6.5 / 7.5 inputs are masked again and on the Z80 I, R and the interrupt mode are cleared.
+    // The other registers keep whatever they held.
+    pub fn reset(&mut self) {
+        self.pc = 0x0000;
+        self.interrupts = false;
+        self.interrupt_delay = false;
+        self.halting = false;
+        self.running = true;
+        self.pins = Pins8085 { lines: self.pins.lines, ..Pins8085::new() };
+        self.z80 = RegistersZ80 { i: 0x00, r: 0x00, iff2: false, interrupt_mode: 0, nmi: false, ..self.z80 };
+    }
+
+    // Whether an interrupt request would be accepted before the next instruction.
+    pub fn accepts_interrupt(&self) -> bool {
+        self.interrupts && !self.interrupt_delay
+    }
+
     pub fn set_zsp(&mut self, value: u8) {
         self.set_flag(RegisterFlags::Zero, value == 0);
         self.set_flag(RegisterFlags::Sign, (value >> 7) != 0);
@@ -184,6 +230,8 @@ impl Registers
             Condition::NotZero => { !self.get_flag(RegisterFlags::Zero) }
             Condition::Plus => { !self.get_flag(RegisterFlags::Sign) }
             Condition::Minus => { self.get_flag(RegisterFlags::Sign) }
+            Condition::NotUnderflow => { self.f & FLAG_UNDERFLOW == 0 }
+            Condition::Underflow => { self.f & FLAG_UNDERFLOW != 0 }
             Condition::None => { true }
         }
     }
@@ -199,7 +247,7 @@ impl Registers
         }
     }
 
-    pub fn get_8(&self, bus: &mut Box<dyn Bus8080>, register: &Register8) -> u8 {
+    pub fn get_8<B: Bus8080 + ?Sized>(&self, bus: &mut B, register: &Register8) -> u8 {
         match register {
             Register8::A => { self.a }
             Register8::B => { self.b }
@@ -236,7 +284,7 @@ impl Registers
         }
     }
 
-    pub fn set_8(&mut self, register: &Register8, bus: &mut Box<dyn Bus8080>, value: u8) {
+    pub fn set_8<B: Bus8080 + ?Sized>(&mut self, register: &Register8, bus: &mut B, value: u8) {
         match register {
             Register8::A => { self.a = value; }
             Register8::B => { self.b = value; }
@@ -244,14 +292,14 @@ impl Registers
             Register8::D => { self.d = value; }
             Register8::E => { self.e = value; }
             Register8::F => { self.f = value; }
-            Register8::H => { self.h = value; self.f = self.f & 0xD7 | 0x2; }
+            Register8::H => { self.h = value; }
             Register8::L => { self.l = value; }
             Register8::M => { bus.write_b(self.get_16(&Register16::HL), value); }
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Instruction8080
 {
     pub length: u8,
@@ -270,7 +318,150 @@ impl Instruction8080
         }
     }
 
-    pub fn from_opcode(opcode: u8, pc: u16, bus: &Box<dyn Bus8080>) -> Self {
+    pub fn from_opcode<B: Bus8080 + ?Sized>(opcode: u8, pc: u16, bus: &B) -> Self {
+        Self::from_bytes(&[opcode, bus.read_b(pc.wrapping_add(1)), bus.read_b(pc.wrapping_add(2))])
+    }
+
+    // Every opcode decoded once with zeroed operands, the interpreter only fills in the immediates it fetches.
+    pub fn decode_table() -> &'static [Instruction8080; 256] {
+        static TABLE: OnceLock<[Instruction8080; 256]> = OnceLock::new();
+        TABLE.get_or_init(|| std::array::from_fn(|opcode| Self::from_bytes(&[opcode as u8, 0, 0])))
+    }
+
+    // The same for the 8085, where most of the 8080's undocumented opcodes are new instructions.
+    pub fn decode_table_8085() -> &'static [Instruction8080; 256] {
+        static TABLE: OnceLock<[Instruction8080; 256]> = OnceLock::new();
+        TABLE.get_or_init(|| std::array::from_fn(|opcode| {
+            Self::decode_8085(&[opcode as u8, 0, 0]).map(|(instruction, _)| instruction).unwrap_or_else(|_| Self::new(opcode as u8))
+        }))
+    }
+
+    // And for the Z80, the prefixes are left as unknown opcodes for InterpreterZ80 to handle.
+    pub fn decode_table_z80() -> &'static [Instruction8080; 256] {
+        static TABLE: OnceLock<[Instruction8080; 256]> = OnceLock::new();
+        TABLE.get_or_init(|| std::array::from_fn(|opcode| {
+            Self::decode_z80(&[opcode as u8, 0, 0]).map(|(instruction, _)| instruction).unwrap_or_else(|_| Self::new(opcode as u8))
+        }))
+    }
+
+    fn from_bytes(bytes: &[u8; 3]) -> Self {
+        Self::decode(bytes).map(|(instruction, _)| instruction).unwrap_or_else(|_| Self::new(bytes[0]))
+    }
+
+    // decode() for the 8085: RIM and SIM plus the undocumented DSUB, ARHL, RDEL, LDHI, LDSI, RSTV,
+    // SHLX, LHLX, JNK and JK in place of the 8080 aliases.
+    pub fn decode_8085(bytes: &[u8]) -> Result<(Self, usize), DecodeError> {
+        let Some(&opcode) = bytes.first() else { return Err(DecodeError::Empty) };
+        let operand8 = bytes.get(1).copied().unwrap_or(0);
+        let operand16 = u16::from_le_bytes([operand8, bytes.get(2).copied().unwrap_or(0)]);
+
+        let mut result = Instruction8080::new(opcode);
+        match opcode {
+            0x08 => {
+                result.action = InstructionAction::Sub16 { register: Register16::HL };
+                result.target = InstructionTarget::Register16 { register: Register16::BC };
+            }
+            0x10 => { result.action = InstructionAction::ShiftRight16 { register: Register16::HL }; }
+            0x18 => { result.action = InstructionAction::RotateLeft16 { register: Register16::DE }; }
+            0x20 => { result.action = InstructionAction::ReadInterruptMask; }
+            0x30 => { result.action = InstructionAction::SetInterruptMask; }
+
+            // LDHI / LDSI
+            0x28 | 0x38 => {
+                result.length += 1;
+                let base = if opcode == 0x28 { Register16::HL } else { Register16::SP };
+                result.action = InstructionAction::LoadOffset16 { register: Register16::DE, base };
+                result.target = InstructionTarget::Immediate8 { value: operand8 };
+            }
+
+            0xCB => { result.action = InstructionAction::RestartOnOverflow; }
+
+            // SHLX / LHLX
+            0xD9 => {
+                result.action = InstructionAction::StoreReg16ToMemory { register: Register16::HL };
+                result.target = InstructionTarget::Register16 { register: Register16::DE };
+            }
+            0xED => {
+                result.action = InstructionAction::LoadReg16FromMemory { register: Register16::HL };
+                result.target = InstructionTarget::Register16 { register: Register16::DE };
+            }
+
+            // JNK / JK
+            0xDD | 0xFD => {
+                result.length += 2;
+                let condition = if opcode == 0xDD { Condition::NotUnderflow } else { Condition::Underflow };
+                result.action = InstructionAction::Jump { condition };
+                result.target = InstructionTarget::Immediate16 { value: operand16 };
+            }
+
+            _ => return Self::decode(bytes)
+        }
+
+        let length = result.length as usize;
+        if bytes.len() < length {
+            return Err(DecodeError::Truncated { opcode, needed: length, available: bytes.len() });
+        }
+        Ok((result, length))
+    }
+
+    // decode() for the unprefixed Z80 opcodes: EX AF,AF', DJNZ, JR and EXX in place of the 8080 aliases.
+    // The CB, DD, ED and FD prefixes decode as unknown opcodes, the prefixed instructions do not fit an Instruction8080.
+    pub fn decode_z80(bytes: &[u8]) -> Result<(Self, usize), DecodeError> {
+        let Some(&opcode) = bytes.first() else { return Err(DecodeError::Empty) };
+        let operand8 = bytes.get(1).copied().unwrap_or(0);
+
+        let mut result = Instruction8080::new(opcode);
+        match opcode {
+            0x08 => { result.action = InstructionAction::ExchangeAF; }
+            0xD9 => { result.action = InstructionAction::ExchangeAlternates; }
+
+            // DJNZ
+            0x10 => {
+                result.length += 1;
+                result.action = InstructionAction::DecrementJump;
+                result.target = InstructionTarget::Immediate8 { value: operand8 };
+            }
+
+            // JR / JR cc
+            0x18 | 0x20 | 0x28 | 0x30 | 0x38 => {
+                const CONDITIONS: [Condition; 5] = [Condition::None, Condition::NotZero, Condition::Zero, Condition::NotCarry, Condition::Carry];
+                result.length += 1;
+                result.action = InstructionAction::JumpRelative { condition: CONDITIONS[(opcode as usize - 0x18) / 8] };
+                result.target = InstructionTarget::Immediate8 { value: operand8 };
+            }
+
+            0xCB | 0xDD | 0xED | 0xFD => { }
+
+            _ => return Self::decode(bytes)
+        }
+
+        let length = result.length as usize;
+        if bytes.len() < length {
+            return Err(DecodeError::Truncated { opcode, needed: length, available: bytes.len() });
+        }
+        Ok((result, length))
+    }
+
+    // Whether this only exists on the 8085, the 8080 decodes the same opcode as something else.
+    pub fn is_8085_only(&self) -> bool {
+        match self.action {
+            InstructionAction::ReadInterruptMask | InstructionAction::SetInterruptMask | InstructionAction::Sub16 { .. }
+                | InstructionAction::ShiftRight16 { .. } | InstructionAction::RotateLeft16 { .. }
+                | InstructionAction::LoadOffset16 { .. } | InstructionAction::RestartOnOverflow => true,
+            InstructionAction::Jump { condition } => matches!(condition, Condition::NotUnderflow | Condition::Underflow),
+            InstructionAction::StoreReg16ToMemory { .. } | InstructionAction::LoadReg16FromMemory { .. } => {
+                matches!(self.target, InstructionTarget::Register16 { .. })
+            }
+            _ => false
+        }
+    }
+
+    // Decodes the instruction at the start of bytes, returning it along with the number of bytes it uses.
+    pub fn decode(bytes: &[u8]) -> Result<(Self, usize), DecodeError> {
+        let Some(&opcode) = bytes.first() else { return Err(DecodeError::Empty) };
+        let operand8 = bytes.get(1).copied().unwrap_or(0);
+        let operand16 = u16::from_le_bytes([operand8, bytes.get(2).copied().unwrap_or(0)]);
+
         let (opcode_high, opcode_low) = ((opcode & 0xF0) >> 4, opcode & 0xF);
         let mut result = Instruction8080::new(opcode);
 
@@ -295,7 +486,7 @@ impl Instruction8080
             (0x0..=0x3, 0x1) => {
                 result.length += 2;
                 result.action = InstructionAction::Load16 { register: REGISTER16_TABLE_FIRST[opcode_high as usize]};
-                result.target = InstructionTarget::Immediate16 { value: bus.read_w(pc + 1) }
+                result.target = InstructionTarget::Immediate16 { value: operand16 }
             }
             
             // STAX
@@ -308,14 +499,14 @@ impl Instruction8080
             (0x2, 0x2) => {
                 result.length += 2;
                 result.action = InstructionAction::StoreReg16ToMemory { register: Register16::HL };
-                result.target = InstructionTarget::Immediate16 { value: bus.read_w(pc + 1) }                 
+                result.target = InstructionTarget::Immediate16 { value: operand16 }                 
             }
 
             // STA
             (0x3, 0x2) => {
                 result.length += 2;
                 result.action = InstructionAction::StoreRegToMemory { register: Register8::A };
-                result.target = InstructionTarget::Immediate16 { value: bus.read_w(pc + 1) }
+                result.target = InstructionTarget::Immediate16 { value: operand16 }
             }
 
             // INX
@@ -335,7 +526,7 @@ impl Instruction8080
             (0x0..=0x3, 0x6) => {
                 result.length += 1;
                 result.action = InstructionAction::MovReg { register: REGISTER8_TABLE_FIRST[opcode_high as usize] };
-                result.target = InstructionTarget::Immediate8 { value: bus.read_b(pc + 1) }
+                result.target = InstructionTarget::Immediate8 { value: operand8 }
             }
 
             // RLC / RAL
@@ -366,14 +557,14 @@ impl Instruction8080
             (0x2, 0xA) => {
                 result.length += 2;
                 result.action = InstructionAction::LoadReg16FromMemory { register: Register16::HL };
-                result.target = InstructionTarget::Immediate16 { value: bus.read_w(pc + 1) };
+                result.target = InstructionTarget::Immediate16 { value: operand16 };
             }
 
             // LDA
             (0x3, 0xA) => {
                 result.length += 2;
                 result.action = InstructionAction::LoadRegFromMemory { register: Register8::A };
-                result.target = InstructionTarget::Immediate16 { value: bus.read_w(pc + 1) };
+                result.target = InstructionTarget::Immediate16 { value: operand16 };
             }
 
             // DCX
@@ -393,7 +584,7 @@ impl Instruction8080
             (0x0..=0x3, 0xE) => {
                 result.length += 1;
                 result.action = InstructionAction::MovReg { register: REGISTER8_TABLE_SECOND[opcode_high as usize] };
-                result.target = InstructionTarget::Immediate8 { value: bus.read_b(pc + 1) }
+                result.target = InstructionTarget::Immediate8 { value: operand8 }
             }
 
             // RRC / RAR
@@ -475,7 +666,6 @@ impl Instruction8080
             // Conditional returns first
             (0xC..=0xF, 0x0) => {
                 result.action = InstructionAction::Return { condition: CONDITION_TABLE_FIRST[(opcode_high - 0xC) as usize] };
-                result.target = InstructionTarget::Immediate16 { value: bus.read_w(pc + 1) };               
             }
 
             // Pop 16-bit
@@ -485,21 +675,21 @@ impl Instruction8080
             (0xC..=0xF, 0x2) => {
                 result.length += 2;
                 result.action = InstructionAction::Jump { condition: CONDITION_TABLE_FIRST[(opcode_high - 0xC) as usize] };
-                result.target = InstructionTarget::Immediate16 { value: bus.read_w(pc + 1) };               
+                result.target = InstructionTarget::Immediate16 { value: operand16 };               
             }
 
             // Unconditional jumps
             (0xC, 0x3) | (0xC, 0xB) => {
                 result.length += 2;
                 result.action = InstructionAction::Jump { condition: Condition::None };
-                result.target = InstructionTarget::Immediate16 { value: bus.read_w(pc + 1) };
+                result.target = InstructionTarget::Immediate16 { value: operand16 };
             }
 
             // Out 8-bit
             (0xD, 0x3) => {
                 result.length += 1;
                 result.action = InstructionAction::Out8;
-                result.target = InstructionTarget::Immediate8 { value: bus.read_b(pc + 1) }
+                result.target = InstructionTarget::Immediate8 { value: operand8 }
             }
 
             // XTHL
@@ -512,7 +702,7 @@ impl Instruction8080
             (0xC..=0xF, 0x4) => {
                 result.length += 2;
                 result.action = InstructionAction::Call { condition: CONDITION_TABLE_FIRST[(opcode_high - 0xC) as usize] };
-                result.target = InstructionTarget::Immediate16 { value: bus.read_w(pc + 1) };               
+                result.target = InstructionTarget::Immediate16 { value: operand16 };               
             }
 
             // Push 16-bit
@@ -524,40 +714,36 @@ impl Instruction8080
             (0xC, 0x6) => {
                 result.length += 1;
                 result.action = InstructionAction::AddReg { register: Register8::A, carry: false };
-                result.target = InstructionTarget::Immediate8 { value:  bus.read_b(pc + 1) }
+                result.target = InstructionTarget::Immediate8 { value: operand8 }
             }
 
             // SUI
             (0xD, 0x6) => {
                 result.length += 1;
                 result.action = InstructionAction::SubReg { register: Register8::A, borrow: false };
-                result.target = InstructionTarget::Immediate8 { value:  bus.read_b(pc + 1) }
+                result.target = InstructionTarget::Immediate8 { value: operand8 }
             }
 
             // ANI
             (0xE, 0x6) => {
                 result.length += 1;
                 result.action = InstructionAction::AndReg { register: Register8::A };
-                result.target = InstructionTarget::Immediate8 { value: bus.read_b(pc + 1) };
+                result.target = InstructionTarget::Immediate8 { value: operand8 };
             }
 
             // ORI
             (0xF, 0x6) => {
                 result.length += 1;
                 result.action = InstructionAction::OrReg { register: Register8::A };
-                result.target = InstructionTarget::Immediate8 { value:  bus.read_b(pc + 1) }
+                result.target = InstructionTarget::Immediate8 { value: operand8 }
             }
 
             // Even resets
-            (0xC..=0xF, 0x7) => {
-                result.action = InstructionAction::Call { condition: Condition::None };
-                result.target = InstructionTarget::Immediate16 { value: 0x0000 + 8 * ((opcode_high - 0xC) * 2) as u16 };                
-            }
+            (0xC..=0xF, 0x7) => { result.action = InstructionAction::Restart { vector: (opcode_high - 0xC) * 2 }; }
 
             // Conditional returns second
             (0xC..=0xF, 0x8) => {
                 result.action = InstructionAction::Return { condition: CONDITION_TABLE_SECOND[(opcode_high - 0xC) as usize] };
-                result.target = InstructionTarget::Immediate16 { value: bus.read_w(pc + 1) };               
             }
 
             // Unconditional returns.
@@ -581,14 +767,14 @@ impl Instruction8080
             (0xC..=0xF, 0xA) => {
                 result.length += 2;
                 result.action = InstructionAction::Jump { condition: CONDITION_TABLE_SECOND[(opcode_high - 0xC) as usize] };
-                result.target = InstructionTarget::Immediate16 { value: bus.read_w(pc + 1) };
+                result.target = InstructionTarget::Immediate16 { value: operand16 };
             }
 
             // IN
             (0xD, 0xB) => {
                 result.length += 1;
                 result.action = InstructionAction::In8;
-                result.target = InstructionTarget::Immediate8 { value: bus.read_b(pc + 1) }
+                result.target = InstructionTarget::Immediate8 { value: operand8 }
             }
 
             // XCHG
@@ -601,54 +787,56 @@ impl Instruction8080
             (0xC..=0xF, 0xC) => {
                 result.length += 2;
                 result.action = InstructionAction::Call { condition: CONDITION_TABLE_SECOND[(opcode_high - 0xC) as usize] };
-                result.target = InstructionTarget::Immediate16 { value: bus.read_w(pc + 1) };               
+                result.target = InstructionTarget::Immediate16 { value: operand16 };               
             }
 
             // Unconditional calls.
             (0xC..=0xF, 0xD) => {
                 result.length += 2;
                 result.action = InstructionAction::Call { condition: Condition::None };
-                result.target = InstructionTarget::Immediate16 { value: bus.read_w(pc + 1) };
+                result.target = InstructionTarget::Immediate16 { value: operand16 };
             }
 
             // ACI
             (0xC, 0xE) => {
                 result.length += 1;
                 result.action = InstructionAction::AddReg { register: Register8::A, carry: true };
-                result.target = InstructionTarget::Immediate8 { value:  bus.read_b(pc + 1) }
+                result.target = InstructionTarget::Immediate8 { value: operand8 }
             }
 
             // SBI
             (0xD, 0xE) => {
                 result.length += 1;
                 result.action = InstructionAction::SubReg { register: Register8::A, borrow: true };
-                result.target = InstructionTarget::Immediate8 { value:  bus.read_b(pc + 1) }
+                result.target = InstructionTarget::Immediate8 { value: operand8 }
             }
 
             // XRI
             (0xE, 0xE) => {
                 result.length += 1;
                 result.action = InstructionAction::XorReg { register: Register8::A };
-                result.target = InstructionTarget::Immediate8 { value: bus.read_b(pc + 1) };
+                result.target = InstructionTarget::Immediate8 { value: operand8 };
             }
 
             // CPI
             (0xF, 0xE) => {
                 result.length += 1;
                 result.action = InstructionAction::CompareReg { register: Register8::A };
-                result.target = InstructionTarget::Immediate8 { value: bus.read_b(pc + 1) }
+                result.target = InstructionTarget::Immediate8 { value: operand8 }
             }
 
             // Odd resets.
-            (0xC..=0xF, 0xF) => {
-                result.action = InstructionAction::Call { condition: Condition::None };
-                result.target = InstructionTarget::Immediate16 { value: 0x0000 + 8 * ((opcode_high - 0xC) * 2 + 1) as u16 }; 
-            }
+            (0xC..=0xF, 0xF) => { result.action = InstructionAction::Restart { vector: (opcode_high - 0xC) * 2 + 1 }; }
 
         // Fourth row end.
 
             _ => { }        // Everything else does not exist.
         }
-        result
+
+        let length = result.length as usize;
+        if bytes.len() < length {
+            return Err(DecodeError::Truncated { opcode, needed: length, available: bytes.len() });
+        }
+        Ok((result, length))
     }
 }
\ No newline at end of file
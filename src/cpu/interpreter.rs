@@ -1,438 +1,758 @@
 use std::sync::{Arc, RwLock};
 use crate::{Bus8080, ErrorBus};
-use crate::cpu::{CPU8080, Instruction8080, InstructionAction, Registers, Register16, Register8, RegisterFlags};
+use crate::cpu::{alu, i8085, BusAccess, BusStorage, CPU8080, CpuError, FaultPolicy, History, Instruction8080, InstructionAction, InstructionType, JournalEntry, Model, Registers, Register16, RegisterFlags, RunExit, RunInfo, SharedBus, StepInfo, FLAG_CARRY, FLAG_HALF_CARRY, FLAG_OVERFLOW, FLAG_ZERO};
+use crate::cpu::step::{budget_exit, halt_fast_forward, HALT_CYCLES};
+use crate::state::SaveState;
 
-use super::instruction::InstructionTarget;
-use super::Condition;
-
-pub struct Interpreter8080
+// Generic over where the bus lives, see BusStorage. The default shares it behind a lock and implements CPU8080,
+// Interpreter8080<MyBus> owns a concrete bus and skips both the lock and the dynamic dispatch.
+pub struct Interpreter8080<B: BusStorage = SharedBus>
 {
-    cycles: u32,
+    cycles: u64,
     registers: Registers,
-    bus: Arc<RwLock<Box<dyn Bus8080>>>
+    fault_policy: FaultPolicy,
+    history: Option<History>,
+    model: Model,
+    undocumented: bool,
+    decode_table: &'static [Instruction8080; 256],
+    bus: B
 }
 
 impl Interpreter8080
 {
     pub fn new() -> Self {
+        Self::with_bus(Arc::new(RwLock::new(Box::new(ErrorBus::new()))))
+    }
+}
+
+impl<B: BusStorage> Interpreter8080<B>
+{
+    pub fn with_bus(bus: B) -> Self {
         Self {
             cycles: 0x00,
             registers: Registers::new(),
-            bus: Arc::new(RwLock::new(Box::new(ErrorBus::new())))
+            fault_policy: FaultPolicy::Stop,
+            history: None,
+            model: Model::Intel8080,
+            undocumented: true,
+            decode_table: Instruction8080::decode_table(),
+            bus
         }
     }
-}
 
-unsafe impl Sync for Interpreter8080{}
-unsafe impl Send for Interpreter8080{}
+    // Runs the 8080 (the default) or the 8085 instruction set, timings and interrupts.
+    pub fn set_model(&mut self, model: Model) {
+        self.model = model;
+        self.update_decode_table();
+    }
 
-impl CPU8080 for Interpreter8080
-{
-    fn get_executed_cycles(&mut self) -> u32 {
+    // With undocumented opcodes disabled they are unknown opcodes handled by the fault policy,
+    // instead of running as the aliases (or 8085 instructions) they decode to.
+    pub fn set_undocumented_opcodes(&mut self, enabled: bool) {
+        self.undocumented = enabled;
+        self.update_decode_table();
+    }
+
+    fn update_decode_table(&mut self) {
+        self.decode_table = if self.undocumented { self.model.decode_table() } else { self.model.documented_decode_table() };
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+
+    pub fn set_fault_policy(&mut self, policy: FaultPolicy) {
+        self.fault_policy = policy;
+    }
+
+    // Starts recording the last `capacity` instructions so they can be undone, with a full snapshot
+    // every `snapshot_interval` instructions (0 for none, buses without save states never get one).
+    pub fn enable_history(&mut self, capacity: usize, snapshot_interval: usize) {
+        self.history = Some(History::new(capacity, snapshot_interval));
+    }
+
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    // Undoes the last instruction, false when there is no history left.
+    pub fn step_back(&mut self) -> bool {
+        self.rewind(1) == 1
+    }
+
+    // Undoes up to `instructions` instructions and returns how many were undone.
+    pub fn rewind(&mut self, instructions: usize) -> usize {
+        let Some(history) = &mut self.history else { return 0 };
+        let mut bus = self.bus.lock_anyway();
+
+        let restored = match history.snapshot_within(instructions) {
+            Some((skipped, state)) if bus.load_state(&state.bus).is_ok() => {
+                self.registers = state.registers;
+                self.cycles = state.cycles;
+                Some(skipped)
+            }
+            _ => None
+        };
+        let mut undone = restored.unwrap_or(0);
+        history.discard(undone);
+
+        while undone < instructions {
+            let Some(entry) = history.pop() else { break };
+            for (address, value) in entry.writes.iter().rev() {
+                bus.write_b(*address, *value);
+            }
+            self.registers = entry.registers;
+            self.cycles = entry.cycles;
+            undone += 1;
+        }
+        undone
+    }
+
+    // The CPU8080 methods, for any bus storage.
+    pub fn get_executed_cycles(&self) -> u64 {
         self.cycles
     }
 
-    fn force_jump(&mut self, a: u16) {
-        self.registers.pc = a;
+    pub fn set_executed_cycles(&mut self, cycles: u64) {
+        self.cycles = cycles;
     }
 
-    fn set_bus(&mut self, b: Arc<RwLock<Box<dyn Bus8080>>>) {
-        self.bus = b;
+    pub fn registers(&self) -> &Registers {
+        &self.registers
     }
 
-    fn get_bus(&self) -> Arc<RwLock<Box<dyn Bus8080>>> {
-        Arc::clone(&self.bus)
+    pub fn registers_mut(&mut self) -> &mut Registers {
+        &mut self.registers
     }
 
-    fn stop(&mut self) {
+    pub fn force_jump(&mut self, a: u16) {
+        self.registers.pc = a;
+    }
+
+    pub fn stop(&mut self) {
         self.registers.running = false;
     }
 
-    fn is_running(&mut self) -> bool {
+    pub fn reset(&mut self) {
+        self.registers.reset();
+    }
+
+    pub fn is_running(&self) -> bool {
         self.registers.running
     }
 
-    fn step(&mut self) {
-        let mut bus_write = self.bus.write().unwrap();
-        // Check and execute interrupts if needed.
-        let instruction = if self.registers.interrupts && bus_write.has_interrupt() {
-            self.registers.halting = false;
-            Instruction8080::from_opcode(bus_write.get_interrupt(), self.registers.pc, &bus_write)
+    pub fn step(&mut self) -> Result<StepInfo, CpuError> {
+        let pc = self.registers.pc;
+        if self.history.is_some() {
+            self.record();
+        }
+        let result = if self.bus.is_poisoned() && !self.should_recover(&CpuError::BusFault { pc }) {
+            Err(CpuError::BusFault { pc })
         }
         else {
-            if self.registers.halting { return }
-            let opcode = bus_write.as_ref().read_b(self.registers.pc);
-            let instruction = Instruction8080::from_opcode(opcode, self.registers.pc, &bus_write);
-            self.registers.pc = self.registers.pc.wrapping_add(instruction.length as u16);
-            instruction
+            self.bus.clear_poison();
+            match self.execute() {
+                Err(error) => self.handle_fault(error),
+                result => result
+            }
         };
 
-        self.cycles += match instruction.action {
-        // NOP.
-            InstructionAction::Nothing => { 4 }
+        if result.is_err() {
+            self.registers.running = false;
+        }
+        result
+    }
+
+    pub fn run(&mut self) -> Result<(), CpuError> {
+        while self.registers.running {
+            self.run_limited(None, |_| false)?;
+        }
+        Ok(())
+    }
+
+    // Runs until at least `cycles` cycles have passed or the CPU stops. A halted CPU nothing wakes skips
+    // straight to the end of the budget, as if it had idled through it in HALT_CYCLES steps.
+    // The bus stays locked between instructions, only faults and history recording go through step().
+    pub fn run_for_cycles(&mut self, cycles: u64) -> Result<RunInfo, CpuError> {
+        self.run_limited(Some(cycles), |_| false)
+    }
+
+    // Runs until the predicate holds after an instruction, the CPU stops or halts with nothing to wake it.
+    pub fn run_until(&mut self, predicate: impl FnMut(&Registers) -> bool) -> Result<RunInfo, CpuError> {
+        self.run_limited(None, predicate)
+    }
 
-        // Flow control section.
-            InstructionAction::Jump { condition } => {
-                if self.registers.check_condition(&condition) {
-                    self.registers.pc = instruction.target.get_value_as_u16(&self.registers);
+    fn run_limited(&mut self, cycles: Option<u64>, mut done: impl FnMut(&Registers) -> bool) -> Result<RunInfo, CpuError> {
+        let start = self.cycles;
+        let budget = cycles.unwrap_or(u64::MAX);
+        let exit = 'run: loop {
+            if !self.registers.running {
+                break RunExit::Stopped;
+            }
+            if self.cycles.wrapping_sub(start) >= budget {
+                break budget_exit(&self.registers);
+            }
+            if self.history.is_some() || self.bus.is_poisoned() {
+                // Every idle step is journaled here, so a halted CPU only idles through an actual budget.
+                if cycles.is_none() && self.registers.halting && !self.interrupt_pending() {
+                    break RunExit::Halted;
+                }
+                self.step()?;
+                if done(&self.registers) {
+                    break RunExit::Condition;
                 }
-                10
+                continue;
             }
 
-            InstructionAction::Call { condition } => {
-                let mut cycles = 11;
-                if self.registers.check_condition(&condition) {
-                    self.registers.sp = self.registers.sp.wrapping_sub(2);
-                    bus_write.write_w(self.registers.sp, self.registers.pc);
-                    self.registers.pc = instruction.target.get_value_as_u16(&self.registers);
-                    cycles = 17;
+            let Some(mut bus) = self.bus.lock() else { continue };
+            let result = loop {
+                let spent = self.cycles.wrapping_sub(start);
+                if !self.registers.running || spent >= budget {
+                    break Ok(());
                 }
-                if condition == Condition::None { cycles = 17; }
-                cycles
-            }
+                if self.registers.halting && !interrupt_waiting(&self.registers, &*bus, self.model) {
+                    if cycles.is_some() {
+                        self.cycles = self.cycles.wrapping_add(halt_fast_forward(budget - spent));
+                    }
+                    break 'run RunExit::Halted;
+                }
+                match execute_instruction(&mut self.registers, &mut *bus, self.decode_table, self.model) {
+                    Ok(info) => self.cycles = self.cycles.wrapping_add(info.cycles as u64),
+                    Err(error) => break Err(error)
+                }
+                if done(&self.registers) {
+                    break 'run RunExit::Condition;
+                }
+            };
+            drop(bus);
 
-            InstructionAction::Return { condition } => {
-                let mut cycles = 5;
-                if self.registers.check_condition(&condition) {
-                    self.registers.pc = bus_write.read_w(self.registers.sp);
-                    self.registers.sp = self.registers.sp.wrapping_add(2);
-                    cycles = 11;
+            if let Err(error) = result {
+                if let Err(error) = self.handle_fault(error) {
+                    self.registers.running = false;
+                    return Err(error);
+                }
+                // A recovered fault counts as an instruction too.
+                if done(&self.registers) {
+                    break RunExit::Condition;
                 }
-                if condition == Condition::None { cycles = 10; }
-                cycles
             }
+        };
+        Ok(RunInfo { cycles: self.cycles.wrapping_sub(start), exit })
+    }
+}
 
-            InstructionAction::Halt => {
-               self.registers.halting = true;
-               7
-            }
+unsafe impl<B: BusStorage> Sync for Interpreter8080<B>{}
+unsafe impl<B: BusStorage> Send for Interpreter8080<B>{}
 
-            InstructionAction::SetInterrupts { enabled } => {
-                self.registers.interrupts = enabled;
-                4
-            }
-        // End flow control section.
+// Inherent methods take precedence, so these forward to the ones above.
+impl CPU8080 for Interpreter8080
+{
+    fn get_executed_cycles(&mut self) -> u64 {
+        Self::get_executed_cycles(self)
+    }
 
-        // Carry section.
-            InstructionAction::SetCarry { value } => {
-                self.registers.set_flag(RegisterFlags::Carry, value);
-                4
-            }
+    fn set_executed_cycles(&mut self, cycles: u64) {
+        Self::set_executed_cycles(self, cycles)
+    }
 
-            InstructionAction::ComplementCarry => {
-                self.registers.set_flag(RegisterFlags::Carry, !self.registers.get_flag(RegisterFlags::Carry));
-                4
-            }
-        // End carry section.
-        
-        // 8-bit registers section.
-            InstructionAction::MovReg { register } => {
-                let value = instruction.target.get_value_as_u8(&mut bus_write, &self.registers);
-                self.registers.set_8(&register, &mut bus_write, value);
-                if matches!(instruction.target, InstructionTarget::Immediate8 { .. }) {
-                    if register == Register8::M { 10 } else { 7 }
-                } else {
-                    if register == Register8::M { 7 } else { 5 }
-                }
-            }
+    fn registers(&self) -> &Registers {
+        Self::registers(self)
+    }
 
-            InstructionAction::IncrementReg { register } => {
-                let register_value = self.registers.get_8(&mut bus_write, &register) as u16;
-                let result = ((register_value + 1) & 0xFF) as u8;
+    fn registers_mut(&mut self) -> &mut Registers {
+        Self::registers_mut(self)
+    }
 
-                // Set flags.
-                self.registers.set_flag(RegisterFlags::HalfCarry, (result & 0xF) == 0x0);
-                self.registers.set_zsp(result);
+    fn force_jump(&mut self, a: u16) {
+        Self::force_jump(self, a)
+    }
 
-                self.registers.set_8(&register, &mut bus_write, result);
-                if register == Register8::M { 10 } else { 5 }
-            }
+    fn reset(&mut self) {
+        Self::reset(self)
+    }
 
-            InstructionAction::DecrementReg { register } => {
-                let register_value = self.registers.get_8(&mut bus_write, &register);
-                let result = register_value.wrapping_sub(1);
-                
-                // Set flags.
-                self.registers.set_flag(RegisterFlags::HalfCarry, (result & 0xF) != 0xF);
-                self.registers.set_zsp(result);
-
-                self.registers.set_8(&register, &mut bus_write, result);
-                if register == Register8::M { 10 } else { 5 }
-            }       
-
-            InstructionAction::AddReg { register, carry } => {
-                let value = instruction.target.get_value_as_u8(&mut bus_write, &self.registers) as u16;
-                let register_value = self.registers.get_8(&mut bus_write, &register) as u16;
-                let carry = if carry && self.registers.get_flag(RegisterFlags::Carry) { 1 } else { 0 };
-                let result = register_value + value + carry;
-                
-                // Set flags.
-                self.registers.set_flag(RegisterFlags::Carry, ((result ^ register_value ^ value) & (1 << 8)) != 0);
-                self.registers.set_flag(RegisterFlags::HalfCarry, ((result ^ register_value ^ value) & (1 << 4)) != 0);
-                
-                let result = (result & 0xFF) as u8;
-                self.registers.set_zsp(result);
-                self.registers.set_8(&register, &mut bus_write, result);
-                if instruction.target == (InstructionTarget::Register8 { register: Register8::M }) { 7 } else { 4 }
-            }
+    fn set_bus(&mut self, b: Arc<RwLock<Box<dyn Bus8080>>>) {
+        self.bus = b;
+    }
 
-            InstructionAction::SubReg { register, borrow: carry } => {
-                // Subtraction is same as addition with !value and inverted carries.
-                let value = !(instruction.target.get_value_as_u8(&mut bus_write, &self.registers) as u16);
-                let register_value = self.registers.get_8(&mut bus_write, &register) as u16;
-                let carry = if carry && self.registers.get_flag(RegisterFlags::Carry) { 0 } else { 1 };
-                let result = register_value.wrapping_add(value).wrapping_add(carry);
-                
-                // Set flags.
-                self.registers.set_flag(RegisterFlags::Carry, ((result ^ register_value ^ value) & (1 << 8)) == 0);
-                self.registers.set_flag(RegisterFlags::HalfCarry, ((result ^ register_value ^ value) & (1 << 4)) != 0);
-                
-                let result = (result & 0xFF) as u8;
-                self.registers.set_zsp(result);
-                self.registers.set_8(&register, &mut bus_write, result);
-                if instruction.target == (InstructionTarget::Register8 { register: Register8::M }) { 7 } else { 4 }
-            }
+    fn get_bus(&self) -> Arc<RwLock<Box<dyn Bus8080>>> {
+        Arc::clone(&self.bus)
+    }
 
-            InstructionAction::CompareReg { register } => {
-                let value = instruction.target.get_value_as_u8(&mut bus_write, &self.registers) as u16;
-                let register_value = self.registers.get_8(&mut bus_write, &register) as u16;
-                let result = register_value.wrapping_sub(value);
-                
-                self.registers.set_flag(RegisterFlags::Carry, (result >> 8) != 0);
-                self.registers.set_flag(RegisterFlags::HalfCarry,  !(register_value ^ result ^ value) & 0x10 != 0);
-                self.registers.set_zsp((result & 0xFF) as u8);
-                if instruction.target == (InstructionTarget::Register8 { register: Register8::M }) { 7 } else { 4 }
-            }
+    fn stop(&mut self) {
+        Self::stop(self)
+    }
 
-            InstructionAction::AndReg { register } => {
-                let register_value = self.registers.get_8(&mut bus_write, &register);
-                let value = instruction.target.get_value_as_u8(&mut bus_write, &self.registers);
-                let result = register_value & value;
+    fn is_running(&mut self) -> bool {
+        Self::is_running(self)
+    }
 
-                self.registers.set_flag(RegisterFlags::Carry, false);
-                self.registers.set_flag(RegisterFlags::HalfCarry, (register_value | value) & 0x08 != 0);
-                self.registers.set_zsp(result);
+    fn step(&mut self) -> Result<StepInfo, CpuError> {
+        Self::step(self)
+    }
 
-                self.registers.set_8(&register, &mut bus_write, result);
-                if instruction.target == (InstructionTarget::Register8 { register: Register8::M }) { 7 } else { 4 }
-            }
+    fn run(&mut self) -> Result<(), CpuError> {
+        Self::run(self)
+    }
 
-            InstructionAction::OrReg { register } => {
-                let register_value = self.registers.get_8(&mut bus_write, &register);
-                let value = instruction.target.get_value_as_u8(&mut bus_write, &self.registers);
-                let result = register_value | value;
+    fn model(&self) -> Model {
+        Self::model(self)
+    }
 
-                self.registers.set_flag(RegisterFlags::Carry, false);
-                self.registers.set_flag(RegisterFlags::HalfCarry, false);
-                self.registers.set_zsp(result);
+    fn run_for_cycles(&mut self, cycles: u64) -> Result<RunInfo, CpuError> {
+        Self::run_for_cycles(self, cycles)
+    }
 
-                self.registers.set_8(&register, &mut bus_write, result);
-                if instruction.target == (InstructionTarget::Register8 { register: Register8::M }) { 7 } else { 4 }
-            }
+    fn run_until(&mut self, predicate: &mut dyn FnMut(&Registers) -> bool) -> Result<RunInfo, CpuError> {
+        Self::run_until(self, predicate)
+    }
+}
 
-            InstructionAction::XorReg { register } => {
-                let register_value = self.registers.get_8(&mut bus_write, &register);
-                let value = instruction.target.get_value_as_u8(&mut bus_write, &self.registers);
-                let result = register_value ^ value;
-
-                self.registers.set_flag(RegisterFlags::Carry, false);
-                self.registers.set_flag(RegisterFlags::HalfCarry, false);
-                self.registers.set_zsp(result);
-                
-                self.registers.set_8(&Register8::A, &mut bus_write, result);
-                if instruction.target == (InstructionTarget::Register8 { register: Register8::M }) { 7 } else { 4 }
-            }
+impl<B: BusStorage> Interpreter8080<B>
+{
+    // Journals the registers and every byte the next instruction may overwrite.
+    fn record(&mut self) {
+        let Some(history) = &mut self.history else { return };
+        let bus = self.bus.lock_anyway();
 
-            InstructionAction::ComplementReg { register } => {
-                let mut value = self.registers.get_8(&mut bus_write, &register);
-                value = !value;
-                self.registers.set_8(&register, &mut bus_write, value);
-                4
+        if history.wants_snapshot() {
+            let state = bus.save_state();
+            if !state.is_empty() {
+                history.push_snapshot(SaveState { registers: self.registers, cycles: self.cycles, bus: state });
             }
+        }
 
-            InstructionAction::StoreRegToMemory { register } => {
-                let value = self.registers.get_8(&mut bus_write, &register);
-                let location = instruction.target.get_value_as_u16(&self.registers);
-                bus_write.write_b(location, value);
-                if instruction.target == (InstructionTarget::Register16 { register: Register16::HL }) { 16 } else { 10 }
-            }
+        // Interrupts and faults trapped to a vector push PC, anything else is predicted from the opcode.
+        let instruction = fetch(&*bus, self.decode_table, self.registers.pc);
+        let pending = interrupt_waiting(&self.registers, &*bus, self.model);
+        let accesses = if pending || instruction.action == InstructionAction::None {
+            vec![BusAccess::Memory { address: self.registers.sp.wrapping_sub(2), size: 2, write: true }]
+        } else {
+            instruction.bus_accesses(&self.registers)
+        };
+
+        let writes = accesses.into_iter()
+            .filter_map(|access| match access {
+                BusAccess::Memory { address, size, write: true } => Some((0..size).map(move |offset| address.wrapping_add(offset))),
+                _ => None
+            })
+            .flatten()
+            .map(|address| (address, bus.read_b(address)))
+            .collect();
+        history.push(JournalEntry { registers: self.registers, cycles: self.cycles, writes });
+    }
 
-            InstructionAction::LoadRegFromMemory { register } => {
-                let location = instruction.target.get_value_as_u16(&self.registers);
-                let value = bus_write.read_b(location);
-                self.registers.set_8(&register, &mut bus_write, value);
-                if matches!(instruction.target, InstructionTarget::Register16 { .. }) { 16 } else { 13 }
+    fn interrupt_pending(&mut self) -> bool {
+        interrupt_waiting(&self.registers, &*self.bus.lock_anyway(), self.model)
+    }
+
+    fn should_recover(&mut self, error: &CpuError) -> bool {
+        match &mut self.fault_policy {
+            FaultPolicy::Stop => false,
+            FaultPolicy::Nop | FaultPolicy::Trap { .. } => true,
+            FaultPolicy::Callback(callback) => callback(error, &mut self.registers)
+        }
+    }
+
+    fn handle_fault(&mut self, error: CpuError) -> Result<StepInfo, CpuError> {
+        let (pc, opcode) = match error {
+            CpuError::UnknownOpcode { pc, opcode } | CpuError::InvalidOperand { pc, opcode } => (pc, opcode),
+            CpuError::BusFault { .. } => return Err(error)
+        };
+
+        let cycles = match &mut self.fault_policy {
+            FaultPolicy::Stop => None,
+            FaultPolicy::Nop => Some(4),
+            FaultPolicy::Trap { vector } => {
+                let mut bus_write = self.bus.lock().ok_or(CpuError::BusFault { pc })?;
+                self.registers.sp = self.registers.sp.wrapping_sub(2);
+                bus_write.write_w(self.registers.sp, self.registers.pc);
+                self.registers.pc = (*vector as u16 & 0x7) * 8;
+                Some(11)
             }
+            FaultPolicy::Callback(callback) => callback(&error, &mut self.registers).then_some(4)
+        };
 
-            InstructionAction::DAAReg { register } => {
-                let register_value = self.registers.get_8(&mut bus_write, &register) as u16;
-                let mut carry = self.registers.get_flag(RegisterFlags::Carry);
-                let mut correction = 0x00;
+        let Some(cycles) = cycles else { return Err(error) };
 
-                let lsb = register_value & 0xF;
-                let msb = register_value >> 4;
+        self.cycles = self.cycles.wrapping_add(cycles as u64);
+        Ok(StepInfo { pc, opcode, cycles })
+    }
 
-                if self.registers.get_flag(RegisterFlags::HalfCarry) || lsb > 9 {
-                    correction += 0x06;
-                }
+    fn execute(&mut self) -> Result<StepInfo, CpuError> {
+        let pc = self.registers.pc;
+        let mut bus = self.bus.lock().ok_or(CpuError::BusFault { pc })?;
+        let info = execute_instruction(&mut self.registers, &mut *bus, self.decode_table, self.model)?;
+        self.cycles = self.cycles.wrapping_add(info.cycles as u64);
+        Ok(info)
+    }
+}
 
-                if self.registers.get_flag(RegisterFlags::Carry) || msb > 9 || (msb >= 9 && lsb > 9) {
-                    correction += 0x60;
-                    carry = true;
-                }
+// Whether an interrupt would be taken before the next instruction.
+pub(crate) fn interrupt_waiting<T: Bus8080 + ?Sized>(registers: &Registers, bus: &T, model: Model) -> bool {
+    match model {
+        Model::Intel8080 => registers.accepts_interrupt() && bus.has_interrupt(),
+        Model::Intel8085 => i8085::interrupt_pending(registers, bus)
+    }
+}
 
-                // Set flags.
-                let result = register_value + correction;
-                self.registers.set_flag(RegisterFlags::Carry, ((result ^ register_value ^ correction) & (1 << 8)) != 0);
-                self.registers.set_flag(RegisterFlags::HalfCarry, ((result ^ register_value ^ correction) & (1 << 4)) != 0);
-                
-                let result = (result & 0xFF) as u8;
-                self.registers.set_zsp(result);
-                self.registers.set_8(&register, &mut bus_write, result);
-                self.registers.set_flag(RegisterFlags::Carry, carry);
-                4
-            }
-            
-            InstructionAction::RotateReg { register, right, arithmetic } => {
-                let mut value = self.registers.get_8(&mut bus_write, &register);
-                let carry_in = if self.registers.get_flag(RegisterFlags::Carry) { 1 } else { 0 };
-
-                let (result, carry_out) = if !arithmetic {
-                    if right {
-                        let carry_out = value & 1;
-                        value = value >> 1;
-                        value |= carry_out << 7;
-                        (value, carry_out)
-                    } else {
-                        let carry_out = (value & 0x80) >> 7;
-                        value = value << 1;
-                        value |= carry_out;
-                        (value, carry_out)
-                    }
-                }
-                else {
-                    if right {
-                        let carry_out = value & 1;
-                        value = value >> 1;
-                        value |= carry_in << 7;
-                        (value, carry_out)
-                    } else {
-                        let carry_out = (value & 0x80) >> 7;
-                        value = value << 1;
-                        value |= carry_in;
-                        (value, carry_out)
-                    }                        
-                };
-
-                self.registers.set_flag(RegisterFlags::Carry, carry_out != 0);
-                self.registers.set_8(&register, &mut bus_write, result);
-                4
-            }
-        // End 8-bit registers section.
+// Runs one instruction (or accepts an interrupt) on an already locked bus.
+pub(crate) fn execute_instruction<T: Bus8080 + ?Sized>(registers: &mut Registers, bus_write: &mut T, table: &[Instruction8080; 256], model: Model) -> Result<StepInfo, CpuError> {
+    let pc = registers.pc;
+    // TRAP and RST 5.5 / 6.5 / 7.5 go ahead of INTR, they are reported as the CALL they amount to.
+    if model == Model::Intel8085 {
+        i8085::sample(registers, bus_write);
+        if let Some(vector) = i8085::vectored(registers) {
+            i8085::accept(registers, bus_write, vector);
+            return Ok(StepInfo { pc, opcode: 0xCD, cycles: i8085::INTERRUPT_CYCLES });
+        }
+    }
+    // Check and execute interrupts if needed, the device supplies the whole instruction.
+    // Accepting it resets INTE, the handler runs with interrupts disabled until its own EI.
+    let instruction = if registers.accepts_interrupt() && bus_write.has_interrupt() {
+        registers.interrupts = false;
+        registers.interrupt_delay = false;
+        registers.halting = false;
+        let opcode = bus_write.acknowledge_interrupt(0);
+        with_operands(table[opcode as usize], |index| bus_write.acknowledge_interrupt(index))
+    }
+    else {
+        if registers.halting { return Ok(StepInfo { pc, opcode: 0x76, cycles: HALT_CYCLES }) }
+        let instruction = fetch(bus_write, table, pc);
+        registers.pc = pc.wrapping_add(instruction.length as u16);
+        instruction
+    };
+    execute_decoded(registers, bus_write, instruction, pc, model)
+}
 
-        // 16-bit registers section.
-            InstructionAction::Load16 { ref register} => {
-                self.registers.set_16(register, instruction.target.get_value_as_u16(&self.registers));
-                10
-            }
+// Decodes the instruction at pc through the decode table, only reading the operand bytes it needs.
+pub(crate) fn fetch<T: Bus8080 + ?Sized>(bus: &T, table: &[Instruction8080; 256], pc: u16) -> Instruction8080 {
+    with_operands(table[bus.read_b(pc) as usize], |index| bus.read_b(pc.wrapping_add(index as u16)))
+}
 
-            InstructionAction::Increment16 { register } => {
-                let value = self.registers.get_16(&register).wrapping_add(1);
-                self.registers.set_16(&register, value);
-                5
-            }
+// Fills in the immediates of a decode table entry, `byte(n)` gives byte n of the instruction.
+pub(crate) fn with_operands(mut instruction: Instruction8080, mut byte: impl FnMut(u8) -> u8) -> Instruction8080 {
+    instruction.target = match instruction.target {
+        InstructionType::Immediate8 { .. } => InstructionType::Immediate8 { value: byte(1) },
+        InstructionType::Immediate16 { .. } => InstructionType::Immediate16 { value: u16::from_le_bytes([byte(1), byte(2)]) },
+        target => target
+    };
+    instruction
+}
 
-            InstructionAction::Decrement16 { register } => {
-                let value = self.registers.get_16(&register).wrapping_sub(1);
-                self.registers.set_16(&register, value);
-                5
+// Runs an instruction fetched from pc, registers.pc already points past it.
+pub(crate) fn execute_decoded<T: Bus8080 + ?Sized>(registers: &mut Registers, bus_write: &mut T, instruction: Instruction8080, pc: u16, model: Model) -> Result<StepInfo, CpuError> {
+    let i8085 = model == Model::Intel8085;
+    let invalid_operand = CpuError::InvalidOperand { pc, opcode: instruction.opcode };
+    // Whatever follows EI has now started, so the delay is over.
+    registers.interrupt_delay = false;
+
+    // Conditional calls and returns (and jumps on the 8085) cost differently depending on the outcome.
+    let mut taken = false;
+    match instruction.action {
+    // NOP.
+        InstructionAction::Nothing => { }
+
+    // Flow control section.
+        InstructionAction::Jump { condition } => {
+            taken = registers.check_condition(&condition);
+            if taken {
+                registers.pc = instruction.target.get_value_as_u16(registers).ok_or(invalid_operand)?;
             }
+        }
 
-            InstructionAction::Add16 { register } => {
-                let register_value = self.registers.get_16(&register);
-                let value = instruction.target.get_value_as_u16(&mut self.registers);
-                let (result, carry) = register_value.overflowing_add(value);
-                self.registers.set_flag(RegisterFlags::Carry, carry);
-                self.registers.set_16(&register, result);
-                10
+        InstructionAction::Call { condition } => {
+            taken = registers.check_condition(&condition);
+            if taken {
+                registers.sp = registers.sp.wrapping_sub(2);
+                bus_write.write_w(registers.sp, registers.pc);
+                registers.pc = instruction.target.get_value_as_u16(registers).ok_or(invalid_operand)?;
             }
+        }
 
-            InstructionAction::Push16 { ref register} => {
-                let value = self.registers.get_16(register);
-                self.registers.sp = self.registers.sp.wrapping_sub(2);
-                bus_write.write_w(self.registers.sp, value);
-                11
-            }
+        InstructionAction::Restart { vector } => {
+            registers.sp = registers.sp.wrapping_sub(2);
+            bus_write.write_w(registers.sp, registers.pc);
+            registers.pc = vector as u16 * 8;
+        }
 
-            InstructionAction::Pop16 { ref register} => {
-                let value = bus_write.read_w(self.registers.sp);
-                self.registers.sp = self.registers.sp.wrapping_add(2);
-                self.registers.set_16(register, value);
-                10
+        InstructionAction::Return { condition } => {
+            taken = registers.check_condition(&condition);
+            if taken {
+                registers.pc = bus_write.read_w(registers.sp);
+                registers.sp = registers.sp.wrapping_add(2);
             }
+        }
 
-            InstructionAction::LoadReg16FromMemory { register } => {
-                let location = instruction.target.get_value_as_u16(&self.registers);
-                let value = bus_write.read_w(location);
-                self.registers.set_16(&register, value);
-                16
-            }
+        InstructionAction::Halt => {
+           registers.halting = true;
+        }
 
-            InstructionAction::StoreReg16ToMemory { register } => {
-                let value = self.registers.get_16(&register);
-                let location = instruction.target.get_value_as_u16(&self.registers);
-                bus_write.write_w(location, value);
-                16
-            }
+        InstructionAction::SetInterrupts { enabled } => {
+            registers.interrupts = enabled;
+            registers.interrupt_delay = enabled;
+        }
+    // End flow control section.
 
-            InstructionAction::Exchange => {
-                let hl = self.registers.get_16(&Register16::HL);
-                let de = self.registers.get_16(&Register16::DE);
-                self.registers.set_16(&Register16::DE, hl);
-                self.registers.set_16(&Register16::HL, de);
-                5
-            }
+    // Carry section.
+        InstructionAction::SetCarry { value } => {
+            registers.set_flag(RegisterFlags::Carry, value);
+        }
 
-            InstructionAction::ExchangeToStack => {
-                let value = bus_write.read_w(self.registers.sp);
-                let hl = self.registers.get_16(&Register16::HL);
-                bus_write.write_w(self.registers.sp, hl);
-                self.registers.set_16(&Register16::HL, value);
-                18
-            }
-        // End of 16-bit registers section.
+        InstructionAction::ComplementCarry => {
+            registers.set_flag(RegisterFlags::Carry, !registers.get_flag(RegisterFlags::Carry));
+        }
+    // End carry section.
+    
+    // 8-bit registers section.
+        InstructionAction::MovReg { register } => {
+            let value = instruction.target.get_value_as_u8(&mut *bus_write, registers).ok_or(invalid_operand)?;
+            registers.set_8(&register, &mut *bus_write, value);
+        }
 
-        // Bus section.
-            InstructionAction::In8 => {
-                let value = instruction.target.get_value_as_u8(&mut bus_write, &self.registers);
-                self.registers.a = bus_write.in_b(&mut self.registers, value);
-                10
-            }
+        InstructionAction::IncrementReg { register } => {
+            let value = registers.get_8(&mut *bus_write, &register);
+            let output = alu::increment(value, registers.f);
+            registers.f = if i8085 { i8085::arithmetic_flags(output.flags, value, 1, output.result, false) } else { output.flags };
+            registers.set_8(&register, &mut *bus_write, output.result);
+        }
 
-            InstructionAction::Out8 => {
-                let value = instruction.target.get_value_as_u8(&mut bus_write, &self.registers);
-                let a = self.registers.a;
-                bus_write.out_b(&mut self.registers, value, a);
-                10
-            }
-        // End of bus section.
-
-        // Default / unimplemented.
-            _ => {
-                self.registers.running = false;
-                println!(
-                    "[EROR]: Unknown opcode found at PC 0x{:04X}: 0x{:02X}",
-                    self.registers.pc - instruction.length as u16, instruction.opcode
-                );
-                println!("{:#?}", instruction);
-                panic!("[WARN]: Dying...");  // TODO: do some actual error handling instead of dying.
+        InstructionAction::DecrementReg { register } => {
+            let value = registers.get_8(&mut *bus_write, &register);
+            let output = alu::decrement(value, registers.f);
+            registers.f = if i8085 { i8085::arithmetic_flags(output.flags, value, 1, output.result, true) } else { output.flags };
+            registers.set_8(&register, &mut *bus_write, output.result);
+        }
+
+        InstructionAction::AddReg { register, carry } => {
+            let value = instruction.target.get_value_as_u8(&mut *bus_write, registers).ok_or(invalid_operand)?;
+            let carry = carry && registers.get_flag(RegisterFlags::Carry);
+            let a = registers.get_8(&mut *bus_write, &register);
+            let output = alu::add(a, value, carry, registers.f);
+            registers.f = if i8085 { i8085::arithmetic_flags(output.flags, a, value, output.result, false) } else { output.flags };
+            registers.set_8(&register, &mut *bus_write, output.result);
+        }
+
+        InstructionAction::SubReg { register, borrow } => {
+            let value = instruction.target.get_value_as_u8(&mut *bus_write, registers).ok_or(invalid_operand)?;
+            let borrow = borrow && registers.get_flag(RegisterFlags::Carry);
+            let a = registers.get_8(&mut *bus_write, &register);
+            let output = alu::sub(a, value, borrow, registers.f);
+            registers.f = if i8085 { i8085::arithmetic_flags(output.flags, a, value, output.result, true) } else { output.flags };
+            registers.set_8(&register, &mut *bus_write, output.result);
+        }
+
+        InstructionAction::CompareReg { register } => {
+            let value = instruction.target.get_value_as_u8(&mut *bus_write, registers).ok_or(invalid_operand)?;
+            let a = registers.get_8(&mut *bus_write, &register);
+            let flags = alu::compare(a, value, registers.f).flags;
+            registers.f = if i8085 { i8085::arithmetic_flags(flags, a, value, a.wrapping_sub(value), true) } else { flags };
+        }
+
+        InstructionAction::AndReg { register } => {
+            let value = instruction.target.get_value_as_u8(&mut *bus_write, registers).ok_or(invalid_operand)?;
+            let output = alu::and(registers.get_8(&mut *bus_write, &register), value, registers.f);
+            // The 8085 always sets AC here.
+            registers.f = if i8085 { i8085::logic_flags(output.flags) | FLAG_HALF_CARRY } else { output.flags };
+            registers.set_8(&register, &mut *bus_write, output.result);
+        }
+
+        InstructionAction::OrReg { register } => {
+            let value = instruction.target.get_value_as_u8(&mut *bus_write, registers).ok_or(invalid_operand)?;
+            let output = alu::or(registers.get_8(&mut *bus_write, &register), value, registers.f);
+            registers.f = if i8085 { i8085::logic_flags(output.flags) } else { output.flags };
+            registers.set_8(&register, &mut *bus_write, output.result);
+        }
+
+        InstructionAction::XorReg { register } => {
+            let value = instruction.target.get_value_as_u8(&mut *bus_write, registers).ok_or(invalid_operand)?;
+            let output = alu::xor(registers.get_8(&mut *bus_write, &register), value, registers.f);
+            registers.f = if i8085 { i8085::logic_flags(output.flags) } else { output.flags };
+            registers.set_8(&register, &mut *bus_write, output.result);
+        }
+
+        InstructionAction::ComplementReg { register } => {
+            let mut value = registers.get_8(&mut *bus_write, &register);
+            value = !value;
+            registers.set_8(&register, &mut *bus_write, value);
+        }
+
+        InstructionAction::StoreRegToMemory { register } => {
+            let value = registers.get_8(&mut *bus_write, &register);
+            let location = instruction.target.get_value_as_u16(registers).ok_or(invalid_operand)?;
+            bus_write.write_b(location, value);
+        }
+
+        InstructionAction::LoadRegFromMemory { register } => {
+            let location = instruction.target.get_value_as_u16(registers).ok_or(invalid_operand)?;
+            let value = bus_write.read_b(location);
+            registers.set_8(&register, &mut *bus_write, value);
+        }
+
+        InstructionAction::DAAReg { register } => {
+            let output = alu::daa(registers.get_8(&mut *bus_write, &register), registers.f);
+            registers.f = output.flags;
+            registers.set_8(&register, &mut *bus_write, output.result);
+        }
+
+        InstructionAction::RotateReg { register, right, arithmetic } => {
+            let output = alu::rotate(registers.get_8(&mut *bus_write, &register), right, arithmetic, registers.f);
+            registers.f = output.flags;
+            registers.set_8(&register, &mut *bus_write, output.result);
+        }
+    // End 8-bit registers section.
+
+    // 16-bit registers section.
+        InstructionAction::Load16 { ref register} => {
+            registers.set_16(register, instruction.target.get_value_as_u16(registers).ok_or(invalid_operand)?);
+        }
+
+        InstructionAction::Increment16 { register } => {
+            let value = registers.get_16(&register).wrapping_add(1);
+            registers.set_16(&register, value);
+            if i8085 { registers.f = i8085::counter_flags(registers.f, value, true); }
+        }
+
+        InstructionAction::Decrement16 { register } => {
+            let value = registers.get_16(&register).wrapping_sub(1);
+            registers.set_16(&register, value);
+            if i8085 { registers.f = i8085::counter_flags(registers.f, value, false); }
+        }
+
+        InstructionAction::Add16 { register } => {
+            let value = instruction.target.get_value_as_u16(registers).ok_or(invalid_operand)?;
+            let (result, flags) = alu::add16(registers.get_16(&register), value, registers.f);
+            registers.f = flags;
+            registers.set_16(&register, result);
+        }
+
+        InstructionAction::Push16 { ref register} => {
+            let value = registers.get_16(register);
+            registers.sp = registers.sp.wrapping_sub(2);
+            bus_write.write_w(registers.sp, value);
+        }
+
+        InstructionAction::Pop16 { ref register} => {
+            let value = bus_write.read_w(registers.sp);
+            registers.sp = registers.sp.wrapping_add(2);
+            registers.set_16(register, value);
+            // The 8085 keeps V and K in the bits the 8080 fixes.
+            if i8085 && *register == Register16::PSW { registers.f = value as u8; }
+        }
+
+        InstructionAction::LoadReg16FromMemory { register } => {
+            let location = instruction.target.get_value_as_u16(registers).ok_or(invalid_operand)?;
+            let value = bus_write.read_w(location);
+            registers.set_16(&register, value);
+        }
+
+        InstructionAction::StoreReg16ToMemory { register } => {
+            let value = registers.get_16(&register);
+            let location = instruction.target.get_value_as_u16(registers).ok_or(invalid_operand)?;
+            bus_write.write_w(location, value);
+        }
+
+        InstructionAction::Exchange => {
+            let hl = registers.get_16(&Register16::HL);
+            let de = registers.get_16(&Register16::DE);
+            registers.set_16(&Register16::DE, hl);
+            registers.set_16(&Register16::HL, de);
+        }
+
+        InstructionAction::ExchangeToStack => {
+            let value = bus_write.read_w(registers.sp);
+            let hl = registers.get_16(&Register16::HL);
+            bus_write.write_w(registers.sp, hl);
+            registers.set_16(&Register16::HL, value);
+        }
+    // End of 16-bit registers section.
+
+    // Bus section.
+        InstructionAction::In8 => {
+            let value = instruction.target.get_value_as_u8(&mut *bus_write, registers).ok_or(invalid_operand)?;
+            registers.a = bus_write.in_b(registers, value);
+        }
+
+        InstructionAction::Out8 => {
+            let value = instruction.target.get_value_as_u8(&mut *bus_write, registers).ok_or(invalid_operand)?;
+            let a = registers.a;
+            bus_write.out_b(registers, value, a);
+        }
+    // End of bus section.
+
+    // 8085 section.
+        InstructionAction::ReadInterruptMask => {
+            registers.a = i8085::read_interrupt_mask(registers, &*bus_write);
+        }
+
+        InstructionAction::SetInterruptMask => {
+            let a = registers.a;
+            i8085::set_interrupt_mask(registers, &mut *bus_write, a);
+        }
+
+        InstructionAction::Sub16 { register } => {
+            // DSUB goes through the ALU a byte at a time, Z is for the whole result.
+            let value = instruction.target.get_value_as_u16(registers).ok_or(invalid_operand)?;
+            let [low, high] = registers.get_16(&register).to_le_bytes();
+            let [value_low, value_high] = value.to_le_bytes();
+            let first = alu::sub(low, value_low, false, registers.f);
+            let second = alu::sub(high, value_high, first.flags & FLAG_CARRY != 0, first.flags);
+            let result = u16::from_le_bytes([first.result, second.result]);
+            let flags = i8085::arithmetic_flags(second.flags, high, value_high, second.result, true);
+            registers.f = if result == 0 { flags | FLAG_ZERO } else { flags & !FLAG_ZERO };
+            registers.set_16(&register, result);
+        }
+
+        InstructionAction::ShiftRight16 { register } => {
+            let value = registers.get_16(&register);
+            registers.set_flag(RegisterFlags::Carry, value & 1 != 0);
+            registers.set_16(&register, ((value as i16) >> 1) as u16);
+        }
+
+        InstructionAction::RotateLeft16 { register } => {
+            let value = registers.get_16(&register);
+            let result = value << 1 | registers.get_flag(RegisterFlags::Carry) as u16;
+            registers.set_flag(RegisterFlags::Carry, value & 0x8000 != 0);
+            registers.f = if (value ^ result) & 0x8000 != 0 { registers.f | FLAG_OVERFLOW } else { registers.f & !FLAG_OVERFLOW };
+            registers.set_16(&register, result);
+        }
+
+        InstructionAction::LoadOffset16 { register, base } => {
+            let offset = instruction.target.get_value_as_u8(&mut *bus_write, registers).ok_or(invalid_operand)?;
+            let value = registers.get_16(&base).wrapping_add(offset as u16);
+            registers.set_16(&register, value);
+        }
+
+        InstructionAction::RestartOnOverflow => {
+            taken = registers.f & FLAG_OVERFLOW != 0;
+            if taken {
+                registers.sp = registers.sp.wrapping_sub(2);
+                bus_write.write_w(registers.sp, registers.pc);
+                registers.pc = 0x0040;
             }
-        }        
-    }
+        }
+    // End of 8085 section.
 
-    fn run(&mut self) {
-        while self.registers.running {
-            self.step();
+    // Default / unimplemented, the Z80 only actions are run by z80.
+        InstructionAction::None | InstructionAction::ExchangeAF | InstructionAction::ExchangeAlternates
+            | InstructionAction::JumpRelative { .. } | InstructionAction::DecrementJump => {
+            return Err(CpuError::UnknownOpcode { pc, opcode: instruction.opcode });
         }
     }
+
+    let info = &model.opcodes()[instruction.opcode as usize];
+    let cycles = if taken { info.cycles_taken } else { info.cycles } as u32;
+    Ok(StepInfo { pc, opcode: instruction.opcode, cycles })
 }
@@ -0,0 +1,479 @@
+use std::sync::OnceLock;
+
+use crate::cpu::{DecodeError, Instruction8080, InstructionAction, InstructionType, Register16, Registers, FLAG_OVERFLOW, FLAG_UNDERFLOW};
+
+// Flag masks, matching the bit layout of the F register.
+pub const FLAG_CARRY: u8 = 1 << 0;
+pub const FLAG_PARITY: u8 = 1 << 2;
+pub const FLAG_HALF_CARRY: u8 = 1 << 4;
+pub const FLAG_ZERO: u8 = 1 << 6;
+pub const FLAG_SIGN: u8 = 1 << 7;
+pub const FLAGS_ALL: u8 = FLAG_SIGN | FLAG_ZERO | FLAG_HALF_CARRY | FLAG_PARITY | FLAG_CARRY;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access
+{
+    None,
+    MemoryRead,
+    MemoryWrite,
+    MemoryReadWrite,
+    StackPush,
+    StackPop,
+    PortIn,
+    PortOut
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OpcodeInfo
+{
+    pub mnemonic: &'static str,
+    // Operand template, d8 / d16 / a16 stand for the immediate bytes.
+    pub operands: &'static str,
+    pub length: u8,
+    // Cycles when a conditional CALL / RET is not taken, the cost of every other instruction.
+    pub cycles: u8,
+    pub cycles_taken: u8,
+    pub flags_read: u8,
+    pub flags_written: u8,
+    pub access: Access,
+    // False for the undocumented aliases of other opcodes.
+    pub documented: bool
+}
+
+#[allow(clippy::too_many_arguments)]
+const fn op(mnemonic: &'static str, operands: &'static str, length: u8, cycles: u8, cycles_taken: u8, flags_read: u8, flags_written: u8, access: Access, documented: bool) -> OpcodeInfo {
+    OpcodeInfo { mnemonic, operands, length, cycles, cycles_taken, flags_read, flags_written, access, documented }
+}
+
+// Reference: Intel 8080 Microcomputer Systems User's Manual, instruction set summary.
+pub const OPCODES: [OpcodeInfo; 256] = [
+    /* 0x00 */ op("NOP", "", 1, 4, 4, 0, 0, Access::None, true),
+    /* 0x01 */ op("LXI", "B,d16", 3, 10, 10, 0, 0, Access::None, true),
+    /* 0x02 */ op("STAX", "B", 1, 7, 7, 0, 0, Access::MemoryWrite, true),
+    /* 0x03 */ op("INX", "B", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x04 */ op("INR", "B", 1, 5, 5, 0, FLAG_SIGN | FLAG_ZERO | FLAG_HALF_CARRY | FLAG_PARITY, Access::None, true),
+    /* 0x05 */ op("DCR", "B", 1, 5, 5, 0, FLAG_SIGN | FLAG_ZERO | FLAG_HALF_CARRY | FLAG_PARITY, Access::None, true),
+    /* 0x06 */ op("MVI", "B,d8", 2, 7, 7, 0, 0, Access::None, true),
+    /* 0x07 */ op("RLC", "", 1, 4, 4, 0, FLAG_CARRY, Access::None, true),
+    /* 0x08 */ op("NOP", "", 1, 4, 4, 0, 0, Access::None, false),
+    /* 0x09 */ op("DAD", "B", 1, 10, 10, 0, FLAG_CARRY, Access::None, true),
+    /* 0x0A */ op("LDAX", "B", 1, 7, 7, 0, 0, Access::MemoryRead, true),
+    /* 0x0B */ op("DCX", "B", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x0C */ op("INR", "C", 1, 5, 5, 0, FLAG_SIGN | FLAG_ZERO | FLAG_HALF_CARRY | FLAG_PARITY, Access::None, true),
+    /* 0x0D */ op("DCR", "C", 1, 5, 5, 0, FLAG_SIGN | FLAG_ZERO | FLAG_HALF_CARRY | FLAG_PARITY, Access::None, true),
+    /* 0x0E */ op("MVI", "C,d8", 2, 7, 7, 0, 0, Access::None, true),
+    /* 0x0F */ op("RRC", "", 1, 4, 4, 0, FLAG_CARRY, Access::None, true),
+    /* 0x10 */ op("NOP", "", 1, 4, 4, 0, 0, Access::None, false),
+    /* 0x11 */ op("LXI", "D,d16", 3, 10, 10, 0, 0, Access::None, true),
+    /* 0x12 */ op("STAX", "D", 1, 7, 7, 0, 0, Access::MemoryWrite, true),
+    /* 0x13 */ op("INX", "D", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x14 */ op("INR", "D", 1, 5, 5, 0, FLAG_SIGN | FLAG_ZERO | FLAG_HALF_CARRY | FLAG_PARITY, Access::None, true),
+    /* 0x15 */ op("DCR", "D", 1, 5, 5, 0, FLAG_SIGN | FLAG_ZERO | FLAG_HALF_CARRY | FLAG_PARITY, Access::None, true),
+    /* 0x16 */ op("MVI", "D,d8", 2, 7, 7, 0, 0, Access::None, true),
+    /* 0x17 */ op("RAL", "", 1, 4, 4, FLAG_CARRY, FLAG_CARRY, Access::None, true),
+    /* 0x18 */ op("NOP", "", 1, 4, 4, 0, 0, Access::None, false),
+    /* 0x19 */ op("DAD", "D", 1, 10, 10, 0, FLAG_CARRY, Access::None, true),
+    /* 0x1A */ op("LDAX", "D", 1, 7, 7, 0, 0, Access::MemoryRead, true),
+    /* 0x1B */ op("DCX", "D", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x1C */ op("INR", "E", 1, 5, 5, 0, FLAG_SIGN | FLAG_ZERO | FLAG_HALF_CARRY | FLAG_PARITY, Access::None, true),
+    /* 0x1D */ op("DCR", "E", 1, 5, 5, 0, FLAG_SIGN | FLAG_ZERO | FLAG_HALF_CARRY | FLAG_PARITY, Access::None, true),
+    /* 0x1E */ op("MVI", "E,d8", 2, 7, 7, 0, 0, Access::None, true),
+    /* 0x1F */ op("RAR", "", 1, 4, 4, FLAG_CARRY, FLAG_CARRY, Access::None, true),
+    /* 0x20 */ op("NOP", "", 1, 4, 4, 0, 0, Access::None, false),
+    /* 0x21 */ op("LXI", "H,d16", 3, 10, 10, 0, 0, Access::None, true),
+    /* 0x22 */ op("SHLD", "a16", 3, 16, 16, 0, 0, Access::MemoryWrite, true),
+    /* 0x23 */ op("INX", "H", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x24 */ op("INR", "H", 1, 5, 5, 0, FLAG_SIGN | FLAG_ZERO | FLAG_HALF_CARRY | FLAG_PARITY, Access::None, true),
+    /* 0x25 */ op("DCR", "H", 1, 5, 5, 0, FLAG_SIGN | FLAG_ZERO | FLAG_HALF_CARRY | FLAG_PARITY, Access::None, true),
+    /* 0x26 */ op("MVI", "H,d8", 2, 7, 7, 0, 0, Access::None, true),
+    /* 0x27 */ op("DAA", "", 1, 4, 4, FLAG_HALF_CARRY | FLAG_CARRY, FLAGS_ALL, Access::None, true),
+    /* 0x28 */ op("NOP", "", 1, 4, 4, 0, 0, Access::None, false),
+    /* 0x29 */ op("DAD", "H", 1, 10, 10, 0, FLAG_CARRY, Access::None, true),
+    /* 0x2A */ op("LHLD", "a16", 3, 16, 16, 0, 0, Access::MemoryRead, true),
+    /* 0x2B */ op("DCX", "H", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x2C */ op("INR", "L", 1, 5, 5, 0, FLAG_SIGN | FLAG_ZERO | FLAG_HALF_CARRY | FLAG_PARITY, Access::None, true),
+    /* 0x2D */ op("DCR", "L", 1, 5, 5, 0, FLAG_SIGN | FLAG_ZERO | FLAG_HALF_CARRY | FLAG_PARITY, Access::None, true),
+    /* 0x2E */ op("MVI", "L,d8", 2, 7, 7, 0, 0, Access::None, true),
+    /* 0x2F */ op("CMA", "", 1, 4, 4, 0, 0, Access::None, true),
+    /* 0x30 */ op("NOP", "", 1, 4, 4, 0, 0, Access::None, false),
+    /* 0x31 */ op("LXI", "SP,d16", 3, 10, 10, 0, 0, Access::None, true),
+    /* 0x32 */ op("STA", "a16", 3, 13, 13, 0, 0, Access::MemoryWrite, true),
+    /* 0x33 */ op("INX", "SP", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x34 */ op("INR", "M", 1, 10, 10, 0, FLAG_SIGN | FLAG_ZERO | FLAG_HALF_CARRY | FLAG_PARITY, Access::MemoryReadWrite, true),
+    /* 0x35 */ op("DCR", "M", 1, 10, 10, 0, FLAG_SIGN | FLAG_ZERO | FLAG_HALF_CARRY | FLAG_PARITY, Access::MemoryReadWrite, true),
+    /* 0x36 */ op("MVI", "M,d8", 2, 10, 10, 0, 0, Access::MemoryWrite, true),
+    /* 0x37 */ op("STC", "", 1, 4, 4, 0, FLAG_CARRY, Access::None, true),
+    /* 0x38 */ op("NOP", "", 1, 4, 4, 0, 0, Access::None, false),
+    /* 0x39 */ op("DAD", "SP", 1, 10, 10, 0, FLAG_CARRY, Access::None, true),
+    /* 0x3A */ op("LDA", "a16", 3, 13, 13, 0, 0, Access::MemoryRead, true),
+    /* 0x3B */ op("DCX", "SP", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x3C */ op("INR", "A", 1, 5, 5, 0, FLAG_SIGN | FLAG_ZERO | FLAG_HALF_CARRY | FLAG_PARITY, Access::None, true),
+    /* 0x3D */ op("DCR", "A", 1, 5, 5, 0, FLAG_SIGN | FLAG_ZERO | FLAG_HALF_CARRY | FLAG_PARITY, Access::None, true),
+    /* 0x3E */ op("MVI", "A,d8", 2, 7, 7, 0, 0, Access::None, true),
+    /* 0x3F */ op("CMC", "", 1, 4, 4, FLAG_CARRY, FLAG_CARRY, Access::None, true),
+    /* 0x40 */ op("MOV", "B,B", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x41 */ op("MOV", "B,C", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x42 */ op("MOV", "B,D", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x43 */ op("MOV", "B,E", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x44 */ op("MOV", "B,H", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x45 */ op("MOV", "B,L", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x46 */ op("MOV", "B,M", 1, 7, 7, 0, 0, Access::MemoryRead, true),
+    /* 0x47 */ op("MOV", "B,A", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x48 */ op("MOV", "C,B", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x49 */ op("MOV", "C,C", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x4A */ op("MOV", "C,D", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x4B */ op("MOV", "C,E", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x4C */ op("MOV", "C,H", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x4D */ op("MOV", "C,L", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x4E */ op("MOV", "C,M", 1, 7, 7, 0, 0, Access::MemoryRead, true),
+    /* 0x4F */ op("MOV", "C,A", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x50 */ op("MOV", "D,B", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x51 */ op("MOV", "D,C", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x52 */ op("MOV", "D,D", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x53 */ op("MOV", "D,E", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x54 */ op("MOV", "D,H", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x55 */ op("MOV", "D,L", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x56 */ op("MOV", "D,M", 1, 7, 7, 0, 0, Access::MemoryRead, true),
+    /* 0x57 */ op("MOV", "D,A", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x58 */ op("MOV", "E,B", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x59 */ op("MOV", "E,C", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x5A */ op("MOV", "E,D", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x5B */ op("MOV", "E,E", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x5C */ op("MOV", "E,H", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x5D */ op("MOV", "E,L", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x5E */ op("MOV", "E,M", 1, 7, 7, 0, 0, Access::MemoryRead, true),
+    /* 0x5F */ op("MOV", "E,A", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x60 */ op("MOV", "H,B", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x61 */ op("MOV", "H,C", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x62 */ op("MOV", "H,D", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x63 */ op("MOV", "H,E", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x64 */ op("MOV", "H,H", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x65 */ op("MOV", "H,L", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x66 */ op("MOV", "H,M", 1, 7, 7, 0, 0, Access::MemoryRead, true),
+    /* 0x67 */ op("MOV", "H,A", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x68 */ op("MOV", "L,B", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x69 */ op("MOV", "L,C", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x6A */ op("MOV", "L,D", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x6B */ op("MOV", "L,E", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x6C */ op("MOV", "L,H", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x6D */ op("MOV", "L,L", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x6E */ op("MOV", "L,M", 1, 7, 7, 0, 0, Access::MemoryRead, true),
+    /* 0x6F */ op("MOV", "L,A", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x70 */ op("MOV", "M,B", 1, 7, 7, 0, 0, Access::MemoryWrite, true),
+    /* 0x71 */ op("MOV", "M,C", 1, 7, 7, 0, 0, Access::MemoryWrite, true),
+    /* 0x72 */ op("MOV", "M,D", 1, 7, 7, 0, 0, Access::MemoryWrite, true),
+    /* 0x73 */ op("MOV", "M,E", 1, 7, 7, 0, 0, Access::MemoryWrite, true),
+    /* 0x74 */ op("MOV", "M,H", 1, 7, 7, 0, 0, Access::MemoryWrite, true),
+    /* 0x75 */ op("MOV", "M,L", 1, 7, 7, 0, 0, Access::MemoryWrite, true),
+    /* 0x76 */ op("HLT", "", 1, 7, 7, 0, 0, Access::None, true),
+    /* 0x77 */ op("MOV", "M,A", 1, 7, 7, 0, 0, Access::MemoryWrite, true),
+    /* 0x78 */ op("MOV", "A,B", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x79 */ op("MOV", "A,C", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x7A */ op("MOV", "A,D", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x7B */ op("MOV", "A,E", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x7C */ op("MOV", "A,H", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x7D */ op("MOV", "A,L", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x7E */ op("MOV", "A,M", 1, 7, 7, 0, 0, Access::MemoryRead, true),
+    /* 0x7F */ op("MOV", "A,A", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0x80 */ op("ADD", "B", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0x81 */ op("ADD", "C", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0x82 */ op("ADD", "D", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0x83 */ op("ADD", "E", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0x84 */ op("ADD", "H", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0x85 */ op("ADD", "L", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0x86 */ op("ADD", "M", 1, 7, 7, 0, FLAGS_ALL, Access::MemoryRead, true),
+    /* 0x87 */ op("ADD", "A", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0x88 */ op("ADC", "B", 1, 4, 4, FLAG_CARRY, FLAGS_ALL, Access::None, true),
+    /* 0x89 */ op("ADC", "C", 1, 4, 4, FLAG_CARRY, FLAGS_ALL, Access::None, true),
+    /* 0x8A */ op("ADC", "D", 1, 4, 4, FLAG_CARRY, FLAGS_ALL, Access::None, true),
+    /* 0x8B */ op("ADC", "E", 1, 4, 4, FLAG_CARRY, FLAGS_ALL, Access::None, true),
+    /* 0x8C */ op("ADC", "H", 1, 4, 4, FLAG_CARRY, FLAGS_ALL, Access::None, true),
+    /* 0x8D */ op("ADC", "L", 1, 4, 4, FLAG_CARRY, FLAGS_ALL, Access::None, true),
+    /* 0x8E */ op("ADC", "M", 1, 7, 7, FLAG_CARRY, FLAGS_ALL, Access::MemoryRead, true),
+    /* 0x8F */ op("ADC", "A", 1, 4, 4, FLAG_CARRY, FLAGS_ALL, Access::None, true),
+    /* 0x90 */ op("SUB", "B", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0x91 */ op("SUB", "C", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0x92 */ op("SUB", "D", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0x93 */ op("SUB", "E", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0x94 */ op("SUB", "H", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0x95 */ op("SUB", "L", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0x96 */ op("SUB", "M", 1, 7, 7, 0, FLAGS_ALL, Access::MemoryRead, true),
+    /* 0x97 */ op("SUB", "A", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0x98 */ op("SBB", "B", 1, 4, 4, FLAG_CARRY, FLAGS_ALL, Access::None, true),
+    /* 0x99 */ op("SBB", "C", 1, 4, 4, FLAG_CARRY, FLAGS_ALL, Access::None, true),
+    /* 0x9A */ op("SBB", "D", 1, 4, 4, FLAG_CARRY, FLAGS_ALL, Access::None, true),
+    /* 0x9B */ op("SBB", "E", 1, 4, 4, FLAG_CARRY, FLAGS_ALL, Access::None, true),
+    /* 0x9C */ op("SBB", "H", 1, 4, 4, FLAG_CARRY, FLAGS_ALL, Access::None, true),
+    /* 0x9D */ op("SBB", "L", 1, 4, 4, FLAG_CARRY, FLAGS_ALL, Access::None, true),
+    /* 0x9E */ op("SBB", "M", 1, 7, 7, FLAG_CARRY, FLAGS_ALL, Access::MemoryRead, true),
+    /* 0x9F */ op("SBB", "A", 1, 4, 4, FLAG_CARRY, FLAGS_ALL, Access::None, true),
+    /* 0xA0 */ op("ANA", "B", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xA1 */ op("ANA", "C", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xA2 */ op("ANA", "D", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xA3 */ op("ANA", "E", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xA4 */ op("ANA", "H", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xA5 */ op("ANA", "L", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xA6 */ op("ANA", "M", 1, 7, 7, 0, FLAGS_ALL, Access::MemoryRead, true),
+    /* 0xA7 */ op("ANA", "A", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xA8 */ op("XRA", "B", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xA9 */ op("XRA", "C", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xAA */ op("XRA", "D", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xAB */ op("XRA", "E", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xAC */ op("XRA", "H", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xAD */ op("XRA", "L", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xAE */ op("XRA", "M", 1, 7, 7, 0, FLAGS_ALL, Access::MemoryRead, true),
+    /* 0xAF */ op("XRA", "A", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xB0 */ op("ORA", "B", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xB1 */ op("ORA", "C", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xB2 */ op("ORA", "D", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xB3 */ op("ORA", "E", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xB4 */ op("ORA", "H", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xB5 */ op("ORA", "L", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xB6 */ op("ORA", "M", 1, 7, 7, 0, FLAGS_ALL, Access::MemoryRead, true),
+    /* 0xB7 */ op("ORA", "A", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xB8 */ op("CMP", "B", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xB9 */ op("CMP", "C", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xBA */ op("CMP", "D", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xBB */ op("CMP", "E", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xBC */ op("CMP", "H", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xBD */ op("CMP", "L", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xBE */ op("CMP", "M", 1, 7, 7, 0, FLAGS_ALL, Access::MemoryRead, true),
+    /* 0xBF */ op("CMP", "A", 1, 4, 4, 0, FLAGS_ALL, Access::None, true),
+    /* 0xC0 */ op("RNZ", "", 1, 5, 11, FLAG_ZERO, 0, Access::StackPop, true),
+    /* 0xC1 */ op("POP", "B", 1, 10, 10, 0, 0, Access::StackPop, true),
+    /* 0xC2 */ op("JNZ", "a16", 3, 10, 10, FLAG_ZERO, 0, Access::None, true),
+    /* 0xC3 */ op("JMP", "a16", 3, 10, 10, 0, 0, Access::None, true),
+    /* 0xC4 */ op("CNZ", "a16", 3, 11, 17, FLAG_ZERO, 0, Access::StackPush, true),
+    /* 0xC5 */ op("PUSH", "B", 1, 11, 11, 0, 0, Access::StackPush, true),
+    /* 0xC6 */ op("ADI", "d8", 2, 7, 7, 0, FLAGS_ALL, Access::None, true),
+    /* 0xC7 */ op("RST", "0", 1, 11, 11, 0, 0, Access::StackPush, true),
+    /* 0xC8 */ op("RZ", "", 1, 5, 11, FLAG_ZERO, 0, Access::StackPop, true),
+    /* 0xC9 */ op("RET", "", 1, 10, 10, 0, 0, Access::StackPop, true),
+    /* 0xCA */ op("JZ", "a16", 3, 10, 10, FLAG_ZERO, 0, Access::None, true),
+    /* 0xCB */ op("JMP", "a16", 3, 10, 10, 0, 0, Access::None, false),
+    /* 0xCC */ op("CZ", "a16", 3, 11, 17, FLAG_ZERO, 0, Access::StackPush, true),
+    /* 0xCD */ op("CALL", "a16", 3, 17, 17, 0, 0, Access::StackPush, true),
+    /* 0xCE */ op("ACI", "d8", 2, 7, 7, FLAG_CARRY, FLAGS_ALL, Access::None, true),
+    /* 0xCF */ op("RST", "1", 1, 11, 11, 0, 0, Access::StackPush, true),
+    /* 0xD0 */ op("RNC", "", 1, 5, 11, FLAG_CARRY, 0, Access::StackPop, true),
+    /* 0xD1 */ op("POP", "D", 1, 10, 10, 0, 0, Access::StackPop, true),
+    /* 0xD2 */ op("JNC", "a16", 3, 10, 10, FLAG_CARRY, 0, Access::None, true),
+    /* 0xD3 */ op("OUT", "d8", 2, 10, 10, 0, 0, Access::PortOut, true),
+    /* 0xD4 */ op("CNC", "a16", 3, 11, 17, FLAG_CARRY, 0, Access::StackPush, true),
+    /* 0xD5 */ op("PUSH", "D", 1, 11, 11, 0, 0, Access::StackPush, true),
+    /* 0xD6 */ op("SUI", "d8", 2, 7, 7, 0, FLAGS_ALL, Access::None, true),
+    /* 0xD7 */ op("RST", "2", 1, 11, 11, 0, 0, Access::StackPush, true),
+    /* 0xD8 */ op("RC", "", 1, 5, 11, FLAG_CARRY, 0, Access::StackPop, true),
+    /* 0xD9 */ op("RET", "", 1, 10, 10, 0, 0, Access::StackPop, false),
+    /* 0xDA */ op("JC", "a16", 3, 10, 10, FLAG_CARRY, 0, Access::None, true),
+    /* 0xDB */ op("IN", "d8", 2, 10, 10, 0, 0, Access::PortIn, true),
+    /* 0xDC */ op("CC", "a16", 3, 11, 17, FLAG_CARRY, 0, Access::StackPush, true),
+    /* 0xDD */ op("CALL", "a16", 3, 17, 17, 0, 0, Access::StackPush, false),
+    /* 0xDE */ op("SBI", "d8", 2, 7, 7, FLAG_CARRY, FLAGS_ALL, Access::None, true),
+    /* 0xDF */ op("RST", "3", 1, 11, 11, 0, 0, Access::StackPush, true),
+    /* 0xE0 */ op("RPO", "", 1, 5, 11, FLAG_PARITY, 0, Access::StackPop, true),
+    /* 0xE1 */ op("POP", "H", 1, 10, 10, 0, 0, Access::StackPop, true),
+    /* 0xE2 */ op("JPO", "a16", 3, 10, 10, FLAG_PARITY, 0, Access::None, true),
+    /* 0xE3 */ op("XTHL", "", 1, 18, 18, 0, 0, Access::MemoryReadWrite, true),
+    /* 0xE4 */ op("CPO", "a16", 3, 11, 17, FLAG_PARITY, 0, Access::StackPush, true),
+    /* 0xE5 */ op("PUSH", "H", 1, 11, 11, 0, 0, Access::StackPush, true),
+    /* 0xE6 */ op("ANI", "d8", 2, 7, 7, 0, FLAGS_ALL, Access::None, true),
+    /* 0xE7 */ op("RST", "4", 1, 11, 11, 0, 0, Access::StackPush, true),
+    /* 0xE8 */ op("RPE", "", 1, 5, 11, FLAG_PARITY, 0, Access::StackPop, true),
+    /* 0xE9 */ op("PCHL", "", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0xEA */ op("JPE", "a16", 3, 10, 10, FLAG_PARITY, 0, Access::None, true),
+    /* 0xEB */ op("XCHG", "", 1, 4, 4, 0, 0, Access::None, true),
+    /* 0xEC */ op("CPE", "a16", 3, 11, 17, FLAG_PARITY, 0, Access::StackPush, true),
+    /* 0xED */ op("CALL", "a16", 3, 17, 17, 0, 0, Access::StackPush, false),
+    /* 0xEE */ op("XRI", "d8", 2, 7, 7, 0, FLAGS_ALL, Access::None, true),
+    /* 0xEF */ op("RST", "5", 1, 11, 11, 0, 0, Access::StackPush, true),
+    /* 0xF0 */ op("RP", "", 1, 5, 11, FLAG_SIGN, 0, Access::StackPop, true),
+    /* 0xF1 */ op("POP", "PSW", 1, 10, 10, 0, FLAGS_ALL, Access::StackPop, true),
+    /* 0xF2 */ op("JP", "a16", 3, 10, 10, FLAG_SIGN, 0, Access::None, true),
+    /* 0xF3 */ op("DI", "", 1, 4, 4, 0, 0, Access::None, true),
+    /* 0xF4 */ op("CP", "a16", 3, 11, 17, FLAG_SIGN, 0, Access::StackPush, true),
+    /* 0xF5 */ op("PUSH", "PSW", 1, 11, 11, FLAGS_ALL, 0, Access::StackPush, true),
+    /* 0xF6 */ op("ORI", "d8", 2, 7, 7, 0, FLAGS_ALL, Access::None, true),
+    /* 0xF7 */ op("RST", "6", 1, 11, 11, 0, 0, Access::StackPush, true),
+    /* 0xF8 */ op("RM", "", 1, 5, 11, FLAG_SIGN, 0, Access::StackPop, true),
+    /* 0xF9 */ op("SPHL", "", 1, 5, 5, 0, 0, Access::None, true),
+    /* 0xFA */ op("JM", "a16", 3, 10, 10, FLAG_SIGN, 0, Access::None, true),
+    /* 0xFB */ op("EI", "", 1, 4, 4, 0, 0, Access::None, true),
+    /* 0xFC */ op("CM", "a16", 3, 11, 17, FLAG_SIGN, 0, Access::StackPush, true),
+    /* 0xFD */ op("CALL", "a16", 3, 17, 17, 0, 0, Access::StackPush, false),
+    /* 0xFE */ op("CPI", "d8", 2, 7, 7, 0, FLAGS_ALL, Access::None, true),
+    /* 0xFF */ op("RST", "7", 1, 11, 11, 0, 0, Access::StackPush, true),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Model
+{
+    Intel8080,
+    Intel8085
+}
+
+impl Model
+{
+    pub fn opcodes(self) -> &'static [OpcodeInfo; 256] {
+        match self {
+            Self::Intel8080 => &OPCODES,
+            Self::Intel8085 => &OPCODES_8085
+        }
+    }
+
+    pub fn decode_table(self) -> &'static [Instruction8080; 256] {
+        match self {
+            Self::Intel8080 => Instruction8080::decode_table(),
+            Self::Intel8085 => Instruction8080::decode_table_8085()
+        }
+    }
+
+    // decode_table() with the undocumented opcodes left unknown, so they fault instead of running as aliases.
+    pub fn documented_decode_table(self) -> &'static [Instruction8080; 256] {
+        static TABLE_8080: OnceLock<[Instruction8080; 256]> = OnceLock::new();
+        static TABLE_8085: OnceLock<[Instruction8080; 256]> = OnceLock::new();
+        let table = match self {
+            Self::Intel8080 => &TABLE_8080,
+            Self::Intel8085 => &TABLE_8085
+        };
+        table.get_or_init(|| std::array::from_fn(|opcode| {
+            if self.opcodes()[opcode].documented { self.decode_table()[opcode] } else { Instruction8080::new(opcode as u8) }
+        }))
+    }
+
+    pub fn decode(self, bytes: &[u8]) -> Result<(Instruction8080, usize), DecodeError> {
+        match self {
+            Self::Intel8080 => Instruction8080::decode(bytes),
+            Self::Intel8085 => Instruction8080::decode_8085(bytes)
+        }
+    }
+}
+
+// Reference: Intel 8085AH data sheet and the undocumented instructions described by Dutta-Roy.
+// The 8085 runs most register operations in 4 cycles instead of 5, spends 6 on 16 bit increments and
+// register pair moves, and reads the operand of a conditional jump only when it is taken.
+pub const OPCODES_8085: [OpcodeInfo; 256] = opcodes_8085();
+
+const fn opcodes_8085() -> [OpcodeInfo; 256] {
+    let mut table = OPCODES;
+    let mut index = 0;
+    while index < 256 {
+        let opcode = index as u8;
+        let (destination, source) = (opcode >> 3 & 7, opcode & 7);
+        let timing = match opcode {
+            0x76 => Some((5, 5)),
+            0x40..=0x7F if destination != 6 && source != 6 => Some((4, 4)),
+            _ if opcode & 0xC6 == 0x04 && destination != 6 => Some((4, 4)),  // INR / DCR
+            _ if opcode & 0xC7 == 0x03 => Some((6, 6)),                       // INX / DCX
+            0xE9 | 0xF9 => Some((6, 6)),                                      // PCHL / SPHL
+            0xE3 => Some((16, 16)),                                           // XTHL
+            0xCD => Some((18, 18)),                                           // CALL
+            _ if opcode & 0xC7 == 0xC2 => Some((7, 10)),                      // Jcc
+            _ if opcode & 0xC7 == 0xC4 => Some((9, 18)),                      // Ccc
+            _ if opcode & 0xC7 == 0xC0 => Some((6, 12)),                      // Rcc
+            _ if opcode & 0xC7 == 0xC7 => Some((12, 12)),                     // RST
+            _ if opcode & 0xCF == 0xC5 => Some((12, 12)),                     // PUSH
+            _ => None
+        };
+        if let Some((cycles, cycles_taken)) = timing {
+            table[index].cycles = cycles;
+            table[index].cycles_taken = cycles_taken;
+        }
+        index += 1;
+    }
+
+    const ARITHMETIC: u8 = FLAGS_ALL | FLAG_OVERFLOW | FLAG_UNDERFLOW;
+    table[0x08] = op("DSUB", "", 1, 10, 10, FLAG_CARRY, ARITHMETIC, Access::None, false);
+    table[0x10] = op("ARHL", "", 1, 7, 7, 0, FLAG_CARRY, Access::None, false);
+    table[0x18] = op("RDEL", "", 1, 10, 10, FLAG_CARRY, FLAG_CARRY | FLAG_OVERFLOW, Access::None, false);
+    table[0x20] = op("RIM", "", 1, 4, 4, 0, 0, Access::None, true);
+    table[0x28] = op("LDHI", "d8", 2, 10, 10, 0, 0, Access::None, false);
+    table[0x30] = op("SIM", "", 1, 4, 4, 0, 0, Access::None, true);
+    table[0x38] = op("LDSI", "d8", 2, 10, 10, 0, 0, Access::None, false);
+    table[0xCB] = op("RSTV", "", 1, 6, 12, FLAG_OVERFLOW, 0, Access::StackPush, false);
+    table[0xD9] = op("SHLX", "", 1, 10, 10, 0, 0, Access::MemoryWrite, false);
+    table[0xDD] = op("JNK", "a16", 3, 7, 10, FLAG_UNDERFLOW, 0, Access::None, false);
+    table[0xED] = op("LHLX", "", 1, 10, 10, 0, 0, Access::MemoryRead, false);
+    table[0xFD] = op("JK", "a16", 3, 7, 10, FLAG_UNDERFLOW, 0, Access::None, false);
+    table
+}
+
+// A single memory or port access an instruction is going to make.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BusAccess
+{
+    Memory { address: u16, size: u16, write: bool },
+    Port { port: u8, write: bool }
+}
+
+impl Instruction8080
+{
+    // The opcode table entry, from OPCODES_8085 for the instructions only the 8085 has.
+    pub fn info(&self) -> &'static OpcodeInfo {
+        let table = if self.is_8085_only() { &OPCODES_8085 } else { &OPCODES };
+        &table[self.opcode as usize]
+    }
+
+    // Works out what this instruction will touch when run with these registers, from the opcode table.
+    // Interrupts are not taken into account, an accepted interrupt pushes PC instead.
+    pub fn bus_accesses(&self, registers: &Registers) -> Vec<BusAccess> {
+        let info = self.info();
+        let (address, size) = self.memory_operand(registers);
+        let port = match self.target {
+            InstructionType::Immediate8 { value } => value,
+            _ => 0
+        };
+
+        match info.access {
+            Access::None => Vec::new(),
+            Access::MemoryRead => vec![BusAccess::Memory { address, size, write: false }],
+            Access::MemoryWrite => vec![BusAccess::Memory { address, size, write: true }],
+            Access::MemoryReadWrite => vec![
+                BusAccess::Memory { address, size, write: false },
+                BusAccess::Memory { address, size, write: true }
+            ],
+            Access::StackPush if self.taken(registers) => vec![BusAccess::Memory { address, size, write: true }],
+            Access::StackPop if self.taken(registers) => vec![BusAccess::Memory { address, size, write: false }],
+            Access::StackPush | Access::StackPop => Vec::new(),
+            Access::PortIn => vec![BusAccess::Port { port, write: false }],
+            Access::PortOut => vec![BusAccess::Port { port, write: true }]
+        }
+    }
+
+    // The memory this instruction writes as (address, size), without allocating like bus_accesses().
+    pub fn memory_write(&self, registers: &Registers) -> Option<(u16, u16)> {
+        match self.info().access {
+            Access::MemoryWrite | Access::MemoryReadWrite => Some(self.memory_operand(registers)),
+            Access::StackPush if self.taken(registers) => Some(self.memory_operand(registers)),
+            _ => None
+        }
+    }
+
+    fn memory_operand(&self, registers: &Registers) -> (u16, u16) {
+        match self.info().access {
+            Access::StackPush => (registers.sp.wrapping_sub(2), 2),
+            Access::StackPop => (registers.sp, 2),
+            // XTHL.
+            _ if self.opcode == 0xE3 => (registers.sp, 2),
+            _ => {
+                let address = match self.target {
+                    InstructionType::Register16 { register } => registers.get_16(&register),
+                    InstructionType::Immediate16 { value } => value,
+                    _ => registers.get_16(&Register16::HL)
+                };
+                // SHLD and LHLD (SHLX and LHLX on the 8085) move a word.
+                let word = matches!(self.action, InstructionAction::StoreReg16ToMemory { .. } | InstructionAction::LoadReg16FromMemory { .. });
+                (address, if word { 2 } else { 1 })
+            }
+        }
+    }
+
+    // Conditional calls and returns only touch the stack when their condition holds.
+    fn taken(&self, registers: &Registers) -> bool {
+        match self.action {
+            InstructionAction::Call { condition } | InstructionAction::Return { condition } => registers.check_condition(&condition),
+            InstructionAction::RestartOnOverflow => registers.f & FLAG_OVERFLOW != 0,
+            _ => true
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use crate::cpu::{CpuError, Registers, CPU8080};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StepInfo
+{
+    // Address the instruction was fetched from.
+    pub pc: u16,
+    pub opcode: u8,
+    // Cycles spent executing this step.
+    pub cycles: u32
+}
+
+// Why run_for_cycles() / run_until() returned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunExit
+{
+    // The cycle budget was used up, the last instruction may have gone a few cycles past it.
+    CyclesElapsed,
+    // The run_until() predicate held after an instruction.
+    Condition,
+    // Halted with no interrupt to wake it, the host has to raise one. A budgeted run idles
+    // through the rest of its budget first.
+    Halted,
+    Stopped
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RunInfo
+{
+    // Cycles actually executed.
+    pub cycles: u64,
+    pub exit: RunExit
+}
+
+// A halted CPU idles in steps of this many cycles until an interrupt wakes it.
+pub(crate) const HALT_CYCLES: u32 = 4;
+
+// Cycles a halted CPU with `remaining` cycles of budget left spends idling, in whole halt steps.
+pub(crate) fn halt_fast_forward(remaining: u64) -> u64 {
+    remaining.div_ceil(HALT_CYCLES as u64) * HALT_CYCLES as u64
+}
+
+// How a run that used up its budget ended, a CPU still halted at that point reports it.
+pub(crate) fn budget_exit(registers: &Registers) -> RunExit {
+    if registers.halting { RunExit::Halted } else { RunExit::CyclesElapsed }
+}
+
+// Runs a CPU one step() at a time, the default for engines without a faster loop of their own.
+// Without a budget, a halted CPU nothing can wake returns right away instead of idling forever.
+pub(crate) fn run_stepping<C: CPU8080 + ?Sized>(cpu: &mut C, cycles: Option<u64>, done: &mut dyn FnMut(&Registers) -> bool) -> Result<RunInfo, CpuError> {
+    let start = cpu.get_executed_cycles();
+    loop {
+        let spent = cpu.get_executed_cycles().wrapping_sub(start);
+        let exit = if !cpu.is_running() {
+            RunExit::Stopped
+        } else if cycles.is_some_and(|cycles| spent >= cycles) {
+            budget_exit(cpu.registers())
+        } else if cycles.is_none() && cpu.registers().halting && !interrupt_pending(cpu) {
+            RunExit::Halted
+        } else {
+            cpu.step()?;
+            if !done(cpu.registers()) {
+                continue;
+            }
+            RunExit::Condition
+        };
+        return Ok(RunInfo { cycles: cpu.get_executed_cycles().wrapping_sub(start), exit });
+    }
+}
+
+fn interrupt_pending<C: CPU8080 + ?Sized>(cpu: &C) -> bool {
+    let bus = cpu.get_bus();
+    let bus = bus.read().unwrap_or_else(|poison| poison.into_inner());
+    cpu.registers().accepts_interrupt() && bus.has_interrupt()
+}
@@ -0,0 +1,61 @@
+use std::{ops::DerefMut, sync::{Arc, RwLock, RwLockWriteGuard}};
+
+use crate::Bus8080;
+
+// The bus as shared with the rest of the program, what CPU8080::set_bus() takes.
+pub type SharedBus = Arc<RwLock<Box<dyn Bus8080>>>;
+
+// Where an interpreter keeps its bus. A shared bus is locked once per step and called through a vtable,
+// a concrete bus owned by the interpreter is called directly and can be inlined.
+pub trait BusStorage: Send + Sync + 'static
+{
+    type Bus: Bus8080 + ?Sized;
+    type Guard<'a>: DerefMut<Target = Self::Bus> where Self: 'a;
+
+    // None when the bus can not be used, like when a device panicked while holding its lock.
+    fn lock(&mut self) -> Option<Self::Guard<'_>>;
+    // Access to the bus even if it is faulty, for inspecting or restoring it.
+    fn lock_anyway(&mut self) -> Self::Guard<'_>;
+
+    fn is_poisoned(&self) -> bool {
+        false
+    }
+
+    fn clear_poison(&self) {}
+}
+
+impl BusStorage for SharedBus
+{
+    type Bus = Box<dyn Bus8080>;
+    type Guard<'a> = RwLockWriteGuard<'a, Box<dyn Bus8080>>;
+
+    fn lock(&mut self) -> Option<Self::Guard<'_>> {
+        self.write().ok()
+    }
+
+    fn lock_anyway(&mut self) -> Self::Guard<'_> {
+        self.write().unwrap_or_else(|poison| poison.into_inner())
+    }
+
+    fn is_poisoned(&self) -> bool {
+        RwLock::is_poisoned(self)
+    }
+
+    fn clear_poison(&self) {
+        RwLock::clear_poison(self)
+    }
+}
+
+impl<B: Bus8080> BusStorage for B
+{
+    type Bus = B;
+    type Guard<'a> = &'a mut B;
+
+    fn lock(&mut self) -> Option<Self::Guard<'_>> {
+        Some(self)
+    }
+
+    fn lock_anyway(&mut self) -> Self::Guard<'_> {
+        self
+    }
+}
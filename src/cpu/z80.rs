@@ -0,0 +1,805 @@
+// The Zilog Z80. The unprefixed opcodes go through the 8080 decode table (with DJNZ, JR, EX AF,AF' and EXX in
+// place of the 8080 aliases) and the 8080 execution paths, only the flags are done the Z80 way. On top of that
+// come the alternate registers, IX / IY with displacements, the CB / DD / ED / FD prefixed instructions,
+// interrupt modes 0 to 2 and NMI. Ports are addressed by C (or the immediate), the bus sees B in the registers.
+// Like BlockCache8080, faults always stop it and there is no fault policy or history.
+
+use std::sync::{Arc, RwLock};
+
+use crate::{Bus8080, ErrorBus};
+use crate::cpu::{z80_alu, BusStorage, CPU8080, CpuError, Instruction8080, InstructionAction, Model, Register16, Registers, RunExit, RunInfo, SharedBus, StepInfo, FLAG_CARRY, FLAG_HALF_CARRY, FLAG_PARITY, FLAG_SIGN, FLAG_ZERO};
+use crate::cpu::interpreter::{execute_decoded, with_operands};
+use crate::cpu::step::{budget_exit, halt_fast_forward, HALT_CYCLES};
+
+// N, set after a subtraction for DAA, and the undocumented copies of result bits 3 and 5.
+// P/V is FLAG_PARITY, it holds the overflow after arithmetic.
+pub const FLAG_SUBTRACT: u8 = 1 << 1;
+pub const FLAG_X3: u8 = 1 << 3;
+pub const FLAG_X5: u8 = 1 << 5;
+
+// Bit of Bus8080::interrupt_lines(), NMI is taken on its rising edge.
+pub const LINE_NMI: u8 = 1 << 4;
+
+const NMI_VECTOR: u16 = 0x0066;
+const IM1_VECTOR: u16 = 0x0038;
+
+// T states of the unprefixed opcodes, conditional instructions when not taken.
+const CYCLES: [u8; 256] = [
+     4, 10,  7,  6,  4,  4,  7,  4,  4, 11,  7,  6,  4,  4,  7,  4,
+     8, 10,  7,  6,  4,  4,  7,  4, 12, 11,  7,  6,  4,  4,  7,  4,
+     7, 10, 16,  6,  4,  4,  7,  4,  7, 11, 16,  6,  4,  4,  7,  4,
+     7, 10, 13,  6, 11, 11, 10,  4,  7, 11, 13,  6,  4,  4,  7,  4,
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,
+     7,  7,  7,  7,  7,  7,  4,  7,  4,  4,  4,  4,  4,  4,  7,  4,
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,
+     5, 10, 10, 10, 10, 11,  7, 11,  5, 10, 10,  4, 10, 17,  7, 11,
+     5, 10, 10, 11, 10, 11,  7, 11,  5,  4, 10, 11, 10,  4,  7, 11,
+     5, 10, 10, 19, 10, 11,  7, 11,  5,  4, 10,  4, 10,  4,  7, 11,
+     5, 10, 10,  4, 10, 11,  7, 11,  5,  6, 10,  4, 10,  4,  7, 11,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegistersZ80
+{
+    // The alternate AF, BC, DE and HL, swapped in by EX AF,AF' and EXX.
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16,
+    pub ix: u16,
+    pub iy: u16,
+    // The IM 2 vector table page and the refresh counter, R counts opcode fetches in its low 7 bits.
+    pub i: u8,
+    pub r: u8,
+    // IFF1 is Registers::interrupts, IFF2 keeps it while an NMI is served.
+    pub iff2: bool,
+    pub interrupt_mode: u8,
+    // The NMI line when it was last sampled, and a request latched on its rising edge.
+    pub nmi_line: bool,
+    pub nmi: bool
+}
+
+impl RegistersZ80
+{
+    pub fn new() -> Self {
+        Self {
+            af: 0x0000, bc: 0x0000, de: 0x0000, hl: 0x0000,
+            ix: 0x0000, iy: 0x0000,
+            i: 0x00, r: 0x00,
+            iff2: false, interrupt_mode: 0,
+            nmi_line: false, nmi: false
+        }
+    }
+}
+
+pub struct InterpreterZ80<B: BusStorage = SharedBus>
+{
+    cycles: u64,
+    registers: Registers,
+    bus: B
+}
+
+impl InterpreterZ80
+{
+    pub fn new() -> Self {
+        Self::with_bus(Arc::new(RwLock::new(Box::new(ErrorBus::new()))))
+    }
+}
+
+impl<B: BusStorage> InterpreterZ80<B>
+{
+    pub fn with_bus(bus: B) -> Self {
+        Self {
+            cycles: 0x00,
+            registers: Registers::new(),
+            bus
+        }
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+
+    pub fn get_executed_cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn set_executed_cycles(&mut self, cycles: u64) {
+        self.cycles = cycles;
+    }
+
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut Registers {
+        &mut self.registers
+    }
+
+    pub fn force_jump(&mut self, a: u16) {
+        self.registers.pc = a;
+    }
+
+    pub fn stop(&mut self) {
+        self.registers.running = false;
+    }
+
+    pub fn reset(&mut self) {
+        self.registers.reset();
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.registers.running
+    }
+
+    // Every instruction takes at least a cycle, so a budget of one is a single step.
+    pub fn step(&mut self) -> Result<StepInfo, CpuError> {
+        self.execute(Some(1), |_| false).map(|(info, _)| info)
+    }
+
+    pub fn run(&mut self) -> Result<(), CpuError> {
+        while self.registers.running {
+            self.execute(None, |_| false)?;
+        }
+        Ok(())
+    }
+
+    // Same contract as Interpreter8080::run_for_cycles().
+    pub fn run_for_cycles(&mut self, cycles: u64) -> Result<RunInfo, CpuError> {
+        let start = self.cycles;
+        let (_, exit) = self.execute(Some(cycles), |_| false)?;
+        Ok(RunInfo { cycles: self.cycles.wrapping_sub(start), exit })
+    }
+
+    // Same contract as Interpreter8080::run_until().
+    pub fn run_until(&mut self, predicate: impl FnMut(&Registers) -> bool) -> Result<RunInfo, CpuError> {
+        let start = self.cycles;
+        let (_, exit) = self.execute(None, predicate)?;
+        Ok(RunInfo { cycles: self.cycles.wrapping_sub(start), exit })
+    }
+
+    // Runs until `cycles` cycles have passed, `done` holds after an instruction, the CPU stops
+    // or halts with nothing to wake it (idling through the budget if there is one).
+    fn execute(&mut self, cycles: Option<u64>, done: impl FnMut(&Registers) -> bool) -> Result<(StepInfo, RunExit), CpuError> {
+        let result = self.execute_locked(cycles, done);
+        if result.is_err() {
+            self.registers.running = false;
+        }
+        result
+    }
+
+    fn execute_locked(&mut self, cycles: Option<u64>, mut done: impl FnMut(&Registers) -> bool) -> Result<(StepInfo, RunExit), CpuError> {
+        let start = self.cycles;
+        let pc = self.registers.pc;
+        let mut bus = self.bus.lock().ok_or(CpuError::BusFault { pc })?;
+        let mut last = StepInfo { pc, opcode: 0x76, cycles: 0 };
+        let budget = cycles.unwrap_or(u64::MAX);
+
+        let exit = loop {
+            if !self.registers.running {
+                break RunExit::Stopped;
+            }
+            let spent = self.cycles.wrapping_sub(start);
+            if spent >= budget {
+                break budget_exit(&self.registers);
+            }
+            if self.registers.halting && !interrupt_pending(&self.registers, &*bus) {
+                if cycles.is_some() {
+                    let idle = halt_fast_forward(budget - spent);
+                    self.cycles = self.cycles.wrapping_add(idle);
+                    refresh(&mut self.registers, (idle / HALT_CYCLES as u64) as u8);
+                    last = StepInfo { pc, opcode: 0x76, cycles: u32::try_from(idle).unwrap_or(u32::MAX) };
+                }
+                break RunExit::Halted;
+            }
+
+            last = execute_instruction(&mut self.registers, &mut *bus)?;
+            self.cycles = self.cycles.wrapping_add(last.cycles as u64);
+            if done(&self.registers) {
+                break RunExit::Condition;
+            }
+        };
+        Ok((last, exit))
+    }
+}
+
+// Inherent methods take precedence, so these forward to the ones above.
+impl CPU8080 for InterpreterZ80
+{
+    fn get_executed_cycles(&mut self) -> u64 {
+        Self::get_executed_cycles(self)
+    }
+
+    fn set_executed_cycles(&mut self, cycles: u64) {
+        Self::set_executed_cycles(self, cycles)
+    }
+
+    fn registers(&self) -> &Registers {
+        Self::registers(self)
+    }
+
+    fn registers_mut(&mut self) -> &mut Registers {
+        Self::registers_mut(self)
+    }
+
+    fn force_jump(&mut self, a: u16) {
+        Self::force_jump(self, a)
+    }
+
+    fn reset(&mut self) {
+        Self::reset(self)
+    }
+
+    fn set_bus(&mut self, b: Arc<RwLock<Box<dyn Bus8080>>>) {
+        self.bus = b;
+    }
+
+    fn get_bus(&self) -> Arc<RwLock<Box<dyn Bus8080>>> {
+        Arc::clone(&self.bus)
+    }
+
+    fn stop(&mut self) {
+        Self::stop(self)
+    }
+
+    fn is_running(&mut self) -> bool {
+        Self::is_running(self)
+    }
+
+    fn step(&mut self) -> Result<StepInfo, CpuError> {
+        Self::step(self)
+    }
+
+    fn run(&mut self) -> Result<(), CpuError> {
+        Self::run(self)
+    }
+
+    fn run_for_cycles(&mut self, cycles: u64) -> Result<RunInfo, CpuError> {
+        Self::run_for_cycles(self, cycles)
+    }
+
+    fn run_until(&mut self, predicate: &mut dyn FnMut(&Registers) -> bool) -> Result<RunInfo, CpuError> {
+        Self::run_until(self, predicate)
+    }
+}
+
+// Whether an NMI or an interrupt would be taken before the next instruction.
+pub(crate) fn interrupt_pending<T: Bus8080 + ?Sized>(registers: &Registers, bus: &T) -> bool {
+    let rising = bus.interrupt_lines() & LINE_NMI != 0 && !registers.z80.nmi_line;
+    registers.z80.nmi || rising || (registers.accepts_interrupt() && bus.has_interrupt())
+}
+
+// Runs one instruction (or accepts an interrupt) on an already locked bus.
+// Interrupts are reported as the CALL or RST they amount to.
+pub(crate) fn execute_instruction<T: Bus8080 + ?Sized>(registers: &mut Registers, bus: &mut T) -> Result<StepInfo, CpuError> {
+    let pc = registers.pc;
+    let line = bus.interrupt_lines() & LINE_NMI != 0;
+    registers.z80.nmi |= line && !registers.z80.nmi_line;
+    registers.z80.nmi_line = line;
+
+    if registers.z80.nmi {
+        registers.z80.nmi = false;
+        registers.interrupts = false;
+        registers.interrupt_delay = false;
+        registers.halting = false;
+        refresh(registers, 1);
+        call(registers, bus, NMI_VECTOR);
+        return Ok(StepInfo { pc, opcode: 0xCD, cycles: 11 });
+    }
+    if registers.accepts_interrupt() && bus.has_interrupt() {
+        return accept_interrupt(registers, bus);
+    }
+    if registers.halting {
+        refresh(registers, 1);
+        return Ok(StepInfo { pc, opcode: 0x76, cycles: HALT_CYCLES });
+    }
+
+    // EI waits for the instruction after it, whatever its prefix.
+    let opcode = bus.read_b(pc);
+    refresh(registers, 1);
+    registers.interrupt_delay = false;
+    let cycles = match opcode {
+        0xCB => {
+            refresh(registers, 1);
+            let opcode = bus.read_b(pc.wrapping_add(1));
+            registers.pc = pc.wrapping_add(2);
+            let address = registers.get_16(&Register16::HL);
+            execute_bits(registers, bus, opcode, address, opcode & 7 == 6, false)
+        }
+        0xED => {
+            refresh(registers, 1);
+            execute_extended(registers, bus, pc)
+        }
+        0xDD | 0xFD => execute_indexed(registers, bus, opcode == 0xDD, pc)?,
+        _ => execute_main(registers, bus, opcode, pc.wrapping_add(1), pc)?
+    };
+    Ok(StepInfo { pc, opcode, cycles })
+}
+
+// IM 0 runs the instruction the device supplies like the 8080, IM 1 is RST 38H and IM 2 calls through
+// the table entry at I * 256 + the byte the device supplies.
+fn accept_interrupt<T: Bus8080 + ?Sized>(registers: &mut Registers, bus: &mut T) -> Result<StepInfo, CpuError> {
+    let pc = registers.pc;
+    registers.interrupts = false;
+    registers.z80.iff2 = false;
+    registers.halting = false;
+    refresh(registers, 1);
+
+    match registers.z80.interrupt_mode {
+        0 => {
+            let opcode = bus.acknowledge_interrupt(0);
+            let instruction = with_operands(Instruction8080::decode_table_z80()[opcode as usize], |index| bus.acknowledge_interrupt(index));
+            let cycles = execute_decoded_z80(registers, bus, instruction, pc)?;
+            Ok(StepInfo { pc, opcode, cycles: cycles + 2 })
+        }
+        1 => {
+            bus.acknowledge_interrupt(0);
+            call(registers, bus, IM1_VECTOR);
+            Ok(StepInfo { pc, opcode: 0xFF, cycles: 13 })
+        }
+        _ => {
+            let vector = u16::from_le_bytes([bus.acknowledge_interrupt(0), registers.z80.i]);
+            let address = bus.read_w(vector);
+            call(registers, bus, address);
+            Ok(StepInfo { pc, opcode: 0xCD, cycles: 19 })
+        }
+    }
+}
+
+// Counts opcode fetches in the low 7 bits of R, bit 7 only changes through LD R,A.
+fn refresh(registers: &mut Registers, fetches: u8) {
+    let r = registers.z80.r;
+    registers.z80.r = r & 0x80 | r.wrapping_add(fetches) & 0x7F;
+}
+
+fn call<T: Bus8080 + ?Sized>(registers: &mut Registers, bus: &mut T, address: u16) {
+    registers.sp = registers.sp.wrapping_sub(2);
+    bus.write_w(registers.sp, registers.pc);
+    registers.pc = address;
+}
+
+// The register fields of the CB and ED opcodes, 6 is (HL) and left to the callers.
+fn get_register(registers: &Registers, code: u8) -> u8 {
+    match code & 7 {
+        0 => registers.b, 1 => registers.c, 2 => registers.d, 3 => registers.e,
+        4 => registers.h, 5 => registers.l, _ => registers.a
+    }
+}
+
+fn set_register(registers: &mut Registers, code: u8, value: u8) {
+    match code & 7 {
+        0 => registers.b = value, 1 => registers.c = value, 2 => registers.d = value, 3 => registers.e = value,
+        4 => registers.h = value, 5 => registers.l = value, 6 => { }, _ => registers.a = value
+    }
+}
+
+// Runs an unprefixed opcode, its operand bytes start at `operands`. Returns the T states it took.
+fn execute_main<T: Bus8080 + ?Sized>(registers: &mut Registers, bus: &mut T, opcode: u8, operands: u16, pc: u16) -> Result<u32, CpuError> {
+    let instruction = with_operands(Instruction8080::decode_table_z80()[opcode as usize], |index| bus.read_b(operands.wrapping_add(index as u16 - 1)));
+    registers.pc = operands.wrapping_add(instruction.length as u16 - 1);
+    execute_decoded_z80(registers, bus, instruction, pc)
+}
+
+// The Z80 side of an unprefixed instruction: whatever sets flags differently or is new on the Z80 is done
+// here, the rest goes through the 8080 execution path.
+fn execute_decoded_z80<T: Bus8080 + ?Sized>(registers: &mut Registers, bus: &mut T, instruction: Instruction8080, pc: u16) -> Result<u32, CpuError> {
+    let invalid_operand = CpuError::InvalidOperand { pc, opcode: instruction.opcode };
+    let operand = |registers: &Registers, bus: &mut T| instruction.target.get_value_as_u8(bus, registers).ok_or(invalid_operand);
+    let carry = registers.f & FLAG_CARRY != 0;
+
+    // Extra T states of conditional calls, returns and relative jumps that are taken.
+    let mut taken = 0;
+    match instruction.action {
+        InstructionAction::AddReg { carry: with_carry, .. } => {
+            let output = z80_alu::add(registers.a, operand(registers, bus)?, with_carry && carry);
+            (registers.a, registers.f) = (output.result, output.flags);
+        }
+
+        InstructionAction::SubReg { borrow, .. } => {
+            let output = z80_alu::sub(registers.a, operand(registers, bus)?, borrow && carry);
+            (registers.a, registers.f) = (output.result, output.flags);
+        }
+
+        InstructionAction::CompareReg { .. } => {
+            registers.f = z80_alu::compare(registers.a, operand(registers, bus)?).flags;
+        }
+
+        InstructionAction::AndReg { .. } => {
+            let output = z80_alu::and(registers.a, operand(registers, bus)?);
+            (registers.a, registers.f) = (output.result, output.flags);
+        }
+
+        InstructionAction::OrReg { .. } => {
+            let output = z80_alu::or(registers.a, operand(registers, bus)?);
+            (registers.a, registers.f) = (output.result, output.flags);
+        }
+
+        InstructionAction::XorReg { .. } => {
+            let output = z80_alu::xor(registers.a, operand(registers, bus)?);
+            (registers.a, registers.f) = (output.result, output.flags);
+        }
+
+        InstructionAction::IncrementReg { register } | InstructionAction::DecrementReg { register } => {
+            let value = registers.get_8(bus, &register);
+            let output = if matches!(instruction.action, InstructionAction::IncrementReg { .. }) {
+                z80_alu::increment(value, registers.f)
+            } else {
+                z80_alu::decrement(value, registers.f)
+            };
+            registers.f = output.flags;
+            registers.set_8(&register, bus, output.result);
+        }
+
+        InstructionAction::DAAReg { .. } => {
+            let output = z80_alu::daa(registers.a, registers.f);
+            (registers.a, registers.f) = (output.result, output.flags);
+        }
+
+        InstructionAction::ComplementReg { .. } => {
+            let output = z80_alu::complement(registers.a, registers.f);
+            (registers.a, registers.f) = (output.result, output.flags);
+        }
+
+        InstructionAction::RotateReg { right, arithmetic, .. } => {
+            let output = z80_alu::rotate_a(registers.a, right, arithmetic, registers.f);
+            (registers.a, registers.f) = (output.result, output.flags);
+        }
+
+        InstructionAction::SetCarry { .. } => registers.f = z80_alu::set_carry(registers.a, registers.f, false),
+        InstructionAction::ComplementCarry => registers.f = z80_alu::set_carry(registers.a, registers.f, true),
+
+        InstructionAction::Add16 { register } => {
+            let value = instruction.target.get_value_as_u16(registers).ok_or(invalid_operand)?;
+            let (result, flags) = z80_alu::add16(registers.get_16(&register), value, registers.f);
+            registers.f = flags;
+            registers.set_16(&register, result);
+        }
+
+        // All eight bits of F are kept.
+        InstructionAction::Pop16 { register: Register16::PSW } => {
+            [registers.f, registers.a] = bus.read_w(registers.sp).to_le_bytes();
+            registers.sp = registers.sp.wrapping_add(2);
+        }
+
+        InstructionAction::SetInterrupts { enabled } => {
+            registers.interrupts = enabled;
+            registers.interrupt_delay = enabled;
+            registers.z80.iff2 = enabled;
+        }
+
+        InstructionAction::ExchangeAF => {
+            let af = registers.get_16(&Register16::PSW);
+            [registers.f, registers.a] = registers.z80.af.to_le_bytes();
+            registers.z80.af = af;
+        }
+
+        InstructionAction::ExchangeAlternates => {
+            for (register, alternate) in [(Register16::BC, registers.z80.bc), (Register16::DE, registers.z80.de), (Register16::HL, registers.z80.hl)] {
+                let value = registers.get_16(&register);
+                registers.set_16(&register, alternate);
+                match register {
+                    Register16::BC => registers.z80.bc = value,
+                    Register16::DE => registers.z80.de = value,
+                    _ => registers.z80.hl = value
+                }
+            }
+        }
+
+        InstructionAction::JumpRelative { condition } => {
+            let displacement = operand(registers, bus)? as i8;
+            if registers.check_condition(&condition) {
+                registers.pc = registers.pc.wrapping_add(displacement as u16);
+                taken = if condition == crate::cpu::Condition::None { 0 } else { 5 };
+            }
+        }
+
+        InstructionAction::DecrementJump => {
+            let displacement = operand(registers, bus)? as i8;
+            registers.b = registers.b.wrapping_sub(1);
+            if registers.b != 0 {
+                registers.pc = registers.pc.wrapping_add(displacement as u16);
+                taken = 5;
+            }
+        }
+
+        InstructionAction::Call { condition } | InstructionAction::Return { condition } => {
+            if condition != crate::cpu::Condition::None && registers.check_condition(&condition) {
+                taken = if matches!(instruction.action, InstructionAction::Call { .. }) { 7 } else { 6 };
+            }
+            execute_decoded(registers, bus, instruction, pc, Model::Intel8080)?;
+        }
+
+        _ => {
+            execute_decoded(registers, bus, instruction, pc, Model::Intel8080)?;
+        }
+    }
+    Ok(CYCLES[instruction.opcode as usize] as u32 + taken)
+}
+
+// Opcodes that take (HL), the DD / FD prefixes turn it into (IX + d) / (IY + d).
+fn uses_memory(opcode: u8) -> bool {
+    match opcode {
+        0x34..=0x36 => true,
+        0x76 => false,
+        0x40..=0x7F => opcode & 0x07 == 6 || opcode & 0x38 == 0x30,
+        0x80..=0xBF => opcode & 0x07 == 6,
+        _ => false
+    }
+}
+
+// Opcodes that use HL, H or L otherwise, the DD / FD prefixes turn them into IX / IY and their halves.
+fn uses_hl(opcode: u8) -> bool {
+    match opcode {
+        0x09 | 0x19 | 0x29 | 0x39 | 0x21..=0x26 | 0x2A..=0x2E | 0xE1 | 0xE3 | 0xE5 | 0xE9 | 0xF9 => true,
+        0x40..=0xBF => matches!(opcode & 0x07, 4 | 5) || (opcode < 0x80 && matches!(opcode & 0x38, 0x20 | 0x28)),
+        _ => false
+    }
+}
+
+// DD / FD: the next opcode with IX / IY in place of HL. Anything that does not use HL runs as if unprefixed,
+// the prefix just costs 4 T states.
+fn execute_indexed<T: Bus8080 + ?Sized>(registers: &mut Registers, bus: &mut T, ix: bool, pc: u16) -> Result<u32, CpuError> {
+    let opcode = bus.read_b(pc.wrapping_add(1));
+    let index = if ix { registers.z80.ix } else { registers.z80.iy };
+    let hl = registers.get_16(&Register16::HL);
+    let displaced = || index.wrapping_add(bus.read_b(pc.wrapping_add(2)) as i8 as u16);
+
+    let cycles = match opcode {
+        // Another prefix follows, this one acts as a NOP.
+        0xDD | 0xED | 0xFD => {
+            registers.pc = pc.wrapping_add(1);
+            return Ok(4);
+        }
+
+        // DD CB d op, with the undocumented copy of the result into a register for the non BIT opcodes.
+        0xCB => {
+            refresh(registers, 1);
+            let address = displaced();
+            let opcode = bus.read_b(pc.wrapping_add(3));
+            registers.pc = pc.wrapping_add(4);
+            return Ok(execute_bits(registers, bus, opcode, address, true, true));
+        }
+
+        // LD H,(IX+d) and friends load and store the real H and L.
+        0x66 | 0x6E | 0x74 | 0x75 => {
+            refresh(registers, 1);
+            let address = displaced();
+            registers.pc = pc.wrapping_add(3);
+            match opcode {
+                0x66 => registers.h = bus.read_b(address),
+                0x6E => registers.l = bus.read_b(address),
+                0x74 => bus.write_b(address, registers.h),
+                _ => bus.write_b(address, registers.l)
+            }
+            return Ok(19);
+        }
+
+        _ if uses_memory(opcode) => {
+            refresh(registers, 1);
+            registers.set_16(&Register16::HL, displaced());
+            let result = execute_main(registers, bus, opcode, pc.wrapping_add(3), pc);
+            registers.set_16(&Register16::HL, hl);
+            result? + if opcode == 0x36 { 9 } else { 12 }
+        }
+
+        _ if uses_hl(opcode) => {
+            refresh(registers, 1);
+            registers.set_16(&Register16::HL, index);
+            let result = execute_main(registers, bus, opcode, pc.wrapping_add(2), pc);
+            let index = registers.get_16(&Register16::HL);
+            if ix { registers.z80.ix = index } else { registers.z80.iy = index }
+            registers.set_16(&Register16::HL, hl);
+            result? + 4
+        }
+
+        _ => {
+            refresh(registers, 1);
+            execute_main(registers, bus, opcode, pc.wrapping_add(2), pc)? + 4
+        }
+    };
+    Ok(cycles)
+}
+
+// The CB page on a register or the byte at `address`: rotates and shifts, BIT, RES and SET.
+// `indexed` is DD CB / FD CB, where the result also goes to the register in the opcode.
+fn execute_bits<T: Bus8080 + ?Sized>(registers: &mut Registers, bus: &mut T, opcode: u8, address: u16, memory: bool, indexed: bool) -> u32 {
+    let code = opcode & 7;
+    let bit = opcode >> 3 & 7;
+    let value = if memory { bus.read_b(address) } else { get_register(registers, code) };
+
+    let result = match opcode >> 6 {
+        0 => {
+            let output = z80_alu::shift(bit, value, registers.f);
+            registers.f = output.flags;
+            output.result
+        }
+        1 => {
+            // X3 and X5 come from the internal WZ register when testing memory, which is only modelled
+            // for (IX + d) where it holds the address.
+            let xy = if memory { (address >> 8) as u8 } else { value };
+            registers.f = z80_alu::bit(bit, value, xy, registers.f);
+            return match (indexed, memory) { (true, _) => 20, (false, true) => 12, _ => 8 };
+        }
+        2 => value & !(1 << bit),
+        _ => value | 1 << bit
+    };
+
+    if memory {
+        bus.write_b(address, result);
+    }
+    if !memory || (indexed && code != 6) {
+        set_register(registers, code, result);
+    }
+    match (indexed, memory) { (true, _) => 23, (false, true) => 15, _ => 8 }
+}
+
+// The ED page. Opcodes it does not define are 8 T state NOPs.
+fn execute_extended<T: Bus8080 + ?Sized>(registers: &mut Registers, bus: &mut T, pc: u16) -> u32 {
+    const PAIRS: [Register16; 4] = [Register16::BC, Register16::DE, Register16::HL, Register16::SP];
+    let opcode = bus.read_b(pc.wrapping_add(1));
+    let code = opcode >> 3 & 7;
+    let pair = PAIRS[(opcode >> 4 & 3) as usize];
+    let carry = registers.f & FLAG_CARRY;
+    registers.pc = pc.wrapping_add(2);
+
+    match opcode {
+        0x40..=0x7F => match opcode & 7 {
+            // IN r,(C), code 6 only sets the flags.
+            0 => {
+                let port = registers.c;
+                let value = bus.in_b(registers, port);
+                registers.f = carry | z80_alu::sz53p(value);
+                set_register(registers, code, value);
+                12
+            }
+            // OUT (C),r, code 6 outputs 0.
+            1 => {
+                let value = if code == 6 { 0x00 } else { get_register(registers, code) };
+                let port = registers.c;
+                bus.out_b(registers, port, value);
+                12
+            }
+            // SBC HL,rr / ADC HL,rr
+            2 => {
+                let value = registers.get_16(&pair);
+                let (result, flags) = z80_alu::add16_carry(registers.get_16(&Register16::HL), value, carry != 0, opcode & 0x08 == 0);
+                registers.f = flags;
+                registers.set_16(&Register16::HL, result);
+                15
+            }
+            // LD (nn),rr / LD rr,(nn)
+            3 => {
+                let address = bus.read_w(pc.wrapping_add(2));
+                registers.pc = pc.wrapping_add(4);
+                if opcode & 0x08 == 0 {
+                    bus.write_w(address, registers.get_16(&pair));
+                } else {
+                    let value = bus.read_w(address);
+                    registers.set_16(&pair, value);
+                }
+                20
+            }
+            // NEG
+            4 => {
+                let output = z80_alu::sub(0, registers.a, false);
+                (registers.a, registers.f) = (output.result, output.flags);
+                8
+            }
+            // RETN / RETI, both restore IFF1 from IFF2.
+            5 => {
+                registers.pc = bus.read_w(registers.sp);
+                registers.sp = registers.sp.wrapping_add(2);
+                registers.interrupts = registers.z80.iff2;
+                14
+            }
+            // IM 0 / 1 / 2, the undocumented duplicates included.
+            6 => {
+                registers.z80.interrupt_mode = [0, 0, 1, 2][(code & 3) as usize];
+                8
+            }
+            _ => match opcode {
+                0x47 => { registers.z80.i = registers.a; 9 }
+                0x4F => { registers.z80.r = registers.a; 9 }
+                // LD A,I / LD A,R copy IFF2 into P/V.
+                0x57 | 0x5F => {
+                    registers.a = if opcode == 0x57 { registers.z80.i } else { registers.z80.r };
+                    registers.f = carry | z80_alu::sz53(registers.a) | if registers.z80.iff2 { FLAG_PARITY } else { 0 };
+                    9
+                }
+                // RRD / RLD rotate nibbles between A and (HL).
+                0x67 | 0x6F => {
+                    let address = registers.get_16(&Register16::HL);
+                    let value = bus.read_b(address);
+                    let (memory, low) = if opcode == 0x67 {
+                        (registers.a << 4 | value >> 4, value & 0x0F)
+                    } else {
+                        (value << 4 | registers.a & 0x0F, value >> 4)
+                    };
+                    bus.write_b(address, memory);
+                    registers.a = registers.a & 0xF0 | low;
+                    registers.f = carry | z80_alu::sz53p(registers.a);
+                    18
+                }
+                _ => 8
+            }
+        },
+        0xA0..=0xA3 | 0xA8..=0xAB | 0xB0..=0xB3 | 0xB8..=0xBB => execute_block(registers, bus, opcode, pc),
+        _ => 8
+    }
+}
+
+// LDI / CPI / INI / OUTI, the D versions count down and the R versions repeat by going back to the
+// instruction until BC (B for I/O) runs out, CPIR / CPDR also stop on a match.
+fn execute_block<T: Bus8080 + ?Sized>(registers: &mut Registers, bus: &mut T, opcode: u8, pc: u16) -> u32 {
+    let step = if opcode & 0x08 == 0 { 0x0001 } else { 0xFFFF };
+    let hl = registers.get_16(&Register16::HL);
+    registers.set_16(&Register16::HL, hl.wrapping_add(step));
+    let parity = |value: u8| if value.count_ones().is_multiple_of(2) { FLAG_PARITY } else { 0 };
+
+    let more = match opcode & 3 {
+        0 => {
+            let value = bus.read_b(hl);
+            let de = registers.get_16(&Register16::DE);
+            bus.write_b(de, value);
+            registers.set_16(&Register16::DE, de.wrapping_add(step));
+            let bc = registers.get_16(&Register16::BC).wrapping_sub(1);
+            registers.set_16(&Register16::BC, bc);
+
+            let n = value.wrapping_add(registers.a);
+            let remaining = if bc != 0 { FLAG_PARITY } else { 0 };
+            registers.f = registers.f & (FLAG_SIGN | FLAG_ZERO | FLAG_CARRY) | n & FLAG_X3 | (n << 4) & FLAG_X5 | remaining;
+            bc != 0
+        }
+        1 => {
+            let value = bus.read_b(hl);
+            let result = registers.a.wrapping_sub(value);
+            let bc = registers.get_16(&Register16::BC).wrapping_sub(1);
+            registers.set_16(&Register16::BC, bc);
+
+            let half = (registers.a ^ value ^ result) & FLAG_HALF_CARRY;
+            let n = result.wrapping_sub((half != 0) as u8);
+            let remaining = if bc != 0 { FLAG_PARITY } else { 0 };
+            registers.f = registers.f & FLAG_CARRY | z80_alu::sz53(result) & (FLAG_SIGN | FLAG_ZERO) | half | FLAG_SUBTRACT
+                | n & FLAG_X3 | (n << 4) & FLAG_X5 | remaining;
+            bc != 0 && result != 0
+        }
+        io => {
+            let (value, k) = if io == 2 {
+                let port = registers.c;
+                let value = bus.in_b(registers, port);
+                bus.write_b(hl, value);
+                (value, value as u16 + registers.c.wrapping_add(step as u8) as u16)
+            } else {
+                let value = bus.read_b(hl);
+                registers.b = registers.b.wrapping_sub(1);
+                let port = registers.c;
+                bus.out_b(registers, port, value);
+                (value, value as u16 + registers.l as u16)
+            };
+            if io == 2 {
+                registers.b = registers.b.wrapping_sub(1);
+            }
+
+            let b = registers.b;
+            let carries = if k > 0xFF { FLAG_HALF_CARRY | FLAG_CARRY } else { 0 };
+            registers.f = z80_alu::sz53(b) | (value >> 6) & FLAG_SUBTRACT | carries | parity(k as u8 & 7 ^ b);
+            b != 0
+        }
+    };
+
+    if opcode & 0x10 != 0 && more {
+        registers.pc = pc;
+        21
+    } else {
+        16
+    }
+}
@@ -0,0 +1,167 @@
+// The Z80 versions of the arithmetic and logic operations, same shape as alu but with Z80 flags:
+// P/V is overflow after arithmetic and parity after logic, N is set by subtractions, H is the carry or
+// borrow out of bit 3 (always set by AND) and the undocumented X3 / X5 mostly copy bits 3 and 5 of the result.
+
+use crate::cpu::alu::AluOutput;
+use crate::cpu::{FLAG_CARRY, FLAG_HALF_CARRY, FLAG_PARITY, FLAG_SIGN, FLAG_SUBTRACT, FLAG_X3, FLAG_X5, FLAG_ZERO};
+
+const FLAGS_XY: u8 = FLAG_X3 | FLAG_X5;
+
+fn flag(value: bool, flag: u8) -> u8 {
+    if value { flag } else { 0 }
+}
+
+// S, Z, X5 and X3 from a result.
+pub(crate) fn sz53(result: u8) -> u8 {
+    result & (FLAG_SIGN | FLAGS_XY) | flag(result == 0, FLAG_ZERO)
+}
+
+// The same with P/V as parity (set when the number of 1 bits is even).
+pub(crate) fn sz53p(result: u8) -> u8 {
+    sz53(result) | flag(result.count_ones().is_multiple_of(2), FLAG_PARITY)
+}
+
+// ADD / ADC.
+pub(crate) fn add(a: u8, value: u8, carry: bool) -> AluOutput {
+    let sum = a as u16 + value as u16 + carry as u16;
+    let result = sum as u8;
+    let overflow = !(a ^ value) & (a ^ result) & 0x80 != 0;
+    let flags = sz53(result) | (a ^ value ^ result) & FLAG_HALF_CARRY | flag(overflow, FLAG_PARITY) | flag(sum > 0xFF, FLAG_CARRY);
+    AluOutput { result, flags }
+}
+
+// SUB / SBC / NEG.
+pub(crate) fn sub(a: u8, value: u8, borrow: bool) -> AluOutput {
+    let difference = a as i16 - value as i16 - borrow as i16;
+    let result = difference as u8;
+    let overflow = (a ^ value) & (a ^ result) & 0x80 != 0;
+    let flags = sz53(result) | (a ^ value ^ result) & FLAG_HALF_CARRY | flag(overflow, FLAG_PARITY)
+        | FLAG_SUBTRACT | flag(difference < 0, FLAG_CARRY);
+    AluOutput { result, flags }
+}
+
+// CP, X3 and X5 come from the operand instead of the result. The result is A, unchanged.
+pub(crate) fn compare(a: u8, value: u8) -> AluOutput {
+    let flags = sub(a, value, false).flags & !FLAGS_XY | value & FLAGS_XY;
+    AluOutput { result: a, flags }
+}
+
+pub(crate) fn and(a: u8, value: u8) -> AluOutput {
+    let result = a & value;
+    AluOutput { result, flags: sz53p(result) | FLAG_HALF_CARRY }
+}
+
+pub(crate) fn or(a: u8, value: u8) -> AluOutput {
+    let result = a | value;
+    AluOutput { result, flags: sz53p(result) }
+}
+
+pub(crate) fn xor(a: u8, value: u8) -> AluOutput {
+    let result = a ^ value;
+    AluOutput { result, flags: sz53p(result) }
+}
+
+// INC, C is not affected.
+pub(crate) fn increment(value: u8, flags: u8) -> AluOutput {
+    let result = value.wrapping_add(1);
+    let flags = flags & FLAG_CARRY | sz53(result) | flag(value & 0x0F == 0x0F, FLAG_HALF_CARRY) | flag(value == 0x7F, FLAG_PARITY);
+    AluOutput { result, flags }
+}
+
+// DEC, C is not affected.
+pub(crate) fn decrement(value: u8, flags: u8) -> AluOutput {
+    let result = value.wrapping_sub(1);
+    let flags = flags & FLAG_CARRY | sz53(result) | flag(value & 0x0F == 0x00, FLAG_HALF_CARRY) | flag(value == 0x80, FLAG_PARITY)
+        | FLAG_SUBTRACT;
+    AluOutput { result, flags }
+}
+
+// DAA corrects after an addition or, with N set, a subtraction.
+pub(crate) fn daa(a: u8, flags: u8) -> AluOutput {
+    let low = a & 0x0F;
+    let subtract = flags & FLAG_SUBTRACT != 0;
+    let mut correction = 0x00;
+    let mut carry = flags & FLAG_CARRY != 0;
+    if flags & FLAG_HALF_CARRY != 0 || low > 9 {
+        correction |= 0x06;
+    }
+    if carry || a > 0x99 {
+        correction |= 0x60;
+        carry = true;
+    }
+
+    let (result, half) = if subtract {
+        (a.wrapping_sub(correction), flags & FLAG_HALF_CARRY != 0 && low < 6)
+    } else {
+        (a.wrapping_add(correction), low > 9)
+    };
+    let flags = sz53p(result) | flag(half, FLAG_HALF_CARRY) | flags & FLAG_SUBTRACT | flag(carry, FLAG_CARRY);
+    AluOutput { result, flags }
+}
+
+// CPL.
+pub(crate) fn complement(a: u8, flags: u8) -> AluOutput {
+    let result = !a;
+    let flags = flags & (FLAG_SIGN | FLAG_ZERO | FLAG_PARITY | FLAG_CARRY) | result & FLAGS_XY | FLAG_HALF_CARRY | FLAG_SUBTRACT;
+    AluOutput { result, flags }
+}
+
+// SCF / CCF, CCF leaves the old carry in H. X3 and X5 come from A.
+pub(crate) fn set_carry(a: u8, flags: u8, complement: bool) -> u8 {
+    let carry = flags & FLAG_CARRY != 0;
+    let (half, carry) = if complement { (carry, !carry) } else { (false, true) };
+    flags & (FLAG_SIGN | FLAG_ZERO | FLAG_PARITY) | a & FLAGS_XY | flag(half, FLAG_HALF_CARRY) | flag(carry, FLAG_CARRY)
+}
+
+// RLCA / RRCA / RLA / RRA, S, Z and P/V are kept.
+pub(crate) fn rotate_a(a: u8, right: bool, through_carry: bool, flags: u8) -> AluOutput {
+    let output = crate::cpu::alu::rotate(a, right, through_carry, flags);
+    let flags = flags & (FLAG_SIGN | FLAG_ZERO | FLAG_PARITY) | output.result & FLAGS_XY | output.flags & FLAG_CARRY;
+    AluOutput { result: output.result, flags }
+}
+
+// The CB rotates and shifts: RLC, RRC, RL, RR, SLA, SRA, the undocumented SLL (shifts in a 1) and SRL.
+pub(crate) fn shift(operation: u8, value: u8, flags: u8) -> AluOutput {
+    let carry_in = flags & FLAG_CARRY;
+    let (result, carry) = match operation & 7 {
+        0 => (value.rotate_left(1), value >> 7),
+        1 => (value.rotate_right(1), value & 1),
+        2 => (value << 1 | carry_in, value >> 7),
+        3 => (value >> 1 | carry_in << 7, value & 1),
+        4 => (value << 1, value >> 7),
+        5 => (value >> 1 | value & 0x80, value & 1),
+        6 => (value << 1 | 1, value >> 7),
+        _ => (value >> 1, value & 1)
+    };
+    AluOutput { result, flags: sz53p(result) | carry }
+}
+
+// BIT, `xy` is where X3 and X5 come from.
+pub(crate) fn bit(bit: u8, value: u8, xy: u8, flags: u8) -> u8 {
+    let set = value & 1 << bit;
+    flags & FLAG_CARRY | FLAG_HALF_CARRY | xy & FLAGS_XY | set & FLAG_SIGN | flag(set == 0, FLAG_ZERO | FLAG_PARITY)
+}
+
+// ADD HL,rr / ADD IX,rr: H is the carry out of bit 11, S, Z and P/V are kept.
+pub(crate) fn add16(a: u16, value: u16, flags: u8) -> (u16, u8) {
+    let (result, carry) = a.overflowing_add(value);
+    let high = (result >> 8) as u8;
+    let flags = flags & (FLAG_SIGN | FLAG_ZERO | FLAG_PARITY) | high & FLAGS_XY
+        | ((a ^ value ^ result) >> 8) as u8 & FLAG_HALF_CARRY | flag(carry, FLAG_CARRY);
+    (result, flags)
+}
+
+// ADC HL,rr / SBC HL,rr set every flag from the 16 bit result.
+pub(crate) fn add16_carry(a: u16, value: u16, carry: bool, subtract: bool) -> (u16, u8) {
+    let (result, out, overflow) = if subtract {
+        let difference = a as i32 - value as i32 - carry as i32;
+        (difference as u16, difference < 0, (a ^ value) & (a ^ difference as u16) & 0x8000 != 0)
+    } else {
+        let sum = a as u32 + value as u32 + carry as u32;
+        (sum as u16, sum > 0xFFFF, !(a ^ value) & (a ^ sum as u16) & 0x8000 != 0)
+    };
+    let high = (result >> 8) as u8;
+    let flags = high & (FLAG_SIGN | FLAGS_XY) | flag(result == 0, FLAG_ZERO) | ((a ^ value ^ result) >> 8) as u8 & FLAG_HALF_CARRY
+        | flag(overflow, FLAG_PARITY) | flag(subtract, FLAG_SUBTRACT) | flag(out, FLAG_CARRY);
+    (result, flags)
+}
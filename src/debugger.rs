@@ -0,0 +1,289 @@
+use std::{collections::BTreeMap, ops::RangeInclusive};
+
+use crate::cpu::{BusAccess, CpuError, CPU8080, Instruction8080, InstructionAction, Interpreter8080, Registers, FLAG_OVERFLOW};
+
+pub type BreakCondition = Box<dyn Fn(&Registers) -> bool + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchKind
+{
+    Read,
+    Write,
+    ReadWrite
+}
+
+impl WatchKind
+{
+    fn matches(&self, write: bool) -> bool {
+        match self {
+            Self::Read => !write,
+            Self::Write => write,
+            Self::ReadWrite => true
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopReason
+{
+    // The requested step, step over or step out finished.
+    Step,
+    // About to execute an instruction with a breakpoint on it.
+    Breakpoint { address: u16 },
+    // The last instruction accessed a watched memory address or port.
+    MemoryWatch { id: usize, address: u16, write: bool },
+    PortWatch { id: usize, port: u8, write: bool },
+    // A global condition became true before executing the instruction at PC.
+    Condition { id: usize },
+    Halted,
+    Stopped,
+    // Stepping backwards reached the oldest recorded instruction (or history is not enabled).
+    HistoryExhausted
+}
+
+enum Watch
+{
+    Memory { range: RangeInclusive<u16>, kind: WatchKind },
+    Port { port: u8, kind: WatchKind }
+}
+
+pub struct Debugger
+{
+    cpu: Interpreter8080,
+    breakpoints: BTreeMap<u16, Option<BreakCondition>>,
+    watches: BTreeMap<usize, Watch>,
+    conditions: BTreeMap<usize, BreakCondition>,
+    next_id: usize
+}
+
+impl Debugger
+{
+    pub fn new(cpu: Interpreter8080) -> Self {
+        Self {
+            cpu,
+            breakpoints: BTreeMap::new(),
+            watches: BTreeMap::new(),
+            conditions: BTreeMap::new(),
+            next_id: 0
+        }
+    }
+
+    pub fn cpu(&self) -> &Interpreter8080 {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Interpreter8080 {
+        &mut self.cpu
+    }
+
+    pub fn into_inner(self) -> Interpreter8080 {
+        self.cpu
+    }
+
+    pub fn registers(&self) -> &Registers {
+        self.cpu.registers()
+    }
+
+    pub fn registers_mut(&mut self) -> &mut Registers {
+        self.cpu.registers_mut()
+    }
+
+    fn allocate_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address, None);
+    }
+
+    // Only breaks when the condition holds for the registers at that point.
+    pub fn add_conditional_breakpoint(&mut self, address: u16, condition: BreakCondition) {
+        self.breakpoints.insert(address, Some(condition));
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address).is_some()
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.keys().copied()
+    }
+
+    pub fn add_memory_watch(&mut self, range: RangeInclusive<u16>, kind: WatchKind) -> usize {
+        let id = self.allocate_id();
+        self.watches.insert(id, Watch::Memory { range, kind });
+        id
+    }
+
+    pub fn add_port_watch(&mut self, port: u8, kind: WatchKind) -> usize {
+        let id = self.allocate_id();
+        self.watches.insert(id, Watch::Port { port, kind });
+        id
+    }
+
+    pub fn remove_watch(&mut self, id: usize) -> bool {
+        self.watches.remove(&id).is_some()
+    }
+
+    // Breaks anywhere once the condition holds, checked before every instruction.
+    pub fn add_condition(&mut self, condition: BreakCondition) -> usize {
+        let id = self.allocate_id();
+        self.conditions.insert(id, condition);
+        id
+    }
+
+    pub fn remove_condition(&mut self, id: usize) -> bool {
+        self.conditions.remove(&id).is_some()
+    }
+
+    pub fn step(&mut self) -> Result<StopReason, CpuError> {
+        self.run_until(|_, _| true)
+    }
+
+    // Runs a CALL / RST as a single step, anything else is a normal step.
+    pub fn step_over(&mut self) -> Result<StopReason, CpuError> {
+        let instruction = self.peek();
+        let registers = self.cpu.registers();
+        let (return_address, stack) = (registers.pc.wrapping_add(instruction.length as u16), registers.sp);
+
+        let calls = match instruction.action {
+            InstructionAction::Call { condition } => registers.check_condition(&condition),
+            InstructionAction::Restart { .. } => true,
+            InstructionAction::RestartOnOverflow => registers.f & FLAG_OVERFLOW != 0,
+            _ => false
+        };
+        if !calls {
+            return self.step();
+        }
+        self.run_until(|registers, _| registers.pc == return_address && registers.sp == stack)
+    }
+
+    // Runs until a return pops the frame that was current when this was called. SP is compared by its
+    // distance from that frame, so a stack at the top of memory can wrap around to 0000H.
+    pub fn step_out(&mut self) -> Result<StopReason, CpuError> {
+        let stack = self.cpu.registers().sp;
+        self.run_until(|registers, instruction| {
+            matches!(instruction.action, InstructionAction::Return { .. }) && registers.sp.wrapping_sub(stack) as i16 > 0
+        })
+    }
+
+    // Runs until a breakpoint, watch or condition triggers or the CPU halts or stops.
+    pub fn resume(&mut self) -> Result<StopReason, CpuError> {
+        self.run_until(|_, _| false)
+    }
+
+    // Undoes the last instruction, needs history enabled on the CPU.
+    pub fn step_back(&mut self) -> StopReason {
+        if self.cpu.step_back() { StopReason::Step } else { StopReason::HistoryExhausted }
+    }
+
+    // Runs backwards until a breakpoint, a condition or the undo of a change to watched memory.
+    pub fn reverse_continue(&mut self) -> StopReason {
+        loop {
+            let changed = self.last_changes();
+            if !self.cpu.step_back() {
+                return StopReason::HistoryExhausted;
+            }
+            if let Some(reason) = self.check_watches(&changed).or_else(|| self.check_breakpoints()) {
+                return reason;
+            }
+        }
+    }
+
+    // The bytes the last recorded instruction actually changed, the journal may hold writes that did not happen.
+    fn last_changes(&self) -> Vec<BusAccess> {
+        let Some(entry) = self.cpu.history().and_then(|history| history.entries().next_back()) else {
+            return Vec::new();
+        };
+        let bus = self.cpu.get_bus();
+        let bus = bus.read().unwrap_or_else(|poison| poison.into_inner());
+        entry.writes.iter()
+            .filter(|(address, old)| bus.read_b(*address) != *old)
+            .map(|(address, _)| BusAccess::Memory { address: *address, size: 1, write: true })
+            .collect()
+    }
+
+    fn check_breakpoints(&self) -> Option<StopReason> {
+        let registers = self.cpu.registers();
+        if let Some(condition) = self.breakpoints.get(&registers.pc) {
+            if condition.as_ref().is_none_or(|condition| condition(registers)) {
+                return Some(StopReason::Breakpoint { address: registers.pc });
+            }
+        }
+        self.conditions.iter().find(|(_, condition)| condition(registers)).map(|(id, _)| StopReason::Condition { id: *id })
+    }
+
+    // Decodes with the CPU's model, the 8085 runs some opcodes the 8080 treats as aliases differently.
+    fn peek(&self) -> Instruction8080 {
+        let pc = self.cpu.registers().pc;
+        let bus = self.cpu.get_bus();
+        let bus = bus.read().unwrap_or_else(|poison| poison.into_inner());
+        let bytes = [0, 1, 2].map(|offset| bus.read_b(pc.wrapping_add(offset)));
+        self.cpu.model().decode(&bytes).map(|(instruction, _)| instruction).unwrap_or_else(|_| Instruction8080::new(bytes[0]))
+    }
+
+    fn run_until(&mut self, mut done: impl FnMut(&Registers, &Instruction8080) -> bool) -> Result<StopReason, CpuError> {
+        let mut first = true;
+        loop {
+            if !self.cpu.is_running() {
+                return Ok(StopReason::Stopped);
+            }
+
+            // The instruction we are stopped on does not trigger its own breakpoint again.
+            if !first {
+                if let Some(reason) = self.check_breakpoints() {
+                    return Ok(reason);
+                }
+            }
+            first = false;
+
+            let instruction = self.peek();
+            let predicted = if self.watches.is_empty() { Vec::new() } else { self.predict(&instruction) };
+
+            self.cpu.step()?;
+
+            if let Some(reason) = self.check_watches(&predicted) {
+                return Ok(reason);
+            }
+            if done(self.cpu.registers(), &instruction) {
+                return Ok(StopReason::Step);
+            }
+            if self.cpu.registers().halting {
+                return Ok(StopReason::Halted);
+            }
+        }
+    }
+
+    // Works out what the instruction about to run will touch.
+    fn predict(&self, instruction: &Instruction8080) -> Vec<BusAccess> {
+        let registers = self.cpu.registers();
+        let bus = self.cpu.get_bus();
+        let bus = bus.read().unwrap_or_else(|poison| poison.into_inner());
+
+        // A pending interrupt replaces the fetched instruction, the prediction would be wrong.
+        if registers.accepts_interrupt() && bus.has_interrupt() {
+            return Vec::new();
+        }
+        instruction.bus_accesses(registers)
+    }
+
+    fn check_watches(&self, predicted: &[BusAccess]) -> Option<StopReason> {
+        for access in predicted {
+            for (id, watch) in &self.watches {
+                match (access, watch) {
+                    (BusAccess::Memory { address, size, write }, Watch::Memory { range, kind }) if kind.matches(*write) => {
+                        if let Some(address) = (0..*size).map(|offset| address.wrapping_add(offset)).find(|a| range.contains(a)) {
+                            return Some(StopReason::MemoryWatch { id: *id, address, write: *write });
+                        }
+                    }
+                    (BusAccess::Port { port, write }, Watch::Port { port: watched, kind }) if kind.matches(*write) && port == watched => {
+                        return Some(StopReason::PortWatch { id: *id, port: *port, write: *write });
+                    }
+                    _ => {}
+                }
+            }
+        }
+        None
+    }
+}
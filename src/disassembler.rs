@@ -0,0 +1,314 @@
+use std::{fmt, ops::RangeInclusive};
+
+use crate::Bus8080;
+use crate::cpu::{Condition, Instruction8080, InstructionAction, InstructionType, Model, Register16, Register8};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Syntax
+{
+    Intel,
+    Zilog
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisassemblyLine
+{
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub text: String
+}
+
+impl fmt::Display for DisassemblyLine
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        write!(f, "{:04X}  {:<8}  {}", self.address, bytes.join(" "), self.text)
+    }
+}
+
+impl fmt::Display for Instruction8080
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format_instruction(self, Syntax::Intel))
+    }
+}
+
+// Disassembles every instruction starting inside the range, the last one may extend past its end.
+pub fn disassemble(bus: &dyn Bus8080, range: RangeInclusive<u16>, model: Model, syntax: Syntax) -> Vec<DisassemblyLine> {
+    let mut result = Vec::new();
+    let mut address = *range.start() as u32;
+
+    while address <= *range.end() as u32 {
+        let pc = address as u16;
+        let bytes = [0, 1, 2].map(|i| bus.read_b(pc.wrapping_add(i)));
+        let (text, length) = match model.decode(&bytes) {
+            Ok((instruction, length)) => (format_instruction(&instruction, syntax), length),
+            Err(_) => (format!("DB {}", hex8(bytes[0])), 1)
+        };
+
+        result.push(DisassemblyLine { address: pc, bytes: bytes[..length].to_vec(), text });
+        address += length as u32;
+    }
+    result
+}
+
+// Disassembles a buffer as if it was loaded at origin, a truncated instruction at the end is emitted as data.
+pub fn disassemble_bytes(bytes: &[u8], origin: u16, model: Model, syntax: Syntax) -> Vec<DisassemblyLine> {
+    let mut result = Vec::new();
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let address = origin.wrapping_add(offset as u16);
+        let (text, length) = match model.decode(&bytes[offset..]) {
+            Ok((instruction, length)) => (format_instruction(&instruction, syntax), length),
+            Err(_) => (format!("DB {}", hex8(bytes[offset])), 1)
+        };
+
+        result.push(DisassemblyLine { address, bytes: bytes[offset..offset + length].to_vec(), text });
+        offset += length;
+    }
+    result
+}
+
+pub fn format_instruction(instruction: &Instruction8080, syntax: Syntax) -> String {
+    match syntax {
+        Syntax::Intel => format_intel(instruction),
+        Syntax::Zilog => format_zilog(instruction)
+    }
+}
+
+// Intel style hex literal, numbers starting with a letter get a leading zero.
+fn hex8(value: u8) -> String {
+    let digits = format!("{:02X}H", value);
+    if digits.starts_with(|c: char| c.is_ascii_alphabetic()) { format!("0{}", digits) } else { digits }
+}
+
+fn hex16(value: u16) -> String {
+    let digits = format!("{:04X}H", value);
+    if digits.starts_with(|c: char| c.is_ascii_alphabetic()) { format!("0{}", digits) } else { digits }
+}
+
+fn intel_register8(register: &Register8) -> &'static str {
+    match register {
+        Register8::A => "A", Register8::B => "B", Register8::C => "C", Register8::D => "D",
+        Register8::E => "E", Register8::F => "F", Register8::H => "H", Register8::L => "L",
+        Register8::M => "M"
+    }
+}
+
+fn intel_register16(register: &Register16) -> &'static str {
+    match register {
+        Register16::BC => "B", Register16::DE => "D", Register16::HL => "H",
+        Register16::SP => "SP", Register16::PSW => "PSW"
+    }
+}
+
+fn intel_condition(condition: &Condition) -> &'static str {
+    match condition {
+        Condition::None => "",
+        Condition::NotZero => "NZ", Condition::Zero => "Z",
+        Condition::NotCarry => "NC", Condition::Carry => "C",
+        Condition::PairtyOdd => "PO", Condition::ParityEven => "PE",
+        Condition::Plus => "P", Condition::Minus => "M",
+        Condition::NotUnderflow => "NK", Condition::Underflow => "K"
+    }
+}
+
+fn zilog_register8(register: &Register8) -> &'static str {
+    match register {
+        Register8::M => "(HL)",
+        _ => intel_register8(register)
+    }
+}
+
+fn zilog_register16(register: &Register16) -> &'static str {
+    match register {
+        Register16::BC => "BC", Register16::DE => "DE", Register16::HL => "HL",
+        Register16::SP => "SP", Register16::PSW => "AF"
+    }
+}
+
+fn format_intel(instruction: &Instruction8080) -> String {
+    // Register or immediate source, picks between the two mnemonics of an ALU operation.
+    let alu = |register_form: &str, immediate_form: &str| match &instruction.target {
+        InstructionType::Register8 { register } => format!("{} {}", register_form, intel_register8(register)),
+        InstructionType::Immediate8 { value } => format!("{} {}", immediate_form, hex8(*value)),
+        _ => register_form.to_string()
+    };
+
+    match (&instruction.action, &instruction.target) {
+        (InstructionAction::Nothing, _) => "NOP".to_string(),
+        (InstructionAction::Halt, _) => "HLT".to_string(),
+
+        (InstructionAction::Jump { .. }, InstructionType::Register16 { .. }) => "PCHL".to_string(),
+        (InstructionAction::Jump { condition }, InstructionType::Immediate16 { value }) => match condition {
+            Condition::None => format!("JMP {}", hex16(*value)),
+            _ => format!("J{} {}", intel_condition(condition), hex16(*value))
+        },
+        (InstructionAction::Call { condition }, InstructionType::Immediate16 { value }) => match condition {
+            Condition::None => format!("CALL {}", hex16(*value)),
+            _ => format!("C{} {}", intel_condition(condition), hex16(*value))
+        },
+        (InstructionAction::Restart { vector }, _) => format!("RST {}", vector),
+        (InstructionAction::Return { condition }, _) => format!("R{}", match condition {
+            Condition::None => "ET",
+            _ => intel_condition(condition)
+        }),
+
+        (InstructionAction::Increment16 { register }, _) => format!("INX {}", intel_register16(register)),
+        (InstructionAction::Decrement16 { register }, _) => format!("DCX {}", intel_register16(register)),
+        (InstructionAction::Add16 { .. }, InstructionType::Register16 { register }) => format!("DAD {}", intel_register16(register)),
+        (InstructionAction::Load16 { .. }, InstructionType::Register16 { .. }) => "SPHL".to_string(),
+        (InstructionAction::Load16 { register }, InstructionType::Immediate16 { value }) => {
+            format!("LXI {},{}", intel_register16(register), hex16(*value))
+        }
+        (InstructionAction::Push16 { register }, _) => format!("PUSH {}", intel_register16(register)),
+        (InstructionAction::Pop16 { register }, _) => format!("POP {}", intel_register16(register)),
+
+        (InstructionAction::MovReg { register }, InstructionType::Register8 { register: source }) => {
+            format!("MOV {},{}", intel_register8(register), intel_register8(source))
+        }
+        (InstructionAction::MovReg { register }, InstructionType::Immediate8 { value }) => {
+            format!("MVI {},{}", intel_register8(register), hex8(*value))
+        }
+        (InstructionAction::AddReg { carry: false, .. }, _) => alu("ADD", "ADI"),
+        (InstructionAction::AddReg { carry: true, .. }, _) => alu("ADC", "ACI"),
+        (InstructionAction::SubReg { borrow: false, .. }, _) => alu("SUB", "SUI"),
+        (InstructionAction::SubReg { borrow: true, .. }, _) => alu("SBB", "SBI"),
+        (InstructionAction::AndReg { .. }, _) => alu("ANA", "ANI"),
+        (InstructionAction::XorReg { .. }, _) => alu("XRA", "XRI"),
+        (InstructionAction::OrReg { .. }, _) => alu("ORA", "ORI"),
+        (InstructionAction::CompareReg { .. }, _) => alu("CMP", "CPI"),
+        (InstructionAction::IncrementReg { register }, _) => format!("INR {}", intel_register8(register)),
+        (InstructionAction::DecrementReg { register }, _) => format!("DCR {}", intel_register8(register)),
+        (InstructionAction::DAAReg { .. }, _) => "DAA".to_string(),
+        (InstructionAction::ComplementReg { .. }, _) => "CMA".to_string(),
+        (InstructionAction::RotateReg { right, arithmetic, .. }, _) => match (right, arithmetic) {
+            (false, false) => "RLC", (true, false) => "RRC",
+            (false, true) => "RAL", (true, true) => "RAR"
+        }.to_string(),
+
+        (InstructionAction::StoreRegToMemory { .. }, InstructionType::Register16 { register }) => format!("STAX {}", intel_register16(register)),
+        (InstructionAction::StoreRegToMemory { .. }, InstructionType::Immediate16 { value }) => format!("STA {}", hex16(*value)),
+        (InstructionAction::LoadRegFromMemory { .. }, InstructionType::Register16 { register }) => format!("LDAX {}", intel_register16(register)),
+        (InstructionAction::LoadRegFromMemory { .. }, InstructionType::Immediate16 { value }) => format!("LDA {}", hex16(*value)),
+        (InstructionAction::StoreReg16ToMemory { .. }, InstructionType::Immediate16 { value }) => format!("SHLD {}", hex16(*value)),
+        (InstructionAction::LoadReg16FromMemory { .. }, InstructionType::Immediate16 { value }) => format!("LHLD {}", hex16(*value)),
+
+        (InstructionAction::SetInterrupts { enabled }, _) => if *enabled { "EI" } else { "DI" }.to_string(),
+        (InstructionAction::SetCarry { .. }, _) => "STC".to_string(),
+        (InstructionAction::ComplementCarry, _) => "CMC".to_string(),
+        (InstructionAction::ExchangeToStack, _) => "XTHL".to_string(),
+        (InstructionAction::Exchange, _) => "XCHG".to_string(),
+        (InstructionAction::In8, InstructionType::Immediate8 { value }) => format!("IN {}", hex8(*value)),
+        (InstructionAction::Out8, InstructionType::Immediate8 { value }) => format!("OUT {}", hex8(*value)),
+
+        // The 8085 additions, from Instruction8080::decode_8085().
+        (InstructionAction::ReadInterruptMask, _) => "RIM".to_string(),
+        (InstructionAction::SetInterruptMask, _) => "SIM".to_string(),
+        (InstructionAction::Sub16 { .. }, _) => "DSUB".to_string(),
+        (InstructionAction::ShiftRight16 { .. }, _) => "ARHL".to_string(),
+        (InstructionAction::RotateLeft16 { .. }, _) => "RDEL".to_string(),
+        (InstructionAction::LoadOffset16 { base, .. }, InstructionType::Immediate8 { value }) => {
+            format!("LD{}I {}", if *base == Register16::SP { "S" } else { "H" }, hex8(*value))
+        }
+        (InstructionAction::RestartOnOverflow, _) => "RSTV".to_string(),
+        (InstructionAction::StoreReg16ToMemory { .. }, InstructionType::Register16 { .. }) => "SHLX".to_string(),
+        (InstructionAction::LoadReg16FromMemory { .. }, InstructionType::Register16 { .. }) => "LHLX".to_string(),
+
+        // Anything the decoder could not make sense of is emitted as data.
+        _ => format!("DB {}", hex8(instruction.opcode))
+    }
+}
+
+fn format_zilog(instruction: &Instruction8080) -> String {
+    let alu = |mnemonic: &str| match &instruction.target {
+        InstructionType::Register8 { register } => format!("{}{}", mnemonic, zilog_register8(register)),
+        InstructionType::Immediate8 { value } => format!("{}{}", mnemonic, hex8(*value)),
+        _ => mnemonic.trim_end().to_string()
+    };
+
+    match (&instruction.action, &instruction.target) {
+        (InstructionAction::Nothing, _) => "NOP".to_string(),
+        (InstructionAction::Halt, _) => "HALT".to_string(),
+
+        (InstructionAction::Jump { .. }, InstructionType::Register16 { .. }) => "JP (HL)".to_string(),
+        (InstructionAction::Jump { condition }, InstructionType::Immediate16 { value }) => match condition {
+            Condition::None => format!("JP {}", hex16(*value)),
+            _ => format!("JP {},{}", intel_condition(condition), hex16(*value))
+        },
+        (InstructionAction::Call { condition }, InstructionType::Immediate16 { value }) => match condition {
+            Condition::None => format!("CALL {}", hex16(*value)),
+            _ => format!("CALL {},{}", intel_condition(condition), hex16(*value))
+        },
+        (InstructionAction::Restart { vector }, _) => format!("RST {}", hex8(vector * 8)),
+        (InstructionAction::Return { condition }, _) => match condition {
+            Condition::None => "RET".to_string(),
+            _ => format!("RET {}", intel_condition(condition))
+        },
+
+        (InstructionAction::Increment16 { register }, _) => format!("INC {}", zilog_register16(register)),
+        (InstructionAction::Decrement16 { register }, _) => format!("DEC {}", zilog_register16(register)),
+        (InstructionAction::Add16 { .. }, InstructionType::Register16 { register }) => format!("ADD HL,{}", zilog_register16(register)),
+        (InstructionAction::Load16 { .. }, InstructionType::Register16 { .. }) => "LD SP,HL".to_string(),
+        (InstructionAction::Load16 { register }, InstructionType::Immediate16 { value }) => {
+            format!("LD {},{}", zilog_register16(register), hex16(*value))
+        }
+        (InstructionAction::Push16 { register }, _) => format!("PUSH {}", zilog_register16(register)),
+        (InstructionAction::Pop16 { register }, _) => format!("POP {}", zilog_register16(register)),
+
+        (InstructionAction::MovReg { register }, InstructionType::Register8 { register: source }) => {
+            format!("LD {},{}", zilog_register8(register), zilog_register8(source))
+        }
+        (InstructionAction::MovReg { register }, InstructionType::Immediate8 { value }) => {
+            format!("LD {},{}", zilog_register8(register), hex8(*value))
+        }
+        (InstructionAction::AddReg { carry: false, .. }, _) => alu("ADD A,"),
+        (InstructionAction::AddReg { carry: true, .. }, _) => alu("ADC A,"),
+        (InstructionAction::SubReg { borrow: false, .. }, _) => alu("SUB "),
+        (InstructionAction::SubReg { borrow: true, .. }, _) => alu("SBC A,"),
+        (InstructionAction::AndReg { .. }, _) => alu("AND "),
+        (InstructionAction::XorReg { .. }, _) => alu("XOR "),
+        (InstructionAction::OrReg { .. }, _) => alu("OR "),
+        (InstructionAction::CompareReg { .. }, _) => alu("CP "),
+        (InstructionAction::IncrementReg { register }, _) => format!("INC {}", zilog_register8(register)),
+        (InstructionAction::DecrementReg { register }, _) => format!("DEC {}", zilog_register8(register)),
+        (InstructionAction::DAAReg { .. }, _) => "DAA".to_string(),
+        (InstructionAction::ComplementReg { .. }, _) => "CPL".to_string(),
+        (InstructionAction::RotateReg { right, arithmetic, .. }, _) => match (right, arithmetic) {
+            (false, false) => "RLCA", (true, false) => "RRCA",
+            (false, true) => "RLA", (true, true) => "RRA"
+        }.to_string(),
+
+        (InstructionAction::StoreRegToMemory { .. }, InstructionType::Register16 { register }) => format!("LD ({}),A", zilog_register16(register)),
+        (InstructionAction::StoreRegToMemory { .. }, InstructionType::Immediate16 { value }) => format!("LD ({}),A", hex16(*value)),
+        (InstructionAction::LoadRegFromMemory { .. }, InstructionType::Register16 { register }) => format!("LD A,({})", zilog_register16(register)),
+        (InstructionAction::LoadRegFromMemory { .. }, InstructionType::Immediate16 { value }) => format!("LD A,({})", hex16(*value)),
+        (InstructionAction::StoreReg16ToMemory { .. }, InstructionType::Immediate16 { value }) => format!("LD ({}),HL", hex16(*value)),
+        (InstructionAction::LoadReg16FromMemory { .. }, InstructionType::Immediate16 { value }) => format!("LD HL,({})", hex16(*value)),
+
+        (InstructionAction::SetInterrupts { enabled }, _) => if *enabled { "EI" } else { "DI" }.to_string(),
+        (InstructionAction::SetCarry { .. }, _) => "SCF".to_string(),
+        (InstructionAction::ComplementCarry, _) => "CCF".to_string(),
+        (InstructionAction::ExchangeToStack, _) => "EX (SP),HL".to_string(),
+        (InstructionAction::Exchange, _) => "EX DE,HL".to_string(),
+        (InstructionAction::In8, InstructionType::Immediate8 { value }) => format!("IN A,({})", hex8(*value)),
+        (InstructionAction::Out8, InstructionType::Immediate8 { value }) => format!("OUT ({}),A", hex8(*value)),
+
+        (InstructionAction::ExchangeAF, _) => "EX AF,AF'".to_string(),
+        (InstructionAction::ExchangeAlternates, _) => "EXX".to_string(),
+        (InstructionAction::DecrementJump, InstructionType::Immediate8 { value }) => format!("DJNZ {}", relative(*value)),
+        (InstructionAction::JumpRelative { condition }, InstructionType::Immediate8 { value }) => match condition {
+            Condition::None => format!("JR {}", relative(*value)),
+            _ => format!("JR {},{}", intel_condition(condition), relative(*value))
+        },
+
+        _ => format!("DB {}", hex8(instruction.opcode))
+    }
+}
+
+// JR / DJNZ targets relative to the start of the two byte instruction.
+fn relative(displacement: u8) -> String {
+    let offset = displacement as i8 as i16 + 2;
+    if offset < 0 { format!("$-{}", -offset) } else { format!("$+{}", offset) }
+}
@@ -0,0 +1,416 @@
+use std::{collections::BTreeMap, io::{self, Read, Write}, net::{TcpListener, ToSocketAddrs}};
+
+use crate::{cpu::{CpuError, CPU8080, Registers}, debugger::{Debugger, StopReason, WatchKind}};
+
+// GDB has no 8080 target, so the register layout is described to it through target.xml.
+const TARGET_XML: &str = concat!(
+    "<?xml version=\"1.0\"?>",
+    "<!DOCTYPE target SYSTEM \"gdb-target.dtd\">",
+    "<target version=\"1.0\"><feature name=\"org.r8080.cpu\">",
+    "<reg name=\"a\" bitsize=\"8\" type=\"uint8\" regnum=\"0\"/>",
+    "<reg name=\"b\" bitsize=\"8\" type=\"uint8\"/>",
+    "<reg name=\"c\" bitsize=\"8\" type=\"uint8\"/>",
+    "<reg name=\"d\" bitsize=\"8\" type=\"uint8\"/>",
+    "<reg name=\"e\" bitsize=\"8\" type=\"uint8\"/>",
+    "<reg name=\"f\" bitsize=\"8\" type=\"uint8\"/>",
+    "<reg name=\"h\" bitsize=\"8\" type=\"uint8\"/>",
+    "<reg name=\"l\" bitsize=\"8\" type=\"uint8\"/>",
+    "<reg name=\"sp\" bitsize=\"16\" type=\"data_ptr\"/>",
+    "<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>",
+    "</feature></target>"
+);
+
+// Register numbers as GDB sees them: a, b, c, d, e, f, h, l are one byte, sp and pc two (little endian).
+const REGISTER_COUNT: usize = 10;
+
+fn register_size(number: usize) -> usize {
+    if number < 8 { 1 } else { 2 }
+}
+
+fn get_register(registers: &Registers, number: usize) -> u16 {
+    match number {
+        0 => registers.a as u16,
+        1 => registers.b as u16,
+        2 => registers.c as u16,
+        3 => registers.d as u16,
+        4 => registers.e as u16,
+        5 => registers.f as u16,
+        6 => registers.h as u16,
+        7 => registers.l as u16,
+        8 => registers.sp,
+        _ => registers.pc
+    }
+}
+
+fn set_register(registers: &mut Registers, number: usize, value: u16) {
+    match number {
+        0 => registers.a = value as u8,
+        1 => registers.b = value as u8,
+        2 => registers.c = value as u8,
+        3 => registers.d = value as u8,
+        4 => registers.e = value as u8,
+        5 => registers.f = value as u8,
+        6 => registers.h = value as u8,
+        7 => registers.l = value as u8,
+        8 => registers.sp = value,
+        _ => registers.pc = value
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
+
+fn parse_number(text: &str) -> Option<u16> {
+    u16::from_str_radix(text, 16).ok()
+}
+
+// Parses "address,length" as used by m, M, Z and z packets.
+fn parse_range(text: &str) -> Option<(u16, u16)> {
+    let (address, length) = text.split_once(',')?;
+    Some((parse_number(address)?, parse_number(length)?))
+}
+
+// Buffers the raw stream and deals with packet framing, checksums and acknowledgements.
+struct Connection<S: Read + Write>
+{
+    stream: S,
+    buffer: [u8; 0x400],
+    length: usize,
+    position: usize
+}
+
+impl<S: Read + Write> Connection<S>
+{
+    fn new(stream: S) -> Self {
+        Self { stream, buffer: [0; 0x400], length: 0, position: 0 }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.position == self.length {
+            self.length = self.stream.read(&mut self.buffer)?;
+            self.position = 0;
+            if self.length == 0 {
+                return Ok(None);
+            }
+        }
+        self.position += 1;
+        Ok(Some(self.buffer[self.position - 1]))
+    }
+
+    // Returns None once the other side closes the connection.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // Skip acknowledgements and anything else outside of a packet.
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'$') => break,
+                    Some(_) => {}
+                }
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte)
+                }
+            }
+            let mut checksum = [0; 2];
+            for digit in &mut checksum {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(byte) => *digit = byte
+                }
+            }
+
+            let expected = std::str::from_utf8(&checksum).ok().and_then(|text| u8::from_str_radix(text, 16).ok());
+            let actual = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+            if expected == Some(actual) {
+                self.stream.write_all(b"+")?;
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+            self.stream.write_all(b"-")?;
+            self.stream.flush()?;
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let mut packet = Vec::with_capacity(data.len() + 4);
+        packet.push(b'$');
+        for byte in data.bytes() {
+            if matches!(byte, b'#' | b'$' | b'}' | b'*') {
+                packet.extend_from_slice(&[b'}', byte ^ 0x20]);
+            } else {
+                packet.push(byte);
+            }
+        }
+        let checksum = packet[1..].iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        packet.extend_from_slice(format!("#{:02x}", checksum).as_bytes());
+        self.stream.write_all(&packet)?;
+        self.stream.flush()
+    }
+}
+
+// Serves the GDB remote serial protocol for a debugger, one client at a time.
+// Continue runs until something stops the CPU, interrupting it from the client is not supported.
+pub struct GdbStub
+{
+    debugger: Debugger,
+    // Watch ids handed out by the debugger for each Z2 / Z3 / Z4 packet.
+    watches: BTreeMap<(u8, u16, u16), usize>
+}
+
+impl GdbStub
+{
+    pub fn new(debugger: Debugger) -> Self {
+        Self { debugger, watches: BTreeMap::new() }
+    }
+
+    pub fn debugger(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    pub fn into_inner(self) -> Debugger {
+        self.debugger
+    }
+
+    // Waits for a single client on the given address and serves it until it detaches.
+    pub fn listen_tcp(&mut self, address: impl ToSocketAddrs) -> io::Result<()> {
+        let listener = TcpListener::bind(address)?;
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        self.serve(stream)
+    }
+
+    #[cfg(unix)]
+    pub fn listen_unix(&mut self, path: impl AsRef<std::path::Path>) -> io::Result<()> {
+        let listener = std::os::unix::net::UnixListener::bind(path)?;
+        let (stream, _) = listener.accept()?;
+        self.serve(stream)
+    }
+
+    // Handles packets until the client kills or detaches from the target or closes the stream.
+    pub fn serve<S: Read + Write>(&mut self, stream: S) -> io::Result<()> {
+        let mut connection = Connection::new(stream);
+        while let Some(packet) = connection.read_packet()? {
+            match self.handle(&packet) {
+                Some(reply) => connection.send(&reply)?,
+                None => {
+                    connection.send("OK")?;
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the reply to a packet, None when the session is over.
+    fn handle(&mut self, packet: &str) -> Option<String> {
+        let (command, arguments) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+        let reply = match command {
+            "?" => "S05".to_string(),
+            "g" => self.read_registers(),
+            "G" => self.write_registers(arguments),
+            "p" => self.read_register(arguments),
+            "P" => self.write_register(arguments),
+            "m" => self.read_memory(arguments),
+            "M" => self.write_memory(arguments),
+            "Z" => self.set_breakpoint(arguments, true),
+            "z" => self.set_breakpoint(arguments, false),
+            "s" | "c" => {
+                if !arguments.is_empty() {
+                    match parse_number(arguments) {
+                        Some(address) => self.debugger.cpu_mut().force_jump(address),
+                        None => return Some("E01".to_string())
+                    }
+                }
+                let result = if command == "s" { self.debugger.step() } else { self.debugger.resume() };
+                self.stop_reply(result)
+            }
+            "b" => {
+                let reason = match arguments {
+                    "s" => self.debugger.step_back(),
+                    "c" => self.debugger.reverse_continue(),
+                    _ => return Some(String::new())
+                };
+                self.stop_reply(Ok(reason))
+            }
+            "H" => "OK".to_string(),
+            "q" => self.query(arguments),
+            "k" => {
+                self.debugger.cpu_mut().stop();
+                return None;
+            }
+            "D" => return None,
+            _ => String::new()
+        };
+        Some(reply)
+    }
+
+    fn query(&self, arguments: &str) -> String {
+        if arguments.starts_with("Supported") {
+            return "PacketSize=400;qXfer:features:read+;ReverseStep+;ReverseContinue+".to_string();
+        }
+        if arguments == "Attached" {
+            return "1".to_string();
+        }
+        let Some(annex) = arguments.strip_prefix("Xfer:features:read:target.xml:") else {
+            return String::new();
+        };
+        let Some((offset, length)) = annex.split_once(',').and_then(|(offset, length)| {
+            Some((usize::from_str_radix(offset, 16).ok()?, usize::from_str_radix(length, 16).ok()?))
+        }) else {
+            return "E01".to_string();
+        };
+        let start = offset.min(TARGET_XML.len());
+        let end = start.saturating_add(length).min(TARGET_XML.len());
+        let marker = if end == TARGET_XML.len() { 'l' } else { 'm' };
+        format!("{}{}", marker, &TARGET_XML[start..end])
+    }
+
+    fn read_registers(&self) -> String {
+        let registers = self.debugger.registers();
+        let bytes: Vec<u8> = (0..REGISTER_COUNT)
+            .flat_map(|number| get_register(registers, number).to_le_bytes().into_iter().take(register_size(number)))
+            .collect();
+        encode_hex(&bytes)
+    }
+
+    fn write_registers(&mut self, arguments: &str) -> String {
+        let Some(bytes) = decode_hex(arguments) else {
+            return "E01".to_string();
+        };
+        if bytes.len() != (0..REGISTER_COUNT).map(register_size).sum::<usize>() {
+            return "E01".to_string();
+        }
+        let registers = self.debugger.registers_mut();
+        let mut offset = 0;
+        for number in 0..REGISTER_COUNT {
+            let value = match register_size(number) {
+                1 => bytes[offset] as u16,
+                _ => u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+            };
+            set_register(registers, number, value);
+            offset += register_size(number);
+        }
+        "OK".to_string()
+    }
+
+    fn read_register(&self, arguments: &str) -> String {
+        match usize::from_str_radix(arguments, 16) {
+            Ok(number) if number < REGISTER_COUNT => {
+                let value = get_register(self.debugger.registers(), number);
+                encode_hex(&value.to_le_bytes()[..register_size(number)])
+            }
+            _ => "E01".to_string()
+        }
+    }
+
+    fn write_register(&mut self, arguments: &str) -> String {
+        let parsed = arguments.split_once('=').and_then(|(number, value)| {
+            Some((usize::from_str_radix(number, 16).ok()?, decode_hex(value)?))
+        });
+        match parsed {
+            Some((number, bytes)) if number < REGISTER_COUNT && bytes.len() == register_size(number) => {
+                let value = match bytes[..] {
+                    [low] => low as u16,
+                    [low, high] => u16::from_le_bytes([low, high]),
+                    _ => unreachable!()
+                };
+                set_register(self.debugger.registers_mut(), number, value);
+                "OK".to_string()
+            }
+            _ => "E01".to_string()
+        }
+    }
+
+    fn read_memory(&self, arguments: &str) -> String {
+        let Some((address, length)) = parse_range(arguments) else {
+            return "E01".to_string();
+        };
+        let bus = self.debugger.cpu().get_bus();
+        let bus = bus.read().unwrap_or_else(|poison| poison.into_inner());
+        let bytes: Vec<u8> = (0..length).map(|offset| bus.read_b(address.wrapping_add(offset))).collect();
+        encode_hex(&bytes)
+    }
+
+    fn write_memory(&mut self, arguments: &str) -> String {
+        let parsed = arguments.split_once(':').and_then(|(range, data)| Some((parse_range(range)?, decode_hex(data)?)));
+        let Some(((address, length), bytes)) = parsed else {
+            return "E01".to_string();
+        };
+        if bytes.len() != length as usize {
+            return "E01".to_string();
+        }
+        let bus = self.debugger.cpu().get_bus();
+        let mut bus = bus.write().unwrap_or_else(|poison| poison.into_inner());
+        for (offset, byte) in bytes.into_iter().enumerate() {
+            bus.write_b(address.wrapping_add(offset as u16), byte);
+        }
+        "OK".to_string()
+    }
+
+    // Z0 / Z1 are execution breakpoints, Z2 / Z3 / Z4 are write / read / access watchpoints.
+    fn set_breakpoint(&mut self, arguments: &str, insert: bool) -> String {
+        let parsed = arguments.split_once(',').and_then(|(kind, range)| Some((kind.parse::<u8>().ok()?, parse_range(range)?)));
+        let Some((kind, (address, length))) = parsed else {
+            return "E01".to_string();
+        };
+        let watch = match kind {
+            0 | 1 => {
+                if insert {
+                    self.debugger.add_breakpoint(address);
+                } else {
+                    self.debugger.remove_breakpoint(address);
+                }
+                return "OK".to_string();
+            }
+            2 => WatchKind::Write,
+            3 => WatchKind::Read,
+            4 => WatchKind::ReadWrite,
+            _ => return String::new()
+        };
+
+        let key = (kind, address, length);
+        if insert {
+            let end = address.wrapping_add(length.max(1) - 1);
+            let id = self.debugger.add_memory_watch(address..=end, watch);
+            if let Some(old) = self.watches.insert(key, id) {
+                self.debugger.remove_watch(old);
+            }
+        } else if let Some(id) = self.watches.remove(&key) {
+            self.debugger.remove_watch(id);
+        }
+        "OK".to_string()
+    }
+
+    fn stop_reply(&self, result: Result<StopReason, CpuError>) -> String {
+        match result {
+            Ok(StopReason::MemoryWatch { id, address, .. }) => {
+                let kind = self.watches.iter().find(|(_, watch)| **watch == id).map_or(2, |((kind, _, _), _)| *kind);
+                let name = match kind {
+                    3 => "rwatch",
+                    4 => "awatch",
+                    _ => "watch"
+                };
+                format!("T05{}:{:04x};", name, address)
+            }
+            Ok(StopReason::Stopped) => "W00".to_string(),
+            Ok(StopReason::HistoryExhausted) => "T05replaylog:begin;".to_string(),
+            Ok(_) => "S05".to_string(),
+            // SIGILL for bad instructions, SIGBUS for a broken bus.
+            Err(CpuError::BusFault { .. }) => "S0a".to_string(),
+            Err(_) => "S04".to_string()
+        }
+    }
+}
@@ -1,8 +1,16 @@
 use std::any::Any;
 
 use cpu::Registers;
+use state::StateError;
 
+pub mod asm;
 pub mod cpu;
+pub mod debugger;
+pub mod disassembler;
+pub mod gdb;
+pub mod lockstep;
+pub mod pic;
+pub mod state;
 
 pub trait Bus8080: Any + Send + Sync
 {
@@ -16,6 +24,108 @@ pub trait Bus8080: Any + Send + Sync
     fn in_b(&mut self, regs: &mut Registers, b: u8) -> u8;
     fn out_b(&mut self, regs: &mut Registers, b: u8, a: u8);
     fn write_buffer(&mut self, a: u16, data: Vec<u8>);
+
+    // Interrupt acknowledge, the CPU reads the instruction the device supplies one INTA cycle at a time:
+    // cycle 0 is the opcode, 1 and 2 the operand bytes of a CALL. The default takes the opcode from
+    // get_interrupt() and leaves the data bus floating (0xFF) for the rest, which is enough for RST.
+    fn acknowledge_interrupt(&mut self, cycle: u8) -> u8 {
+        if cycle == 0 { self.get_interrupt() } else { 0xFF }
+    }
+
+    // 8085 and Z80 only: the levels of the TRAP and RST 7.5 / 6.5 / 5.5 inputs, or the Z80 NMI, as
+    // cpu::LINE_* bits, sampled before every instruction. RST 7.5, TRAP and NMI trigger on the rising edge.
+    fn interrupt_lines(&self) -> u8 {
+        0
+    }
+
+    // 8085 only: the SID input read by RIM and the SOD output set by SIM.
+    fn serial_in(&self) -> bool {
+        false
+    }
+
+    fn serial_out(&mut self, _level: bool) {
+
+    }
+
+    // Snapshot hooks for save states, a bus without state of its own can keep the defaults.
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        if state.is_empty() {
+            Ok(())
+        } else {
+            Err(StateError::InvalidBusState { reason: "this bus does not support save states".to_string() })
+        }
+    }
+}
+
+impl<T: Bus8080 + ?Sized> Bus8080 for Box<T>
+{
+    fn read_b(&self, a: u16) -> u8 {
+        (**self).read_b(a)
+    }
+
+    fn read_w(&self, a: u16) -> u16 {
+        (**self).read_w(a)
+    }
+
+    fn has_interrupt(&self) -> bool {
+        (**self).has_interrupt()
+    }
+
+    fn get_interrupt(&mut self) -> u8 {
+        (**self).get_interrupt()
+    }
+
+    fn push_interrupt(&mut self, b: u8) {
+        (**self).push_interrupt(b)
+    }
+
+    fn write_b(&mut self, a: u16, b: u8) {
+        (**self).write_b(a, b)
+    }
+
+    fn write_w(&mut self, a: u16, w: u16) {
+        (**self).write_w(a, w)
+    }
+
+    fn in_b(&mut self, regs: &mut Registers, b: u8) -> u8 {
+        (**self).in_b(regs, b)
+    }
+
+    fn out_b(&mut self, regs: &mut Registers, b: u8, a: u8) {
+        (**self).out_b(regs, b, a)
+    }
+
+    fn write_buffer(&mut self, a: u16, data: Vec<u8>) {
+        (**self).write_buffer(a, data)
+    }
+
+    fn acknowledge_interrupt(&mut self, cycle: u8) -> u8 {
+        (**self).acknowledge_interrupt(cycle)
+    }
+
+    fn interrupt_lines(&self) -> u8 {
+        (**self).interrupt_lines()
+    }
+
+    fn serial_in(&self) -> bool {
+        (**self).serial_in()
+    }
+
+    fn serial_out(&mut self, level: bool) {
+        (**self).serial_out(level)
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        (**self).save_state()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        (**self).load_state(state)
+    }
 }
 
 struct ErrorBus;
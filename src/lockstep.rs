@@ -0,0 +1,341 @@
+use std::{any::Any, collections::VecDeque, fmt, sync::{Arc, Mutex}};
+
+use crate::{Bus8080, ErrorBus};
+use crate::cpu::{CpuError, Registers, StepInfo, CPU8080, FLAG_CARRY, FLAG_HALF_CARRY, FLAG_PARITY, FLAG_SIGN, FLAG_ZERO};
+use crate::disassembler::{disassemble, DisassemblyLine, Syntax};
+use crate::state::StateError;
+
+const DEFAULT_TRACE_LENGTH: usize = 16;
+
+// Something an instruction did to the outside world, word writes are logged as their two bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BusEvent
+{
+    Write { address: u16, value: u8 },
+    Out { port: u8, value: u8 }
+}
+
+impl fmt::Display for BusEvent
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Write { address, value } => write!(f, "[{:04X}] <- {:02X}", address, value),
+            Self::Out { port, value } => write!(f, "OUT {:02X} <- {:02X}", port, value)
+        }
+    }
+}
+
+// One way the two CPUs disagreed after the same instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mismatch
+{
+    // The steps themselves differ (fetched from elsewhere, different cycles or only one of them failed).
+    Step { reference: Result<StepInfo, CpuError>, candidate: Result<StepInfo, CpuError> },
+    // Anything in the registers except F.
+    Registers { reference: Registers, candidate: Registers },
+    Flags { reference: u8, candidate: u8 },
+    // The executed cycle counters.
+    Cycles { reference: u64, candidate: u64 },
+    // Memory writes and port outputs, in the order the bus saw them.
+    BusEvents { reference: Vec<BusEvent>, candidate: Vec<BusEvent> }
+}
+
+impl fmt::Display for Mismatch
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Step { reference, candidate } => write!(f, "step: {:?} vs {:?}", reference, candidate),
+            Self::Registers { reference, candidate } => write!(f, "registers: {} vs {}", format_registers(reference), format_registers(candidate)),
+            Self::Flags { reference, candidate } => {
+                write!(f, "flags: {:08b} vs {:08b} (differ in {})", reference, candidate, flag_names(reference ^ candidate))
+            }
+            Self::Cycles { reference, candidate } => write!(f, "cycles: {} vs {}", reference, candidate),
+            Self::BusEvents { reference, candidate } => write!(f, "bus: [{}] vs [{}]", format_events(reference), format_events(candidate))
+        }
+    }
+}
+
+// An instruction both CPUs ran, with the reference registers before it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry
+{
+    pub index: u64,
+    pub registers: Registers,
+    pub line: DisassemblyLine
+}
+
+impl fmt::Display for TraceEntry
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:<10} {:<28} {}", self.index, self.line.to_string(), format_registers(&self.registers))
+    }
+}
+
+// The first instruction after which the CPUs no longer agree, the last trace entry is that instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence
+{
+    pub index: u64,
+    pub line: DisassemblyLine,
+    pub mismatches: Vec<Mismatch>,
+    pub trace: Vec<TraceEntry>
+}
+
+impl fmt::Display for Divergence
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "CPUs diverged at instruction #{}: {}", self.index, self.line)?;
+        for mismatch in &self.mismatches {
+            writeln!(f, "  {}", mismatch)?;
+        }
+        writeln!(f, "Recent instructions (reference registers before each):")?;
+        for entry in &self.trace {
+            writeln!(f, "  {}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Divergence {}
+
+fn format_registers(registers: &Registers) -> String {
+    format!("PC={:04X} SP={:04X} A={:02X} B={:02X} C={:02X} D={:02X} E={:02X} H={:02X} L={:02X} F={:02X} I={} EI={} HLT={} RUN={}",
+        registers.pc, registers.sp, registers.a, registers.b, registers.c, registers.d, registers.e, registers.h, registers.l,
+        registers.f, registers.interrupts as u8, registers.interrupt_delay as u8, registers.halting as u8, registers.running as u8)
+}
+
+fn format_events(events: &[BusEvent]) -> String {
+    events.iter().map(|event| event.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+fn flag_names(flags: u8) -> String {
+    let names = [(FLAG_SIGN, "S"), (FLAG_ZERO, "Z"), (FLAG_HALF_CARRY, "AC"), (FLAG_PARITY, "P"), (FLAG_CARRY, "CY")];
+    let mut result: Vec<String> = names.iter().filter(|(flag, _)| flags & flag != 0).map(|(_, name)| name.to_string()).collect();
+    let unused = flags & !(FLAG_SIGN | FLAG_ZERO | FLAG_HALF_CARRY | FLAG_PARITY | FLAG_CARRY);
+    if unused != 0 {
+        result.push(format!("unused bits {:08b}", unused));
+    }
+    result.join(" ")
+}
+
+// Forwards to the real bus and logs what the CPU wrote through it.
+struct RecordingBus
+{
+    inner: Box<dyn Bus8080>,
+    log: Arc<Mutex<Vec<BusEvent>>>
+}
+
+impl RecordingBus
+{
+    fn record(&self, event: BusEvent) {
+        self.log.lock().unwrap_or_else(|poison| poison.into_inner()).push(event);
+    }
+}
+
+impl Bus8080 for RecordingBus
+{
+    fn read_b(&self, a: u16) -> u8 {
+        self.inner.read_b(a)
+    }
+
+    fn read_w(&self, a: u16) -> u16 {
+        self.inner.read_w(a)
+    }
+
+    fn has_interrupt(&self) -> bool {
+        self.inner.has_interrupt()
+    }
+
+    fn get_interrupt(&mut self) -> u8 {
+        self.inner.get_interrupt()
+    }
+
+    fn push_interrupt(&mut self, b: u8) {
+        self.inner.push_interrupt(b)
+    }
+
+    fn write_b(&mut self, a: u16, b: u8) {
+        self.record(BusEvent::Write { address: a, value: b });
+        self.inner.write_b(a, b)
+    }
+
+    fn write_w(&mut self, a: u16, w: u16) {
+        self.record(BusEvent::Write { address: a, value: w as u8 });
+        self.record(BusEvent::Write { address: a.wrapping_add(1), value: (w >> 8) as u8 });
+        self.inner.write_w(a, w)
+    }
+
+    fn in_b(&mut self, regs: &mut Registers, b: u8) -> u8 {
+        self.inner.in_b(regs, b)
+    }
+
+    fn out_b(&mut self, regs: &mut Registers, b: u8, a: u8) {
+        self.record(BusEvent::Out { port: b, value: a });
+        self.inner.out_b(regs, b, a)
+    }
+
+    fn write_buffer(&mut self, a: u16, data: Vec<u8>) {
+        self.inner.write_buffer(a, data)
+    }
+
+    fn acknowledge_interrupt(&mut self, cycle: u8) -> u8 {
+        self.inner.acknowledge_interrupt(cycle)
+    }
+
+    fn interrupt_lines(&self) -> u8 {
+        self.inner.interrupt_lines()
+    }
+
+    fn serial_in(&self) -> bool {
+        self.inner.serial_in()
+    }
+
+    fn serial_out(&mut self, level: bool) {
+        self.inner.serial_out(level)
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.inner.save_state()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        self.inner.load_state(state)
+    }
+}
+
+// Puts a recording bus in front of the bus the CPU already has, in the same shared slot.
+fn intercept(cpu: &mut dyn CPU8080) -> Arc<Mutex<Vec<BusEvent>>> {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let bus = cpu.get_bus();
+    let mut bus = bus.write().unwrap_or_else(|poison| poison.into_inner());
+    let inner = std::mem::replace(&mut *bus, Box::new(ErrorBus::new()));
+    *bus = Box::new(RecordingBus { inner, log: Arc::clone(&log) });
+    log
+}
+
+// Takes the recording bus back out, leaving the CPU with the bus it had before.
+fn release(cpu: &mut dyn CPU8080) {
+    let bus = cpu.get_bus();
+    let mut bus = bus.write().unwrap_or_else(|poison| poison.into_inner());
+    let recording: Box<dyn Bus8080> = std::mem::replace(&mut *bus, Box::new(ErrorBus::new()));
+    *bus = match (recording as Box<dyn Any>).downcast::<RecordingBus>() {
+        Ok(recording) => recording.inner,
+        Err(_) => unreachable!("the lockstep bus was replaced behind its back")
+    };
+}
+
+// Runs two CPU8080s one instruction at a time and compares them after every step.
+// Both need their own bus (e.g. two instances set up the same way), the reference one (and its model) is used to disassemble.
+pub struct Lockstep
+{
+    reference: Box<dyn CPU8080>,
+    candidate: Box<dyn CPU8080>,
+    logs: [Arc<Mutex<Vec<BusEvent>>>; 2],
+    trace: VecDeque<TraceEntry>,
+    trace_length: usize,
+    executed: u64
+}
+
+impl Lockstep
+{
+    pub fn new(mut reference: Box<dyn CPU8080>, mut candidate: Box<dyn CPU8080>) -> Self {
+        let logs = [intercept(reference.as_mut()), intercept(candidate.as_mut())];
+        Self {
+            reference,
+            candidate,
+            logs,
+            trace: VecDeque::new(),
+            trace_length: DEFAULT_TRACE_LENGTH,
+            executed: 0
+        }
+    }
+
+    // How many instructions a divergence report looks back, including the one that diverged.
+    pub fn set_trace_length(&mut self, length: usize) {
+        self.trace_length = length.max(1);
+        while self.trace.len() > self.trace_length {
+            self.trace.pop_front();
+        }
+    }
+
+    pub fn reference(&self) -> &dyn CPU8080 {
+        self.reference.as_ref()
+    }
+
+    pub fn candidate(&self) -> &dyn CPU8080 {
+        self.candidate.as_ref()
+    }
+
+    pub fn into_inner(mut self) -> (Box<dyn CPU8080>, Box<dyn CPU8080>) {
+        release(self.reference.as_mut());
+        release(self.candidate.as_mut());
+        (self.reference, self.candidate)
+    }
+
+    // Instructions both CPUs agreed on so far.
+    pub fn executed(&self) -> u64 {
+        self.executed
+    }
+
+    // Steps both CPUs, failing ones count as agreeing if they fail the same way.
+    pub fn step(&mut self) -> Result<(), Box<Divergence>> {
+        let registers = *self.reference.registers();
+        let line = {
+            let bus = self.reference.get_bus();
+            let bus = bus.read().unwrap_or_else(|poison| poison.into_inner());
+            disassemble(&**bus, registers.pc..=registers.pc, self.reference.model(), Syntax::Intel).remove(0)
+        };
+        if self.trace.len() == self.trace_length {
+            self.trace.pop_front();
+        }
+        self.trace.push_back(TraceEntry { index: self.executed, registers, line: line.clone() });
+
+        let reference = self.reference.step();
+        let candidate = self.candidate.step();
+        let mismatches = self.compare(reference, candidate);
+        if !mismatches.is_empty() {
+            return Err(Box::new(Divergence { index: self.executed, line, mismatches, trace: self.trace.iter().cloned().collect() }));
+        }
+        self.executed += 1;
+        Ok(())
+    }
+
+    // Steps until both CPUs stop or `instructions` have run, returns how many did.
+    pub fn run(&mut self, instructions: u64) -> Result<u64, Box<Divergence>> {
+        let start = self.executed;
+        while self.executed - start < instructions && (self.reference.is_running() || self.candidate.is_running()) {
+            self.step()?;
+        }
+        Ok(self.executed - start)
+    }
+
+    fn compare(&mut self, reference: Result<StepInfo, CpuError>, candidate: Result<StepInfo, CpuError>) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+        if reference != candidate {
+            mismatches.push(Mismatch::Step { reference, candidate });
+        }
+
+        let (left, right) = (*self.reference.registers(), *self.candidate.registers());
+        if (Registers { f: 0, ..left }) != (Registers { f: 0, ..right }) {
+            mismatches.push(Mismatch::Registers { reference: left, candidate: right });
+        }
+        if left.f != right.f {
+            mismatches.push(Mismatch::Flags { reference: left.f, candidate: right.f });
+        }
+
+        let (left, right) = (self.reference.get_executed_cycles(), self.candidate.get_executed_cycles());
+        if left != right {
+            mismatches.push(Mismatch::Cycles { reference: left, candidate: right });
+        }
+
+        let [left, right] = &self.logs;
+        let mut left = left.lock().unwrap_or_else(|poison| poison.into_inner());
+        let mut right = right.lock().unwrap_or_else(|poison| poison.into_inner());
+        if *left != *right {
+            mismatches.push(Mismatch::BusEvents { reference: left.clone(), candidate: right.clone() });
+        }
+        left.clear();
+        right.clear();
+        mismatches
+    }
+}
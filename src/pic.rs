@@ -0,0 +1,308 @@
+// Intel 8259A programmable interrupt controller in 8080 mode.
+// A bus mounts it on a port pair (A0 is the low bit of the port) by forwarding in_b / out_b to
+// read / write, and answers has_interrupt / acknowledge_interrupt from it. Accepting an interrupt
+// then reads a CALL to the level's vector over the three INTA cycles.
+// Cascading is not modelled (ICW3 is only stored) and the 8086 mode bit of ICW4 is ignored.
+
+use crate::state::StateError;
+
+// OUT with A0 = 0 and bit 4 set starts the initialization sequence.
+const ICW1_INIT: u8 = 1 << 4;
+const ICW1_IC4: u8 = 1 << 0;
+const ICW1_SINGLE: u8 = 1 << 1;
+const ICW1_INTERVAL_4: u8 = 1 << 2;
+const ICW1_LEVEL: u8 = 1 << 3;
+const ICW4_AUTO_EOI: u8 = 1 << 1;
+// OUT with A0 = 0, bit 4 clear: bit 3 picks OCW3 over OCW2.
+const OCW3_SELECT: u8 = 1 << 3;
+
+const CALL: u8 = 0xCD;
+const STATE_SIZE: usize = 11;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Init
+{
+    Ready,
+    Icw2,
+    Icw3,
+    Icw4
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pic8259
+{
+    // Interrupt request, in service and mask registers, bit n is IRn.
+    irr: u8,
+    isr: u8,
+    imr: u8,
+    // The current level of each IR input, for edge detection.
+    lines: u8,
+    icw1: u8,
+    icw2: u8,
+    icw3: u8,
+    icw4: u8,
+    init: Init,
+    // The level with the lowest priority, the one after it has the highest. IR7 after initialization.
+    lowest: u8,
+    rotate_on_auto_eoi: bool,
+    special_mask: bool,
+    read_isr: bool,
+    poll: bool,
+    // The level being acknowledged between INTA cycles.
+    acknowledging: u8
+}
+
+impl Pic8259
+{
+    pub fn new() -> Self {
+        Self {
+            irr: 0, isr: 0, imr: 0xFF, lines: 0,
+            icw1: 0, icw2: 0, icw3: 0, icw4: 0,
+            init: Init::Ready,
+            lowest: 7,
+            rotate_on_auto_eoi: false, special_mask: false, read_isr: false, poll: false,
+            acknowledging: 7
+        }
+    }
+
+    pub fn irr(&self) -> u8 {
+        self.irr
+    }
+
+    pub fn isr(&self) -> u8 {
+        self.isr
+    }
+
+    pub fn imr(&self) -> u8 {
+        self.imr
+    }
+
+    // OUT to the port pair, `a0` is the low address bit.
+    pub fn write(&mut self, a0: bool, value: u8) {
+        if !a0 && value & ICW1_INIT != 0 {
+            self.initialize(value);
+            return;
+        }
+
+        match (a0, self.init) {
+            (true, Init::Icw2) => {
+                self.icw2 = value;
+                self.init = self.next_after(Init::Icw2);
+            }
+            (true, Init::Icw3) => {
+                self.icw3 = value;
+                self.init = self.next_after(Init::Icw3);
+            }
+            (true, Init::Icw4) => {
+                self.icw4 = value;
+                self.init = Init::Ready;
+            }
+            (true, Init::Ready) => self.imr = value,
+            (false, _) if value & OCW3_SELECT != 0 => self.command_ocw3(value),
+            (false, _) => self.command_ocw2(value)
+        }
+    }
+
+    // IN from the port pair. A0 = 0 gives IRR or ISR as selected by OCW3 (or the poll word after a poll
+    // command), A0 = 1 the mask.
+    pub fn read(&mut self, a0: bool) -> u8 {
+        if self.poll {
+            self.poll = false;
+            return match self.pending() {
+                Some(level) => {
+                    self.start_service(level);
+                    0x80 | level
+                }
+                None => 0x00
+            };
+        }
+        match (a0, self.read_isr) {
+            (true, _) => self.imr,
+            (false, true) => self.isr,
+            (false, false) => self.irr
+        }
+    }
+
+    // Drives an IR input. Edge triggered mode latches a request on the rising edge, level triggered
+    // mode requests for as long as the line is high.
+    pub fn set_line(&mut self, level: u8, high: bool) {
+        let bit = 1 << (level & 7);
+        let rising = high && self.lines & bit == 0;
+        if high { self.lines |= bit } else { self.lines &= !bit }
+
+        if self.icw1 & ICW1_LEVEL != 0 {
+            if high { self.irr |= bit } else { self.irr &= !bit }
+        } else if rising {
+            self.irr |= bit;
+        } else if !high {
+            // The request has to be held until it is acknowledged.
+            self.irr &= !bit;
+        }
+    }
+
+    // A pulse on an IR input, for devices that just want to request once.
+    pub fn request(&mut self, level: u8) {
+        self.set_line(level, true);
+        self.lines &= !(1 << (level & 7));
+    }
+
+    // The INT output: an unmasked request with a higher priority than anything in service.
+    pub fn has_interrupt(&self) -> bool {
+        self.init == Init::Ready && self.pending().is_some()
+    }
+
+    // INTA cycle 0 gives CALL and moves the request into service, 1 and 2 the vector address.
+    // Without a request left at cycle 0 the 8259A answers for IR7 without setting it in service.
+    pub fn acknowledge(&mut self, cycle: u8) -> u8 {
+        match cycle {
+            0 => {
+                match self.pending() {
+                    Some(level) => self.start_service(level),
+                    None => self.acknowledging = 7
+                }
+                CALL
+            }
+            1 => self.vector(self.acknowledging) as u8,
+            _ => {
+                if self.icw4 & ICW4_AUTO_EOI != 0 {
+                    self.end_of_interrupt(self.acknowledging, self.rotate_on_auto_eoi);
+                }
+                (self.vector(self.acknowledging) >> 8) as u8
+            }
+        }
+    }
+
+    // The address the CALL for a level goes to, ICW2 is the high byte and ICW1 gives the low bits.
+    pub fn vector(&self, level: u8) -> u16 {
+        let level = level as u16 & 7;
+        let low = if self.icw1 & ICW1_INTERVAL_4 != 0 {
+            (self.icw1 as u16 & 0xE0) | level << 2
+        } else {
+            (self.icw1 as u16 & 0xC0) | level << 3
+        };
+        (self.icw2 as u16) << 8 | low
+    }
+
+    // The whole controller as bytes, for a bus to put in its own save state.
+    pub fn save_state(&self) -> Vec<u8> {
+        let init = match self.init { Init::Ready => 0, Init::Icw2 => 1, Init::Icw3 => 2, Init::Icw4 => 3 };
+        let flags = self.rotate_on_auto_eoi as u8 | (self.special_mask as u8) << 1 | (self.read_isr as u8) << 2 | (self.poll as u8) << 3;
+        vec![self.irr, self.isr, self.imr, self.lines, self.icw1, self.icw2, self.icw3, self.icw4, init, self.lowest | self.acknowledging << 4, flags]
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let Ok(&[irr, isr, imr, lines, icw1, icw2, icw3, icw4, init, priority, flags]) = <&[u8; STATE_SIZE]>::try_from(state) else {
+            return Err(StateError::InvalidBusState { reason: format!("8259A state is {} bytes, expected {}", state.len(), STATE_SIZE) });
+        };
+        let init = match init {
+            0 => Init::Ready,
+            1 => Init::Icw2,
+            2 => Init::Icw3,
+            3 => Init::Icw4,
+            _ => return Err(StateError::InvalidBusState { reason: format!("invalid 8259A initialization step {}", init) })
+        };
+
+        *self = Self {
+            irr, isr, imr, lines, icw1, icw2, icw3, icw4, init,
+            lowest: priority & 7,
+            rotate_on_auto_eoi: flags & 1 != 0,
+            special_mask: flags & 2 != 0,
+            read_isr: flags & 4 != 0,
+            poll: flags & 8 != 0,
+            acknowledging: priority >> 4 & 7
+        };
+        Ok(())
+    }
+
+    fn initialize(&mut self, icw1: u8) {
+        // ICW1 clears the mask, the edge sense latches, special mask mode and rotation, and selects IRR for reading.
+        *self = Self { icw1, imr: 0, lines: self.lines, init: Init::Icw2, ..Self::new() };
+    }
+
+    fn next_after(&self, step: Init) -> Init {
+        match step {
+            Init::Icw2 if self.icw1 & ICW1_SINGLE == 0 => Init::Icw3,
+            Init::Icw2 | Init::Icw3 if self.icw1 & ICW1_IC4 != 0 => Init::Icw4,
+            _ => Init::Ready
+        }
+    }
+
+    fn command_ocw2(&mut self, value: u8) {
+        let level = value & 7;
+        match value >> 5 {
+            // Non-specific EOI, without and with rotation.
+            0b001 => if let Some(level) = self.highest_in_service() { self.end_of_interrupt(level, false) },
+            0b101 => if let Some(level) = self.highest_in_service() { self.end_of_interrupt(level, true) },
+            // Specific EOI, without and with rotation.
+            0b011 => self.end_of_interrupt(level, false),
+            0b111 => self.end_of_interrupt(level, true),
+            // Rotate in automatic EOI mode, clear and set.
+            0b000 => self.rotate_on_auto_eoi = false,
+            0b100 => self.rotate_on_auto_eoi = true,
+            // Set priority, the given level becomes the lowest.
+            0b110 => self.lowest = level,
+            _ => { }
+        }
+    }
+
+    fn command_ocw3(&mut self, value: u8) {
+        // ESMM (bit 6) enables changing SMM (bit 5), RR (bit 1) enables changing RIS (bit 0).
+        if value & 0x40 != 0 {
+            self.special_mask = value & 0x20 != 0;
+        }
+        if value & 0x02 != 0 {
+            self.read_isr = value & 0x01 != 0;
+        }
+        self.poll = value & 0x04 != 0;
+    }
+
+    // The levels from highest to lowest priority.
+    fn priorities(&self) -> impl Iterator<Item = u8> {
+        let lowest = self.lowest;
+        (1..=8).map(move |offset| (lowest + offset) & 7)
+    }
+
+    fn highest_in_service(&self) -> Option<u8> {
+        self.priorities().find(|level| self.isr & 1 << level != 0)
+    }
+
+    // The request to serve next. Anything in service blocks its level and those below it, in special
+    // mask mode masked levels in service do not.
+    fn pending(&self) -> Option<u8> {
+        let requests = self.irr & !self.imr;
+        let in_service = if self.special_mask { self.isr & !self.imr } else { self.isr };
+        for level in self.priorities() {
+            if in_service & 1 << level != 0 {
+                return None;
+            }
+            if requests & 1 << level != 0 {
+                return Some(level);
+            }
+        }
+        None
+    }
+
+    fn start_service(&mut self, level: u8) {
+        // In level triggered mode a line still held high requests again straight away.
+        self.irr &= !(1 << level);
+        if self.icw1 & ICW1_LEVEL != 0 {
+            self.irr |= self.lines & 1 << level;
+        }
+        self.isr |= 1 << level;
+        self.acknowledging = level;
+    }
+
+    fn end_of_interrupt(&mut self, level: u8, rotate: bool) {
+        self.isr &= !(1 << level);
+        if rotate {
+            self.lowest = level;
+        }
+    }
+}
+
+impl Default for Pic8259
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
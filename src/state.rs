@@ -0,0 +1,202 @@
+use std::{fmt, fs, io, path::Path};
+
+use crate::cpu::{Registers, CPU8080};
+
+// Every save state starts with this, followed by a little endian u16 version.
+pub const STATE_MAGIC: [u8; 4] = *b"R80S";
+pub const STATE_VERSION: u16 = 3;
+
+// Magic, version, pc, sp, a, b, c, d, e, f, h, l, status bits, the 8085 pins, the Z80 registers, cycles and the bus state length.
+// Version 1 has no 8085 pins and version 2 no Z80 registers, they still load with those at their reset values.
+const HEADER_SIZE: usize = 4 + 2 + 2 + 2 + 8 + 1 + PINS_SIZE + Z80_SIZE + 8 + 4;
+const PINS_SIZE: usize = 2;
+const Z80_SIZE: usize = 16;
+
+const STATUS_INTERRUPTS: u8 = 1 << 0;
+const STATUS_HALTING: u8 = 1 << 1;
+const STATUS_RUNNING: u8 = 1 << 2;
+const STATUS_INTERRUPT_DELAY: u8 = 1 << 3;
+
+// The 8085 pins byte holds the RST masks in bits 0 to 2, it is followed by the sampled interrupt lines.
+const PINS_RST75: u8 = 1 << 3;
+const PINS_TRAP: u8 = 1 << 4;
+const PINS_SERIAL_OUT: u8 = 1 << 5;
+
+// The Z80 block is AF', BC', DE', HL', IX, IY, I, R, the interrupt mode and these bits.
+const Z80_IFF2: u8 = 1 << 0;
+const Z80_NMI_LINE: u8 = 1 << 1;
+const Z80_NMI: u8 = 1 << 2;
+
+#[derive(Debug)]
+pub enum StateError
+{
+    // The data does not start with STATE_MAGIC.
+    BadMagic,
+    // The state was written by a format version this build does not know.
+    UnsupportedVersion { version: u16 },
+    // The data ends before the state does.
+    Truncated { needed: usize, available: usize },
+    // The bus refused its part of the state.
+    InvalidBusState { reason: String },
+    Io(io::Error),
+}
+
+impl fmt::Display for StateError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "not a save state"),
+            Self::UnsupportedVersion { version } => write!(f, "unsupported save state version {} (expected {})", version, STATE_VERSION),
+            Self::Truncated { needed, available } => write!(f, "save state needs {} bytes but only {} are available", needed, available),
+            Self::InvalidBusState { reason } => write!(f, "invalid bus state: {}", reason),
+            Self::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+impl From<io::Error> for StateError
+{
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+// A snapshot of a whole machine: the CPU registers, its cycle counter and whatever the bus saved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaveState
+{
+    pub registers: Registers,
+    pub cycles: u64,
+    pub bus: Vec<u8>
+}
+
+impl SaveState
+{
+    pub fn capture(cpu: &mut dyn CPU8080) -> Self {
+        let bus = cpu.get_bus();
+        let bus = bus.read().unwrap_or_else(|poison| poison.into_inner()).save_state();
+        Self {
+            registers: *cpu.registers(),
+            cycles: cpu.get_executed_cycles(),
+            bus
+        }
+    }
+
+    // The bus gets its state first, so a refused bus state leaves the CPU untouched.
+    pub fn restore(&self, cpu: &mut dyn CPU8080) -> Result<(), StateError> {
+        let bus = cpu.get_bus();
+        bus.write().unwrap_or_else(|poison| poison.into_inner()).load_state(&self.bus)?;
+        *cpu.registers_mut() = self.registers;
+        cpu.set_executed_cycles(self.cycles);
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let registers = &self.registers;
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.bus.len());
+        bytes.extend_from_slice(&STATE_MAGIC);
+        bytes.extend_from_slice(&STATE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&registers.pc.to_le_bytes());
+        bytes.extend_from_slice(&registers.sp.to_le_bytes());
+        bytes.extend_from_slice(&[registers.a, registers.b, registers.c, registers.d, registers.e, registers.f, registers.h, registers.l]);
+
+        let mut status = 0;
+        if registers.interrupts { status |= STATUS_INTERRUPTS; }
+        if registers.halting { status |= STATUS_HALTING; }
+        if registers.running { status |= STATUS_RUNNING; }
+        if registers.interrupt_delay { status |= STATUS_INTERRUPT_DELAY; }
+        bytes.push(status);
+
+        let pins = &registers.pins;
+        let mut latches = pins.masks & 0x07;
+        if pins.rst75 { latches |= PINS_RST75; }
+        if pins.trap { latches |= PINS_TRAP; }
+        if pins.serial_out { latches |= PINS_SERIAL_OUT; }
+        bytes.extend_from_slice(&[latches, pins.lines]);
+
+        let z80 = &registers.z80;
+        for word in [z80.af, z80.bc, z80.de, z80.hl, z80.ix, z80.iy] {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        let mut z80_status = 0;
+        if z80.iff2 { z80_status |= Z80_IFF2; }
+        if z80.nmi_line { z80_status |= Z80_NMI_LINE; }
+        if z80.nmi { z80_status |= Z80_NMI; }
+        bytes.extend_from_slice(&[z80.i, z80.r, z80.interrupt_mode, z80_status]);
+
+        bytes.extend_from_slice(&self.cycles.to_le_bytes());
+        bytes.extend_from_slice(&(self.bus.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.bus);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, StateError> {
+        if bytes.len() < 4 || bytes[..4] != STATE_MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        if bytes.len() < 6 {
+            return Err(StateError::Truncated { needed: HEADER_SIZE, available: bytes.len() });
+        }
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+        if !(1..=STATE_VERSION).contains(&version) {
+            return Err(StateError::UnsupportedVersion { version });
+        }
+        let pins = 19;
+        let z80 = if version >= 2 { pins + PINS_SIZE } else { pins };
+        let cycles = if version >= 3 { z80 + Z80_SIZE } else { z80 };
+        let header_size = cycles + 8 + 4;
+        if bytes.len() < header_size {
+            return Err(StateError::Truncated { needed: header_size, available: bytes.len() });
+        }
+
+        let word = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+        let status = bytes[18];
+        let mut registers = Registers::new();
+        registers.pc = word(6);
+        registers.sp = word(8);
+        [registers.a, registers.b, registers.c, registers.d, registers.e, registers.f, registers.h, registers.l] = bytes[10..18].try_into().unwrap();
+        registers.interrupts = status & STATUS_INTERRUPTS != 0;
+        registers.halting = status & STATUS_HALTING != 0;
+        registers.running = status & STATUS_RUNNING != 0;
+        registers.interrupt_delay = status & STATUS_INTERRUPT_DELAY != 0;
+
+        if version >= 2 {
+            let latches = bytes[pins];
+            registers.pins.masks = latches & 0x07;
+            registers.pins.rst75 = latches & PINS_RST75 != 0;
+            registers.pins.trap = latches & PINS_TRAP != 0;
+            registers.pins.serial_out = latches & PINS_SERIAL_OUT != 0;
+            registers.pins.lines = bytes[pins + 1];
+        }
+
+        if version >= 3 {
+            let z80_status = bytes[36];
+            let z80 = &mut registers.z80;
+            [z80.af, z80.bc, z80.de, z80.hl, z80.ix, z80.iy] = [21, 23, 25, 27, 29, 31].map(word);
+            [z80.i, z80.r, z80.interrupt_mode] = [bytes[33], bytes[34], bytes[35]];
+            z80.iff2 = z80_status & Z80_IFF2 != 0;
+            z80.nmi_line = z80_status & Z80_NMI_LINE != 0;
+            z80.nmi = z80_status & Z80_NMI != 0;
+        }
+
+        let length = u32::from_le_bytes(bytes[cycles + 8..header_size].try_into().unwrap()) as usize;
+        let cycles = u64::from_le_bytes(bytes[cycles..cycles + 8].try_into().unwrap());
+        let needed = header_size + length;
+        if bytes.len() < needed {
+            return Err(StateError::Truncated { needed, available: bytes.len() });
+        }
+
+        Ok(Self { registers, cycles, bus: bytes[header_size..needed].to_vec() })
+    }
+
+    pub fn save_file(&self, path: impl AsRef<Path>) -> Result<(), StateError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn load_file(path: impl AsRef<Path>) -> Result<Self, StateError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
@@ -0,0 +1,140 @@
+use r8080::cpu::alu::{self, AluOutput};
+
+// Flags going in have the unused bits 1, 3 and 5 set, they have to come out untouched.
+const UNUSED: u8 = 0b0010_1010;
+
+// A bit at a time reference, written from the 8080 manual rather than from the module.
+fn bit(value: u8, index: u32) -> bool {
+    value >> index & 1 != 0
+}
+
+// Ripple carry adder, returns the sum and the carry out of every bit position.
+fn ripple(a: u8, b: u8, carry: bool) -> (u8, [bool; 8]) {
+    let mut sum = 0;
+    let mut carries = [false; 8];
+    let mut carry = carry;
+    for index in 0..8 {
+        let (x, y) = (bit(a, index), bit(b, index));
+        sum |= ((x ^ y ^ carry) as u8) << index;
+        carry = (x && y) || (carry && (x ^ y));
+        carries[index as usize] = carry;
+    }
+    (sum, carries)
+}
+
+// S Z - AC - P - CY, the unused bits are filled in from the old flags by the callers.
+fn pack(sign: bool, zero: bool, half_carry: bool, parity: bool, carry: bool) -> u8 {
+    (sign as u8) << 7 | (zero as u8) << 6 | (half_carry as u8) << 4 | (parity as u8) << 2 | carry as u8
+}
+
+fn szp(result: u8) -> (bool, bool, bool) {
+    let ones = (0..8).filter(|index| bit(result, *index)).count();
+    (bit(result, 7), result == 0, ones % 2 == 0)
+}
+
+fn arithmetic(result: u8, half_carry: bool, carry: bool, flags: u8) -> AluOutput {
+    let (sign, zero, parity) = szp(result);
+    AluOutput { result, flags: flags & UNUSED | pack(sign, zero, half_carry, parity, carry) }
+}
+
+fn reference_add(a: u8, b: u8, carry: bool, flags: u8) -> AluOutput {
+    let (sum, carries) = ripple(a, b, carry);
+    arithmetic(sum, carries[3], carries[7], flags)
+}
+
+fn reference_sub(a: u8, b: u8, borrow: bool, flags: u8) -> AluOutput {
+    // Two's complement: A + !B + 1 - borrow, CY is the complement of the adder carry.
+    let (sum, carries) = ripple(a, !b, !borrow);
+    arithmetic(sum, carries[3], !carries[7], flags)
+}
+
+fn reference_logic(result: u8, half_carry: bool, flags: u8) -> AluOutput {
+    arithmetic(result, half_carry, false, flags)
+}
+
+fn carry_of(flags: u8) -> bool {
+    bit(flags, 0)
+}
+
+#[test]
+fn test_add_and_sub_exhaustive()
+{
+    for a in 0..=255u8 {
+        for b in 0..=255u8 {
+            for carry in [false, true] {
+                let flags = UNUSED | carry as u8;
+                assert_eq!(alu::add(a, b, carry, flags), reference_add(a, b, carry, flags), "ADD {:02X} {:02X} {}", a, b, carry);
+                assert_eq!(alu::sub(a, b, carry, flags), reference_sub(a, b, carry, flags), "SUB {:02X} {:02X} {}", a, b, carry);
+
+                // CMP is SUB without a borrow that leaves A alone, whatever CY was before.
+                let compare = reference_sub(a, b, false, flags);
+                assert_eq!(alu::compare(a, b, flags), AluOutput { result: a, flags: compare.flags }, "CMP {:02X} {:02X}", a, b);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_logic_exhaustive()
+{
+    for a in 0..=255u8 {
+        for b in 0..=255u8 {
+            for flags in [UNUSED, 0xFF] {
+                assert_eq!(alu::and(a, b, flags), reference_logic(a & b, bit(a, 3) || bit(b, 3), flags), "ANA {:02X} {:02X}", a, b);
+                assert_eq!(alu::or(a, b, flags), reference_logic(a | b, false, flags), "ORA {:02X} {:02X}", a, b);
+                assert_eq!(alu::xor(a, b, flags), reference_logic(a ^ b, false, flags), "XRA {:02X} {:02X}", a, b);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_increment_and_decrement_exhaustive()
+{
+    for value in 0..=255u8 {
+        for flags in [UNUSED, UNUSED | 1] {
+            // INR adds 1, DCR adds FFH, neither touches CY.
+            let (sum, carries) = ripple(value, 0x01, false);
+            assert_eq!(alu::increment(value, flags), arithmetic(sum, carries[3], carry_of(flags), flags), "INR {:02X}", value);
+            let (sum, carries) = ripple(value, 0xFF, false);
+            assert_eq!(alu::decrement(value, flags), arithmetic(sum, carries[3], carry_of(flags), flags), "DCR {:02X}", value);
+        }
+    }
+}
+
+#[test]
+fn test_daa_exhaustive()
+{
+    for a in 0..=255u8 {
+        for (half_carry, carry) in [(false, false), (false, true), (true, false), (true, true)] {
+            let flags = UNUSED | (half_carry as u8) << 4 | carry as u8;
+
+            // Correct the low nibble first, then look at the high nibble of the corrected value.
+            let low = if a & 0x0F > 9 || half_carry { 0x06 } else { 0x00 };
+            let (first, first_carries) = ripple(a, low, false);
+            let high = if first >> 4 > 9 || carry || first_carries[7] { 0x60 } else { 0x00 };
+            let (result, _) = ripple(first, high, false);
+            let expected = arithmetic(result, first_carries[3], carry || high != 0, flags);
+
+            assert_eq!(alu::daa(a, flags), expected, "DAA {:02X} AC={} CY={}", a, half_carry, carry);
+        }
+    }
+}
+
+#[test]
+fn test_rotate_and_add16()
+{
+    for value in 0..=255u8 {
+        for carry in [false, true] {
+            let flags = UNUSED | carry as u8;
+            let with_carry = |result: u8, carry: bool| AluOutput { result, flags: UNUSED | carry as u8 };
+            assert_eq!(alu::rotate(value, false, false, flags), with_carry(value.rotate_left(1), bit(value, 7)));
+            assert_eq!(alu::rotate(value, true, false, flags), with_carry(value.rotate_right(1), bit(value, 0)));
+            assert_eq!(alu::rotate(value, false, true, flags), with_carry(value << 1 | carry as u8, bit(value, 7)));
+            assert_eq!(alu::rotate(value, true, true, flags), with_carry(value >> 1 | (carry as u8) << 7, bit(value, 0)));
+        }
+    }
+
+    assert_eq!(alu::add16(0xFFFF, 0x0002, UNUSED), (0x0001, UNUSED | 1));
+    assert_eq!(alu::add16(0x1234, 0x1111, UNUSED | 1), (0x2345, UNUSED));
+}
@@ -0,0 +1,83 @@
+mod buses;
+
+use std::sync::{Arc, RwLock};
+
+use buses::TestCPMBus;
+use r8080::{asm::{assemble, assemble_for, AsmError}, cpu::{Interpreter8080, Model, CPU8080}, Bus8080};
+
+#[test]
+fn test_assemble_instructions_and_directives()
+{
+    let program = assemble("
+STACK   EQU 0200H
+        ORG 100H
+START:  LXI SP,STACK
+        MVI A,LOW(TABLE + 1)
+        MVI B,HIGH TABLE
+        MOV M,A
+        RST 7
+        JMP $
+TABLE:  DB 1, 'AB', -1
+        DW START, 'AB'
+        DS 2
+END_:   NOP
+    ").unwrap();
+
+    assert_eq!(program.origin, 0x0100);
+    assert_eq!(program.bytes, [
+        0x31, 0x00, 0x02,
+        0x3E, 0x0D,
+        0x06, 0x01,
+        0x77,
+        0xFF,
+        0xC3, 0x09, 0x01,
+        0x01, 0x41, 0x42, 0xFF,
+        0x00, 0x01, 0x42, 0x41,
+        0x00, 0x00,
+        0x00,
+    ]);
+    assert_eq!(program.symbols["TABLE"], 0x010C);
+    assert_eq!(program.symbols["STACK"], 0x0200);
+    assert_eq!(program.symbols["END_"], 0x0116);
+}
+
+#[test]
+fn test_assemble_reports_line_and_column()
+{
+    assert_eq!(assemble("  NOP\n  MVI A,UNKNOWN").unwrap_err(), AsmError { line: 2, column: 9, message: "undefined symbol 'UNKNOWN'".to_string() });
+    assert_eq!(assemble("  FOO A").unwrap_err().column, 3);
+    assert_eq!(assemble("  MOV A,SP").unwrap_err().message, "invalid operands for MOV");
+    assert_eq!(assemble("  MVI A,256").unwrap_err().message, "value 256 does not fit in a byte");
+    assert_eq!(assemble("X: NOP\nX: NOP").unwrap_err(), AsmError { line: 2, column: 1, message: "symbol 'X' is already defined".to_string() });
+}
+
+#[test]
+fn test_8085_instructions_need_the_8085()
+{
+    let source = "  RIM\n  SIM\n  DSUB\n  JK 1234H\n  LDSI 12H";
+    assert_eq!(assemble(source).unwrap_err(), AsmError { line: 1, column: 3, message: "'RIM' is an 8085 instruction".to_string() });
+    assert_eq!(assemble("  JNK 0").unwrap_err().message, "'JNK' is an 8085 instruction");
+    assert_eq!(assemble_for(source, Model::Intel8080), assemble(source));
+    assert_eq!(assemble_for(source, Model::Intel8085).unwrap().bytes, [0x20, 0x30, 0x08, 0xFD, 0x34, 0x12, 0x38, 0x12]);
+}
+
+#[test]
+fn test_assembled_program_runs()
+{
+    let program = assemble("
+        ORG 0100H
+        LXI D,MESSAGE
+        MVI C,9
+        CALL 5
+        JMP 0
+MESSAGE DB 'Hello, 8080!$'
+    ").unwrap();
+
+    let mut bus = Box::new(TestCPMBus::new("Hello, 8080!"));
+    bus.write_buffer(program.origin, program.bytes);
+
+    let mut cpu = Interpreter8080::new();
+    cpu.force_jump(0x100);
+    cpu.set_bus(Arc::new(RwLock::new(bus)));
+    cpu.run().unwrap();
+}
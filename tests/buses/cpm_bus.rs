@@ -1,10 +1,13 @@
-use r8080::{cpu::{Register16, Registers}, Bus8080};
+use std::collections::VecDeque;
+
+use r8080::{asm::assemble, cpu::{Register16, Registers}, state::StateError, Bus8080};
 
 pub struct TestCPMBus
 {
     ram: [u8; 0x10000],
     output: Vec<u8>,
-    expected_output: &'static str
+    expected_output: &'static str,
+    interrupts: VecDeque<u8>
 }
 
 impl TestCPMBus
@@ -13,11 +16,18 @@ impl TestCPMBus
         let mut result = Self {
             ram: [0x00; 0x10000],
             output: Vec::new(),
-            expected_output
+            expected_output,
+            interrupts: VecDeque::new()
         };
 
-        result.write_buffer(0x0000, [0xD3, 0x00].to_vec());         // Stop.
-        result.write_buffer(0x0005, [0xD3, 0x01, 0xC9].to_vec());   // Print and ret.
+        let bios = assemble("
+            ORG 0000H
+            OUT 0       ; Stop.
+            ORG 0005H
+            OUT 1       ; Print and ret.
+            RET
+        ").unwrap();
+        result.write_buffer(bios.origin, bios.bytes);
 
         result
     }
@@ -49,15 +59,20 @@ impl Drop for TestCPMBus {
 impl Bus8080 for TestCPMBus
 {
     fn get_interrupt(&mut self) -> u8 {
-        0x00
+        self.interrupts.pop_front().unwrap_or(0x00)
+    }
+
+    // Every pushed byte is one INTA cycle, so a CALL is pushed as its three bytes.
+    fn acknowledge_interrupt(&mut self, _: u8) -> u8 {
+        self.interrupts.pop_front().unwrap_or(0xFF)
     }
     
     fn has_interrupt(&self) -> bool {
-        false
+        !self.interrupts.is_empty()
     }
 
-    fn push_interrupt(&mut self, _: u8) {
-        
+    fn push_interrupt(&mut self, b: u8) {
+        self.interrupts.push_back(b);
     }
 
     fn in_b(&mut self, _: &mut Registers, _: u8) -> u8 {
@@ -117,4 +132,16 @@ impl Bus8080 for TestCPMBus
     fn write_buffer(&mut self, a: u16, data: Vec<u8>) {
         self.ram[a as usize..a as usize + data.len()].copy_from_slice(data.as_slice());
     }
-}
\ No newline at end of file
+
+    fn save_state(&self) -> Vec<u8> {
+        self.ram.to_vec()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        if state.len() != self.ram.len() {
+            return Err(StateError::InvalidBusState { reason: format!("expected {} bytes of RAM, got {}", self.ram.len(), state.len()) });
+        }
+        self.ram.copy_from_slice(state);
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+// The test bus is upstream code, its explicit returns are left as written.
+#[allow(clippy::needless_return)]
 mod cpm_bus;
 
 pub type TestCPMBus = cpm_bus::TestCPMBus;
@@ -0,0 +1,162 @@
+mod buses;
+
+use std::sync::{Arc, RwLock};
+
+use buses::TestCPMBus;
+use r8080::{asm::assemble_for, cpu::{Interpreter8080, Model, CPU8080}, debugger::{Debugger, StopReason, WatchKind}, Bus8080};
+
+const PROGRAM: &str = "
+        ORG 0100H
+        LXI SP,0200H
+        MVI A,5
+        CALL SUB
+        STA 0180H
+        OUT 0
+SUB:    INR A
+        PUSH B
+        POP B
+        RET
+";
+
+fn debugger() -> Debugger {
+    debugger_for(PROGRAM, Model::Intel8080)
+}
+
+fn debugger_for(source: &str, model: Model) -> Debugger {
+    let program = assemble_for(source, model).unwrap();
+    let mut bus = Box::new(TestCPMBus::new(""));
+    bus.write_buffer(program.origin, program.bytes);
+
+    let mut cpu = Interpreter8080::new();
+    cpu.set_model(model);
+    cpu.force_jump(0x100);
+    cpu.set_bus(Arc::new(RwLock::new(bus)));
+    Debugger::new(cpu)
+}
+
+#[test]
+fn test_breakpoints_and_watches()
+{
+    let mut debugger = debugger();
+    debugger.add_breakpoint(0x010D);
+    assert_eq!(debugger.resume(), Ok(StopReason::Breakpoint { address: 0x010D }));
+    assert_eq!(debugger.registers().a, 5);
+
+    assert_eq!(debugger.step_out(), Ok(StopReason::Step));
+    assert_eq!(debugger.registers().pc, 0x0108);
+
+    let memory = debugger.add_memory_watch(0x0180..=0x0181, WatchKind::Write);
+    let port = debugger.add_port_watch(0x00, WatchKind::ReadWrite);
+    assert_eq!(debugger.resume(), Ok(StopReason::MemoryWatch { id: memory, address: 0x0180, write: true }));
+    assert_eq!(debugger.registers().pc, 0x010B);
+    assert_eq!(debugger.resume(), Ok(StopReason::PortWatch { id: port, port: 0x00, write: true }));
+    assert_eq!(debugger.resume(), Ok(StopReason::Stopped));
+}
+
+#[test]
+fn test_step_over_and_conditions()
+{
+    let mut debugger = debugger();
+    debugger.add_conditional_breakpoint(0x010E, Box::new(|registers| registers.a == 7));
+    let stack_watch = debugger.add_memory_watch(0x01FC..=0x01FD, WatchKind::Read);
+
+    assert_eq!(debugger.step(), Ok(StopReason::Step));
+    assert_eq!(debugger.step(), Ok(StopReason::Step));
+    // The POP inside the call reads back what PUSH wrote.
+    assert_eq!(debugger.step_over(), Ok(StopReason::MemoryWatch { id: stack_watch, address: 0x01FC, write: false }));
+    debugger.remove_watch(stack_watch);
+
+    let mut debugger = self::debugger();
+    let condition = debugger.add_condition(Box::new(|registers| registers.a == 6));
+    debugger.step().unwrap();
+    debugger.step().unwrap();
+    assert_eq!(debugger.step_over(), Ok(StopReason::Condition { id: condition }));
+    assert_eq!(debugger.registers().pc, 0x010E);
+    debugger.remove_condition(condition);
+
+    assert_eq!(debugger.step_out(), Ok(StopReason::Step));
+    assert_eq!((debugger.registers().pc, debugger.registers().a), (0x0108, 6));
+}
+
+#[test]
+fn test_step_out_with_stack_at_top_of_memory()
+{
+    // The RET moves SP from FFFEH around to 0000H.
+    let mut debugger = debugger_for("
+            ORG 0100H
+            LXI SP,0000H
+            CALL SUB
+            MVI A,1
+            OUT 0
+    SUB:    NOP
+            RET
+    ", Model::Intel8080);
+    debugger.add_breakpoint(0x010A);
+    assert_eq!(debugger.resume(), Ok(StopReason::Breakpoint { address: 0x010A }));
+    assert_eq!(debugger.registers().sp, 0xFFFE);
+
+    assert_eq!(debugger.step_out(), Ok(StopReason::Step));
+    assert_eq!((debugger.registers().pc, debugger.registers().sp), (0x0106, 0x0000));
+}
+
+#[test]
+fn test_8085_instructions()
+{
+    let mut debugger = debugger_for("
+            ORG 0040H       ; RSTV.
+            RET
+
+            ORG 0100H
+            LXI SP,0200H
+            LXI B,0FFFFH
+            INX B           ; Wraps, sets K.
+            JK SKIP
+            OUT 0
+    SKIP:   MVI A,7FH
+            INR A           ; Overflows, sets V.
+            RSTV
+            LXI H,1234H
+            LXI D,0180H
+            SHLX
+            OUT 0
+    ", Model::Intel8085);
+    for _ in 0..3 {
+        debugger.step().unwrap();
+    }
+
+    // JK is a jump and RSTV a call, not the 8080 CALL and JMP aliases.
+    assert_eq!(debugger.step_over(), Ok(StopReason::Step));
+    assert_eq!(debugger.registers().pc, 0x010C);
+    debugger.step().unwrap();
+    debugger.step().unwrap();
+    assert_eq!(debugger.step_over(), Ok(StopReason::Step));
+    assert_eq!((debugger.registers().pc, debugger.registers().sp), (0x0110, 0x0200));
+
+    // SHLX stores HL at (DE).
+    let watch = debugger.add_memory_watch(0x0180..=0x0181, WatchKind::Write);
+    assert_eq!(debugger.resume(), Ok(StopReason::MemoryWatch { id: watch, address: 0x0180, write: true }));
+    assert_eq!(debugger.registers().pc, 0x0117);
+}
+
+#[test]
+fn test_reverse_execution()
+{
+    let mut debugger = debugger();
+    debugger.cpu_mut().enable_history(100, 0);
+    assert_eq!(debugger.resume(), Ok(StopReason::Stopped));
+
+    assert_eq!(debugger.step_back(), StopReason::Step);
+    assert_eq!(debugger.registers().pc, 0x010B);
+
+    // Undoing the STA puts the old value back.
+    let watch = debugger.add_memory_watch(0x0180..=0x0180, WatchKind::Write);
+    assert_eq!(debugger.reverse_continue(), StopReason::MemoryWatch { id: watch, address: 0x0180, write: true });
+    assert_eq!(debugger.registers().pc, 0x0108);
+    debugger.remove_watch(watch);
+
+    debugger.add_breakpoint(0x010D);
+    assert_eq!(debugger.reverse_continue(), StopReason::Breakpoint { address: 0x010D });
+    assert_eq!(debugger.registers().a, 5);
+    assert_eq!(debugger.reverse_continue(), StopReason::HistoryExhausted);
+    assert_eq!(debugger.registers().pc, 0x0100);
+}
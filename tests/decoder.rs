@@ -0,0 +1,54 @@
+use r8080::{cpu::{DecodeError, Instruction8080, InstructionAction, InstructionType, Model, Register16}, disassembler::{disassemble_bytes, Syntax}};
+
+#[test]
+fn test_decode_from_slice()
+{
+    let (instruction, length) = Instruction8080::decode(&[0x21, 0x34, 0x12, 0x00]).unwrap();
+    assert_eq!(length, 3);
+    assert!(matches!(instruction.action, InstructionAction::Load16 { register: Register16::HL }));
+    assert_eq!(instruction.target, InstructionType::Immediate16 { value: 0x1234 });
+
+    // Conditional returns do not read past their opcode.
+    let (instruction, length) = Instruction8080::decode(&[0xC0]).unwrap();
+    assert_eq!(length, 1);
+    assert_eq!(instruction.target, InstructionType::None);
+}
+
+#[test]
+fn test_decode_truncated_input()
+{
+    assert_eq!(Instruction8080::decode(&[]).unwrap_err(), DecodeError::Empty);
+    assert_eq!(
+        Instruction8080::decode(&[0xCD, 0x00]).unwrap_err(),
+        DecodeError::Truncated { opcode: 0xCD, needed: 3, available: 2 }
+    );
+}
+
+#[test]
+fn test_decode_table_matches_decoder()
+{
+    // The table only differs from a full decode in the immediates, which it leaves zeroed.
+    for (opcode, entry) in Instruction8080::decode_table().iter().enumerate() {
+        let (instruction, length) = Instruction8080::decode(&[opcode as u8, 0x00, 0x00]).unwrap();
+        assert_eq!(*entry, instruction, "opcode {:02X}", opcode);
+        assert_eq!(entry.length as usize, length);
+        match entry.length {
+            2 => assert!(matches!(entry.target, InstructionType::Immediate8 { value: 0 }), "opcode {:02X}", opcode),
+            3 => assert!(matches!(entry.target, InstructionType::Immediate16 { value: 0 }), "opcode {:02X}", opcode),
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn test_disassemble_bytes()
+{
+    let lines: Vec<String> = disassemble_bytes(&[0x3E, 0x01, 0x76, 0xC3, 0x00], 0x0100, Model::Intel8080, Syntax::Intel)
+        .iter().map(|line| line.to_string()).collect();
+    assert_eq!(lines, [
+        "0100  3E 01     MVI A,01H",
+        "0102  76        HLT",
+        "0103  C3        DB 0C3H",
+        "0104  00        NOP",
+    ]);
+}
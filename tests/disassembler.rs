@@ -0,0 +1,114 @@
+mod buses;
+
+use buses::TestCPMBus;
+use r8080::{cpu::{Instruction8080, Model}, disassembler::{disassemble, disassemble_bytes, format_instruction, Syntax}, Bus8080};
+
+fn decode(bytes: &[u8]) -> Instruction8080 {
+    let mut bus = TestCPMBus::new("");
+    bus.write_buffer(0x0100, bytes.to_vec());
+    Instruction8080::from_opcode(bytes[0], 0x0100, &bus)
+}
+
+#[test]
+fn test_intel_mnemonics()
+{
+    let cases: [(&[u8], &str); 16] = [
+        (&[0x3E, 0x3F], "MVI A,3FH"),
+        (&[0x21, 0x34, 0x12], "LXI H,1234H"),
+        (&[0xFF], "RST 7"),
+        (&[0xE9], "PCHL"),
+        (&[0xF9], "SPHL"),
+        (&[0x76], "HLT"),
+        (&[0x70], "MOV M,B"),
+        (&[0xC3, 0xFF, 0xFF], "JMP 0FFFFH"),
+        (&[0xCC, 0x00, 0x01], "CZ 0100H"),
+        (&[0xF0], "RP"),
+        (&[0xF5], "PUSH PSW"),
+        (&[0x1A], "LDAX D"),
+        (&[0x9E], "SBB M"),
+        (&[0xDE, 0xA0], "SBI 0A0H"),
+        (&[0x17], "RAL"),
+        (&[0xDB, 0x10], "IN 10H"),
+    ];
+
+    for (bytes, text) in cases {
+        assert_eq!(decode(bytes).to_string(), text);
+    }
+}
+
+#[test]
+fn test_zilog_mnemonics()
+{
+    let cases: [(&[u8], &str); 8] = [
+        (&[0x3E, 0x3F], "LD A,3FH"),
+        (&[0x21, 0x34, 0x12], "LD HL,1234H"),
+        (&[0xFF], "RST 38H"),
+        (&[0xE9], "JP (HL)"),
+        (&[0x70], "LD (HL),B"),
+        (&[0x8F], "ADC A,A"),
+        (&[0xE3], "EX (SP),HL"),
+        (&[0xD3, 0x01], "OUT (01H),A"),
+    ];
+
+    for (bytes, text) in cases {
+        assert_eq!(format_instruction(&decode(bytes), Syntax::Zilog), text);
+    }
+}
+
+#[test]
+fn test_z80_only_mnemonics()
+{
+    let cases: [(&[u8], &str); 5] = [
+        (&[0x08], "EX AF,AF'"),
+        (&[0xD9], "EXX"),
+        (&[0x10, 0x05], "DJNZ $+7"),
+        (&[0x18, 0xFE], "JR $+0"),
+        (&[0x20, 0xFA], "JR NZ,$-4"),
+    ];
+
+    for (bytes, text) in cases {
+        let (instruction, length) = Instruction8080::decode_z80(bytes).unwrap();
+        assert_eq!(length, bytes.len());
+        assert_eq!(format_instruction(&instruction, Syntax::Zilog), text);
+    }
+    // Without the Z80 table they stay the 8080 aliases.
+    assert_eq!(format_instruction(&decode(&[0x08]), Syntax::Zilog), "NOP");
+}
+
+#[test]
+fn test_disassemble_range()
+{
+    let mut bus = TestCPMBus::new("");
+    bus.write_buffer(0x0100, [0x31, 0x00, 0x02, 0x3E, 0x41, 0xD3, 0x01, 0xC9].to_vec());
+
+    let lines: Vec<String> = disassemble(&bus, 0x0100..=0x0107, Model::Intel8080, Syntax::Intel).iter().map(|line| line.to_string()).collect();
+    assert_eq!(lines, [
+        "0100  31 00 02  LXI SP,0200H",
+        "0103  3E 41     MVI A,41H",
+        "0105  D3 01     OUT 01H",
+        "0107  C9        RET",
+    ]);
+}
+
+// The same bytes are 8085 instructions or 8080 aliases depending on the model.
+#[test]
+fn test_disassemble_8085()
+{
+    let bytes = [0x20, 0x30, 0x08, 0xDD, 0x34, 0x12, 0xCB];
+    let mut bus = TestCPMBus::new("");
+    bus.write_buffer(0x0100, bytes.to_vec());
+
+    let lines: Vec<String> = disassemble(&bus, 0x0100..=0x0106, Model::Intel8085, Syntax::Intel).iter().map(|line| line.to_string()).collect();
+    assert_eq!(lines, [
+        "0100  20        RIM",
+        "0101  30        SIM",
+        "0102  08        DSUB",
+        "0103  DD 34 12  JNK 1234H",
+        "0106  CB        RSTV",
+    ]);
+    let lines: Vec<String> = disassemble_bytes(&bytes, 0x0100, Model::Intel8085, Syntax::Intel).iter().map(|line| line.to_string()).collect();
+    assert_eq!(lines[4], "0106  CB        RSTV");
+
+    let lines: Vec<String> = disassemble(&bus, 0x0100..=0x0106, Model::Intel8080, Syntax::Intel).iter().map(|line| line.text.clone()).collect();
+    assert_eq!(lines, ["NOP", "NOP", "NOP", "CALL 1234H", "JMP 0000H"]);
+}
@@ -0,0 +1,45 @@
+use r8080::cpu::{Condition, EncodeError, Instruction8080, InstructionAction, InstructionType, Register16, Register8};
+
+// Opcodes the 8080 decodes like another one, along with the documented opcode they alias.
+const ALIASES: [(u8, u8); 12] = [
+    (0x08, 0x00), (0x10, 0x00), (0x18, 0x00), (0x20, 0x00), (0x28, 0x00), (0x30, 0x00), (0x38, 0x00),
+    (0xCB, 0xC3), (0xD9, 0xC9), (0xDD, 0xCD), (0xED, 0xCD), (0xFD, 0xCD),
+];
+
+#[test]
+fn test_round_trip_all_opcodes()
+{
+    for opcode in 0x00..=0xFF {
+        let bytes = [opcode, 0x34, 0x12];
+        let (instruction, length) = Instruction8080::decode(&bytes).unwrap();
+        let encoded = instruction.to_bytes().unwrap_or_else(|error| panic!("0x{:02X}: {}", opcode, error));
+
+        let expected_opcode = ALIASES.iter().find(|(alias, _)| *alias == opcode).map_or(opcode, |(_, documented)| *documented);
+        assert_eq!(encoded[0], expected_opcode, "opcode 0x{:02X} encodes as 0x{:02X}", opcode, encoded[0]);
+        assert_eq!(encoded[1..], bytes[1..length], "operands of 0x{:02X}", opcode);
+
+        let (decoded, _) = Instruction8080::decode(&encoded).unwrap();
+        assert_eq!((decoded.action, decoded.target), (instruction.action, instruction.target), "opcode 0x{:02X}", opcode);
+    }
+}
+
+#[test]
+fn test_encode_synthesized_instructions()
+{
+    let encode = |action, target| Instruction8080::encode(&action, &target);
+
+    assert_eq!(encode(InstructionAction::MovReg { register: Register8::A }, InstructionType::Immediate8 { value: 0x3F }), Ok(vec![0x3E, 0x3F]));
+    assert_eq!(encode(InstructionAction::Call { condition: Condition::Minus }, InstructionType::Immediate16 { value: 0x1234 }), Ok(vec![0xFC, 0x34, 0x12]));
+    assert_eq!(encode(InstructionAction::Restart { vector: 7 }, InstructionType::None), Ok(vec![0xFF]));
+    assert_eq!(encode(InstructionAction::Restart { vector: 8 }, InstructionType::None), Err(EncodeError::InvalidRestart { vector: 8 }));
+
+    let unencodable = [
+        (InstructionAction::MovReg { register: Register8::M }, InstructionType::Register8 { register: Register8::M }),
+        (InstructionAction::Push16 { register: Register16::SP }, InstructionType::None),
+        (InstructionAction::SetCarry { value: false }, InstructionType::None),
+        (InstructionAction::None, InstructionType::None),
+    ];
+    for (action, target) in unencodable {
+        assert_eq!(encode(action, target), Err(EncodeError::Unencodable { action, target }));
+    }
+}
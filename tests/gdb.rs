@@ -0,0 +1,128 @@
+mod buses;
+
+use std::{io::{self, Read, Write}, sync::{Arc, RwLock}};
+
+use buses::TestCPMBus;
+use r8080::{asm::assemble, cpu::{Interpreter8080, CPU8080}, debugger::Debugger, gdb::GdbStub, Bus8080};
+
+// Feeds the stub a fixed script of packets and collects whatever it answers.
+struct ScriptedStream
+{
+    input: io::Cursor<Vec<u8>>,
+    output: Vec<u8>
+}
+
+impl Read for ScriptedStream
+{
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buffer)
+    }
+}
+
+impl Write for ScriptedStream
+{
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        self.output.write(buffer)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn packet(data: &str) -> String {
+    let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+    format!("${}#{:02x}", data, checksum)
+}
+
+fn session(packets: &[&str]) -> Vec<String> {
+    let program = assemble("
+            ORG 0100H
+            LXI SP,0200H
+            MVI A,5
+            STA 0180H
+            INR A
+            OUT 0
+    ").unwrap();
+    let mut bus = Box::new(TestCPMBus::new(""));
+    bus.write_buffer(program.origin, program.bytes);
+
+    let mut cpu = Interpreter8080::new();
+    cpu.force_jump(0x100);
+    cpu.set_bus(Arc::new(RwLock::new(bus)));
+    let mut stub = GdbStub::new(Debugger::new(cpu));
+
+    let input: String = packets.iter().map(|data| packet(data) + "+").collect();
+    let mut stream = ScriptedStream { input: io::Cursor::new(input.into_bytes()), output: Vec::new() };
+    stub.serve(&mut stream).unwrap();
+
+    // Split the replies back up, dropping acknowledgements and checksums.
+    let output = String::from_utf8(stream.output).unwrap();
+    output.split('$').skip(1).map(|reply| reply.split('#').next().unwrap().to_string()).collect()
+}
+
+#[test]
+fn test_registers_and_memory()
+{
+    let replies = session(&[
+        "?",
+        "g",
+        "P0=42",
+        "p0",
+        "p9",
+        "G0102030405020607",
+        "G01020304050206070002ff00",
+        "g",
+        "M180,2:abcd",
+        "m17f,4",
+        "qXfer:features:read:target.xml:0,e",
+        "vMustReplyEmpty",
+        "D"
+    ]);
+    assert_eq!(replies, [
+        "S05",
+        "000000000002000000000001",
+        "OK",
+        "42",
+        "0001",
+        "E01",
+        "OK",
+        "01020304050206070002ff00",
+        "OK",
+        "00abcd00",
+        "m<?xml version=",
+        "",
+        "OK"
+    ]);
+}
+
+#[test]
+fn test_breakpoints_and_execution()
+{
+    let replies = session(&[
+        "Z0,105,1",
+        "c",
+        "p0",
+        "z0,105,1",
+        "Z2,180,1",
+        "c",
+        "s",
+        "p9",
+        "z2,180,1",
+        "c",
+        "k"
+    ]);
+    assert_eq!(replies, [
+        "OK",
+        "S05",
+        "05",
+        "OK",
+        "OK",
+        "T05watch:0180;",
+        "S05",
+        "0901",
+        "OK",
+        "W00",
+        "OK"
+    ]);
+}
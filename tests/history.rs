@@ -0,0 +1,110 @@
+mod buses;
+
+use std::sync::{Arc, RwLock};
+
+use buses::TestCPMBus;
+use r8080::{asm::assemble, cpu::{Interpreter8080, Registers, CPU8080}, Bus8080};
+
+// Patches its own MVI operand on every pass, then calls a subroutine that pushes and stores.
+const PROGRAM: &str = "
+        ORG 0100H
+        LXI SP,0200H
+        LXI H,0180H
+LOOP:   MVI A,0
+        INR A
+        STA LOOP+1
+        MOV M,A
+        INX H
+        CALL SAVE
+        CPI 8
+        JNZ LOOP
+        OUT 0
+SAVE:   PUSH H
+        SHLD 0190H
+        XTHL
+        POP H
+        RET
+";
+
+fn machine() -> Interpreter8080 {
+    let program = assemble(PROGRAM).unwrap();
+    let mut bus = Box::new(TestCPMBus::new(""));
+    bus.write_buffer(program.origin, program.bytes);
+
+    let mut cpu = Interpreter8080::new();
+    cpu.force_jump(program.origin);
+    cpu.set_bus(Arc::new(RwLock::new(bus)));
+    cpu
+}
+
+fn state(cpu: &mut Interpreter8080) -> (Registers, u64, Vec<u8>) {
+    let memory = (0x0100..0x0200).map(|address| cpu.get_bus().read().unwrap().read_b(address)).collect();
+    (*cpu.registers(), cpu.get_executed_cycles(), memory)
+}
+
+fn run_recording(cpu: &mut Interpreter8080) -> Vec<(Registers, u64, Vec<u8>)> {
+    let mut states = vec![state(cpu)];
+    while cpu.is_running() {
+        cpu.step().unwrap();
+        states.push(state(cpu));
+    }
+    states
+}
+
+#[test]
+fn test_step_back_undoes_every_instruction()
+{
+    let mut cpu = machine();
+    cpu.enable_history(1000, 0);
+    let mut states = run_recording(&mut cpu);
+    assert!(states.len() > 50);
+
+    states.pop();
+    while let Some(expected) = states.pop() {
+        assert!(cpu.step_back());
+        assert_eq!(state(&mut cpu), expected);
+    }
+    assert!(!cpu.step_back());
+}
+
+#[test]
+fn test_rewind_through_snapshots_matches_the_journal()
+{
+    let mut cpu = machine();
+    cpu.enable_history(1000, 7);
+    let states = run_recording(&mut cpu);
+
+    let mut position = states.len() - 1;
+    for distance in [1, 9, 14, 3, 30] {
+        assert_eq!(cpu.rewind(distance), distance);
+        position -= distance;
+        assert_eq!(state(&mut cpu), states[position]);
+    }
+
+    // Running forward again records over the rewound instructions.
+    for _ in 0..20 {
+        cpu.step().unwrap();
+    }
+    position += 20;
+    assert_eq!(state(&mut cpu), states[position]);
+    assert_eq!(cpu.rewind(usize::MAX), position);
+    assert_eq!(state(&mut cpu), states[0]);
+}
+
+#[test]
+fn test_history_is_bounded()
+{
+    let mut cpu = machine();
+    cpu.enable_history(10, 4);
+    let states = run_recording(&mut cpu);
+
+    let history = cpu.history().unwrap();
+    assert_eq!(history.len(), 10);
+    // What was A before the last few instructions.
+    assert_eq!(history.registers_before(0).unwrap().a, states[states.len() - 2].0.a);
+    assert_eq!(history.registers_before(9), Some(&states[states.len() - 11].0));
+    assert_eq!(history.registers_before(10), None);
+
+    assert_eq!(cpu.rewind(50), 10);
+    assert_eq!(state(&mut cpu), states[states.len() - 11]);
+}
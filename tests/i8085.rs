@@ -0,0 +1,261 @@
+use r8080::{asm::{assemble_for, Program}, cpu::{Interpreter8080, Model, Registers, LINE_RST55, LINE_RST65, LINE_RST75, LINE_TRAP}, state::SaveState, Bus8080};
+
+// Flat RAM with the 8085 interrupt inputs and serial pins, OUT 0FFH stops.
+struct PinBus
+{
+    ram: Vec<u8>,
+    lines: u8,
+    sid: bool,
+    sod: Vec<bool>
+}
+
+impl Bus8080 for PinBus
+{
+    fn read_b(&self, a: u16) -> u8 {
+        self.ram[a as usize]
+    }
+
+    fn read_w(&self, a: u16) -> u16 {
+        u16::from_le_bytes([self.read_b(a), self.read_b(a.wrapping_add(1))])
+    }
+
+    fn has_interrupt(&self) -> bool {
+        false
+    }
+
+    fn get_interrupt(&mut self) -> u8 {
+        0xFF
+    }
+
+    fn push_interrupt(&mut self, _: u8) {}
+
+    fn write_b(&mut self, a: u16, b: u8) {
+        self.ram[a as usize] = b;
+    }
+
+    fn write_w(&mut self, a: u16, w: u16) {
+        self.write_b(a, w as u8);
+        self.write_b(a.wrapping_add(1), (w >> 8) as u8);
+    }
+
+    fn in_b(&mut self, _: &mut Registers, _: u8) -> u8 {
+        0xFF
+    }
+
+    fn out_b(&mut self, regs: &mut Registers, b: u8, _: u8) {
+        if b == 0xFF {
+            regs.running = false;
+        }
+    }
+
+    fn write_buffer(&mut self, a: u16, data: Vec<u8>) {
+        self.ram[a as usize..a as usize + data.len()].copy_from_slice(&data);
+    }
+
+    fn interrupt_lines(&self) -> u8 {
+        self.lines
+    }
+
+    fn serial_in(&self) -> bool {
+        self.sid
+    }
+
+    fn serial_out(&mut self, level: bool) {
+        self.sod.push(level);
+    }
+}
+
+// Every program starts at 0100H, below it are the restart vectors.
+fn cpu(program: &Program, model: Model) -> Interpreter8080<PinBus> {
+    let mut bus = PinBus { ram: vec![0; 0x10000], lines: 0, sid: false, sod: Vec::new() };
+    bus.write_buffer(program.origin, program.bytes.clone());
+    let mut cpu = Interpreter8080::with_bus(bus);
+    cpu.set_model(model);
+    cpu.force_jump(0x0100);
+    cpu
+}
+
+#[test]
+fn test_rim_sim_and_serial_pins()
+{
+    let program = assemble_for("
+            ORG 0100H
+            RIM
+            MOV B,A
+            MVI A,0C8H      ; SOD high, unmask everything.
+            SIM
+            RIM
+            MOV C,A
+            MVI A,40H       ; SOD low, masks untouched.
+            SIM
+            OUT 0FFH
+    ", Model::Intel8085).unwrap();
+
+    let mut cpu = cpu(&program, Model::Intel8085);
+    cpu.bus_mut().sid = true;
+    cpu.bus_mut().lines = LINE_RST65;
+    cpu.run().unwrap();
+
+    // SID, RST 6.5 pending and all three masks set out of reset, then the masks cleared by SIM.
+    let registers = cpu.registers();
+    assert_eq!((registers.b, registers.c), (0xA7, 0xA0));
+    assert_eq!(registers.pins.masks, 0x00);
+    assert_eq!(cpu.bus().sod, [true, false]);
+
+    // On the 8080 both are NOPs.
+    let mut cpu = self::cpu(&program, Model::Intel8080);
+    cpu.bus_mut().sid = true;
+    cpu.run().unwrap();
+    assert_eq!((cpu.registers().b, cpu.registers().c), (0x00, 0xC8));
+    assert!(cpu.bus().sod.is_empty());
+}
+
+#[test]
+fn test_vectored_interrupts()
+{
+    let program = assemble_for("
+            ORG 0100H
+            LXI SP,0200H
+            MVI A,08H       ; Unmask RST 5.5 / 6.5 / 7.5.
+            SIM
+            EI
+    LOOP:   NOP
+            JMP LOOP
+    ", Model::Intel8085).unwrap();
+
+    let mut cpu = cpu(&program, Model::Intel8085);
+    cpu.bus_mut().lines = LINE_RST55 | LINE_RST65;
+    for _ in 0..4 {
+        cpu.step().unwrap();
+    }
+
+    // EI waits for the NOP, then RST 6.5 wins over RST 5.5.
+    assert_eq!(cpu.step().unwrap().opcode, 0x00);
+    let info = cpu.step().unwrap();
+    assert_eq!((info.opcode, info.cycles, cpu.registers().pc), (0xCD, 12, 0x0034));
+    assert_eq!(cpu.bus().read_w(cpu.registers().sp), 0x0108);
+    assert!(!cpu.registers().interrupts);
+
+    // TRAP goes through with interrupts disabled, but only once per rising edge.
+    cpu.bus_mut().lines |= LINE_TRAP;
+    cpu.step().unwrap();
+    assert_eq!(cpu.registers().pc, 0x0024);
+    assert_eq!(cpu.step().unwrap().opcode, 0x00);
+    assert_eq!(cpu.registers().pc, 0x0025);
+
+    // A pulse on RST 7.5 stays latched until interrupts are enabled, and it beats RST 6.5.
+    cpu.bus_mut().lines |= LINE_RST75;
+    cpu.step().unwrap();
+    cpu.bus_mut().lines &= !LINE_RST75;
+    cpu.step().unwrap();
+    assert!(cpu.registers().pins.rst75);
+
+    let state = SaveState { registers: *cpu.registers(), cycles: 0, bus: Vec::new() };
+    assert_eq!(SaveState::from_bytes(&state.to_bytes()).unwrap(), state);
+
+    cpu.registers_mut().interrupts = true;
+    cpu.step().unwrap();
+    assert_eq!(cpu.registers().pc, 0x003C);
+    assert!(!cpu.registers().pins.rst75);
+}
+
+#[test]
+fn test_masked_interrupt_wakes_halt_once_unmasked()
+{
+    let program = assemble_for("
+            ORG 002CH
+            OUT 0FFH
+            ORG 0100H
+            LXI SP,0200H
+            EI
+            HLT
+    ", Model::Intel8085).unwrap();
+
+    let mut cpu = cpu(&program, Model::Intel8085);
+    cpu.bus_mut().lines = LINE_RST55;
+    let info = cpu.run_for_cycles(100).unwrap();
+    assert!(info.cycles >= 100);
+    assert!(cpu.registers().halting);
+
+    cpu.registers_mut().pins.masks = 0x06;
+    cpu.run().unwrap();
+    assert_eq!(cpu.registers().pc, 0x002E);
+}
+
+#[test]
+fn test_8085_timings()
+{
+    let program = assemble_for("
+            ORG 0100H
+            LXI SP,0200H
+            MOV B,C
+            INX H
+            PUSH B
+            XRA A
+            JNZ 0
+            CALL SUB
+            OUT 0FFH
+    SUB:    RNZ
+            RET
+    ", Model::Intel8085).unwrap();
+
+    let cycles = |model| {
+        let mut cpu = cpu(&program, model);
+        (0..9).map(|_| cpu.step().unwrap().cycles).collect::<Vec<u32>>()
+    };
+    assert_eq!(cycles(Model::Intel8080), [10, 5, 5, 11, 4, 10, 17, 5, 10]);
+    assert_eq!(cycles(Model::Intel8085), [10, 4, 6, 12, 4, 7, 18, 6, 10]);
+}
+
+#[test]
+fn test_undocumented_instructions()
+{
+    let program = assemble_for("
+            ORG 0040H       ; RSTV.
+            LXI B,0FFFFH
+            INX B           ; Wraps, sets K.
+            JNK FAIL
+            MVI A,55H
+    FAIL:   OUT 0FFH
+
+            ORG 0100H
+            LXI SP,0200H
+            LXI H,1234H
+            LXI B,0235H
+            DSUB
+            SHLD R1
+            LXI H,8001H
+            ARHL            ; Sets CY from bit 0.
+            SHLD R2
+            LXI D,4001H
+            RDEL            ; Shifts CY in, bit 15 changes so V is set.
+            XCHG
+            SHLD R3
+            LXI H,0300H
+            LDHI 10H
+            LXI H,0ABCDH
+            SHLX
+            LXI H,0
+            LHLX
+            SHLD R4
+            MVI A,7FH
+            INR A           ; Overflows, V set and K clear.
+            JK FAIL
+            JNK OK
+            JMP FAIL
+    OK:     RSTV
+            JMP FAIL
+
+    R1:     DW 0
+    R2:     DW 0
+    R3:     DW 0
+    R4:     DW 0
+    ", Model::Intel8085).unwrap();
+
+    let mut cpu = cpu(&program, Model::Intel8085);
+    cpu.run().unwrap();
+    let word = |name: &str| cpu.bus().read_w(program.symbols[name]);
+    assert_eq!([word("R1"), word("R2"), word("R3"), word("R4")], [0x0FFF, 0xC000, 0x8003, 0xABCD]);
+    assert_eq!(cpu.bus().read_w(0x0310), 0xABCD);
+    assert_eq!(cpu.registers().a, 0x55);
+}
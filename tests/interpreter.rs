@@ -1,9 +1,9 @@
 mod buses;
 
-use std::{fs::File, io::Read, sync::{Arc, RwLock}};
+use std::{fs::File, io::Read, sync::{Arc, RwLock}, thread};
 
 use buses::TestCPMBus;
-use r8080::{cpu::{Interpreter8080, CPU8080}, Bus8080};
+use r8080::{asm::assemble, cpu::{BlockCache8080, CpuError, FaultPolicy, Interpreter8080, Model, Register16, Registers, RunExit, RunInfo, StepInfo, CPU8080}, Bus8080};
 
 fn read_file_to_vec(filename: &str) -> Vec<u8> {
     let mut file = File::open(filename).unwrap();
@@ -22,7 +22,19 @@ fn test_tst8080_com()
     let mut cpu = Box::new(Interpreter8080::new()) as Box<dyn CPU8080>;
     cpu.force_jump(0x100);
     cpu.set_bus(Arc::new(RwLock::new(bus)));
-    cpu.run();
+    cpu.run().unwrap();
+}
+
+#[test]
+fn test_tst8080_com_with_owned_bus()
+{
+    let mut bus = TestCPMBus::new("MICROCOSM ASSOCIATES 8080/8085 CPU DIAGNOSTIC\x0D\x0A VERSION 1.0  (C) 1980\x0D\x0A\x0D\x0A CPU IS OPERATIONAL");
+    bus.write_buffer(0x0100, read_file_to_vec("test_roms/TST8080.COM"));
+
+    let mut cpu = Interpreter8080::with_bus(bus);
+    cpu.force_jump(0x100);
+    cpu.run().unwrap();
+    assert_eq!(cpu.bus().read_b(0x0000), 0xD3);
 }
 
 #[test]
@@ -34,7 +46,7 @@ fn test_cputest_com()
     let mut cpu = Box::new(Interpreter8080::new()) as Box<dyn CPU8080>;
     cpu.force_jump(0x100);
     cpu.set_bus(Arc::new(RwLock::new(bus)));
-    cpu.run();
+    cpu.run().unwrap();
 }
 
 #[test]
@@ -46,7 +58,7 @@ fn test_8080pre_com()
     let mut cpu: Box<dyn CPU8080> = Box::new(Interpreter8080::new());
     cpu.force_jump(0x100);
     cpu.set_bus(Arc::new(RwLock::new(bus)));
-    cpu.run();
+    cpu.run().unwrap();
 }
 
 #[test]
@@ -58,5 +70,501 @@ fn test_8080exm_com()
     let mut cpu = Box::new(Interpreter8080::new()) as Box<dyn CPU8080>;
     cpu.force_jump(0x100);
     cpu.set_bus(Arc::new(RwLock::new(bus)));
-    cpu.run();
+    cpu.run().unwrap();
+}
+
+fn poisoned_bus() -> Arc<RwLock<Box<dyn Bus8080>>> {
+    let bus: Arc<RwLock<Box<dyn Bus8080>>> = Arc::new(RwLock::new(Box::new(TestCPMBus::new(""))));
+
+    // Poison the lock by panicking while holding it, like a faulty device would.
+    let poisoner = Arc::clone(&bus);
+    let _ = thread::spawn(move || {
+        let _guard = poisoner.write().unwrap();
+        panic!("Device failure.");
+    }).join();
+    bus
+}
+
+#[test]
+fn test_poisoned_bus_is_reported()
+{
+    let mut cpu = Box::new(Interpreter8080::new()) as Box<dyn CPU8080>;
+    cpu.force_jump(0x100);
+    cpu.set_bus(poisoned_bus());
+    assert_eq!(cpu.step(), Err(CpuError::BusFault { pc: 0x100 }));
+    assert!(!cpu.is_running());
+}
+
+#[test]
+fn test_fault_policy_recovers_poisoned_bus()
+{
+    let faults = Arc::new(RwLock::new(Vec::new()));
+    let seen = Arc::clone(&faults);
+
+    let mut cpu = Interpreter8080::new();
+    cpu.set_fault_policy(FaultPolicy::Callback(Box::new(move |error, registers| {
+        seen.write().unwrap().push((*error, registers.pc));
+        true
+    })));
+    cpu.force_jump(0x100);
+    cpu.set_bus(poisoned_bus());
+
+    assert!(cpu.step().is_ok());
+    assert!(cpu.is_running());
+    assert!(!cpu.get_bus().is_poisoned());
+    assert_eq!(*faults.read().unwrap(), vec![(CpuError::BusFault { pc: 0x100 }, 0x100)]);
+}
+
+// LXI SP,0200H followed by the undocumented NOP alias 08H, run with undocumented opcodes disabled.
+fn undocumented_opcode_cpu(policy: FaultPolicy) -> Interpreter8080<TestCPMBus> {
+    let mut bus = TestCPMBus::new("");
+    bus.write_buffer(0x0100, vec![0x31, 0x00, 0x02, 0x08]);
+    let mut cpu = Interpreter8080::with_bus(bus);
+    cpu.set_undocumented_opcodes(false);
+    cpu.set_fault_policy(policy);
+    cpu.force_jump(0x100);
+    cpu.step().unwrap();
+    cpu
+}
+
+#[test]
+fn test_fault_policies_on_unknown_opcode()
+{
+    let fault = CpuError::UnknownOpcode { pc: 0x103, opcode: 0x08 };
+
+    let mut cpu = undocumented_opcode_cpu(FaultPolicy::Stop);
+    assert_eq!(cpu.step(), Err(fault));
+    assert!(!cpu.is_running());
+
+    // NOP skips the opcode.
+    let mut cpu = undocumented_opcode_cpu(FaultPolicy::Nop);
+    assert_eq!(cpu.step(), Ok(StepInfo { pc: 0x103, opcode: 0x08, cycles: 4 }));
+    assert_eq!((cpu.registers().pc, cpu.registers().sp), (0x104, 0x200));
+    assert!(cpu.is_running());
+
+    // Trap runs RST vector with the address after the opcode as the return address, vector 9 wraps to RST 1.
+    for (vector, target) in [(5, 0x28), (9, 0x08)] {
+        let mut cpu = undocumented_opcode_cpu(FaultPolicy::Trap { vector });
+        assert_eq!(cpu.step(), Ok(StepInfo { pc: 0x103, opcode: 0x08, cycles: 11 }));
+        assert_eq!((cpu.registers().pc, cpu.registers().sp), (target, 0x1FE));
+        assert_eq!(cpu.bus().read_w(0x1FE), 0x104);
+    }
+
+    // The callback sees the error and the registers, and decides.
+    for keep_going in [true, false] {
+        let faults = Arc::new(RwLock::new(Vec::new()));
+        let seen = Arc::clone(&faults);
+        let mut cpu = undocumented_opcode_cpu(FaultPolicy::Callback(Box::new(move |error, registers| {
+            seen.write().unwrap().push((*error, registers.pc, registers.sp));
+            keep_going
+        })));
+
+        let result = cpu.step();
+        assert_eq!(*faults.read().unwrap(), vec![(fault, 0x104, 0x200)]);
+        assert_eq!(cpu.is_running(), keep_going);
+        if keep_going {
+            assert_eq!(result, Ok(StepInfo { pc: 0x103, opcode: 0x08, cycles: 4 }));
+        } else {
+            assert_eq!(result, Err(fault));
+        }
+    }
+}
+
+#[test]
+fn test_undocumented_opcodes_can_be_disabled()
+{
+    // Enabled by default, 08H is a NOP alias.
+    let mut bus = TestCPMBus::new("");
+    bus.write_buffer(0x0100, vec![0x08, 0x20, 0xCB]);
+    let mut cpu = Interpreter8080::with_bus(bus);
+    cpu.force_jump(0x100);
+    assert!(cpu.step().is_ok());
+
+    // On the 8085 RIM stays, RSTV goes.
+    cpu.set_model(Model::Intel8085);
+    cpu.set_undocumented_opcodes(false);
+    assert!(cpu.step().is_ok());
+    assert_eq!(cpu.step(), Err(CpuError::UnknownOpcode { pc: 0x102, opcode: 0xCB }));
+}
+
+#[test]
+fn test_registers_can_be_seeded_and_inspected()
+{
+    let program = assemble("
+            ORG 0100H
+            XCHG
+            PUSH D
+            DAD D
+            OUT 0
+    ").unwrap();
+    let mut bus = Box::new(TestCPMBus::new(""));
+    bus.write_buffer(program.origin, program.bytes);
+
+    let mut cpu = Box::new(Interpreter8080::new()) as Box<dyn CPU8080>;
+    cpu.force_jump(0x100);
+    cpu.set_bus(Arc::new(RwLock::new(bus)));
+    let registers = cpu.registers_mut();
+    registers.sp = 0x0200;
+    registers.set_16(&Register16::HL, 0x1234);
+    registers.set_16(&Register16::DE, 0x0101);
+    cpu.run().unwrap();
+
+    let registers = cpu.registers();
+    assert_eq!(registers.get_16(&Register16::HL), 0x1335);
+    assert_eq!(registers.get_16(&Register16::DE), 0x1234);
+    assert_eq!(registers.sp, 0x01FE);
+    assert_eq!(cpu.get_bus().read().unwrap().read_w(0x01FE), 0x1234);
+}
+
+#[test]
+fn test_run_for_cycles_matches_stepping()
+{
+    let program = read_file_to_vec("test_roms/8080PRE.COM");
+    let mut stepped = Interpreter8080::with_bus(TestCPMBus::new("8080 Preliminary tests complete"));
+    let mut budgeted = Interpreter8080::with_bus(TestCPMBus::new("8080 Preliminary tests complete"));
+    for cpu in [&mut stepped, &mut budgeted] {
+        cpu.bus_mut().write_buffer(0x0100, program.clone());
+        cpu.force_jump(0x100);
+    }
+
+    // Every slice ends on an instruction boundary at or just past its budget.
+    for budget in [1, 10, 1000, 4321] {
+        let run = budgeted.run_for_cycles(budget).unwrap();
+        assert_eq!(run.exit, RunExit::CyclesElapsed);
+        let spent = run.cycles;
+        assert!(spent >= budget && spent < budget + 18, "{} cycles for a budget of {}", spent, budget);
+        while stepped.get_executed_cycles() < budgeted.get_executed_cycles() {
+            stepped.step().unwrap();
+        }
+        assert_eq!(stepped.registers(), budgeted.registers());
+        assert_eq!(stepped.get_executed_cycles(), budgeted.get_executed_cycles());
+    }
+
+    stepped.run().unwrap();
+    let run = budgeted.run_for_cycles(u64::MAX).unwrap();
+    assert_eq!(run.exit, RunExit::Stopped);
+    assert!(!budgeted.is_running() && run.cycles < u64::MAX);
+    assert_eq!(stepped.registers(), budgeted.registers());
+    assert_eq!(stepped.get_executed_cycles(), budgeted.get_executed_cycles());
+}
+
+#[test]
+fn test_run_until_and_cycle_slices()
+{
+    let program = assemble("
+            ORG 0100H
+            MVI A,0
+    LOOP:   INR A
+            CPI 5
+            JNZ LOOP
+            HLT
+    ").unwrap();
+
+    let engines: [Box<dyn CPU8080>; 2] = [Box::new(Interpreter8080::new()), Box::new(BlockCache8080::new())];
+    for mut cpu in engines {
+        let mut bus = Box::new(TestCPMBus::new(""));
+        bus.write_buffer(program.origin, program.bytes.clone());
+        cpu.set_bus(Arc::new(RwLock::new(bus)));
+        cpu.force_jump(0x0100);
+        // The counter keeps going past what a u32 holds.
+        let base = u32::MAX as u64;
+        cpu.set_executed_cycles(base);
+
+        // MVI, then INR / CPI / JNZ three times less the last JNZ.
+        let run = cpu.run_until(&mut |registers| registers.a == 3).unwrap();
+        assert_eq!(run.exit, RunExit::Condition);
+        assert_eq!((cpu.registers().a, cpu.registers().pc), (3, 0x0103));
+        assert_eq!(run.cycles, 7 + 2 * (5 + 7 + 10) + 5);
+
+        let run = cpu.run_for_cycles(1).unwrap();
+        assert_eq!((run.exit, run.cycles), (RunExit::CyclesElapsed, 7));
+
+        let run = cpu.run_until(&mut |registers| registers.halting).unwrap();
+        assert_eq!(run.exit, RunExit::Condition);
+        assert_eq!(cpu.get_executed_cycles(), base + 7 + 5 * (5 + 7 + 10) + 7);
+
+        // Halted, the CPU idles through budgets in steps of 4 cycles, but will not wait forever without one.
+        assert_eq!(cpu.run_for_cycles(1_000).unwrap(), RunInfo { cycles: 1_000, exit: RunExit::Halted });
+        assert_eq!(cpu.run_for_cycles(1_001).unwrap(), RunInfo { cycles: 1_004, exit: RunExit::Halted });
+        assert_eq!(cpu.step().unwrap().cycles, 4);
+        assert_eq!(cpu.run_until(&mut |_| false).unwrap(), RunInfo { cycles: 0, exit: RunExit::Halted });
+        assert!(cpu.registers().halting);
+
+        cpu.stop();
+        assert_eq!(cpu.run_for_cycles(1_000).unwrap(), RunInfo { cycles: 0, exit: RunExit::Stopped });
+    }
+}
+
+#[test]
+fn test_interrupt_wakes_halted_cpu()
+{
+    let program = assemble("
+            ORG 0008H
+            OUT 0
+            ORG 0100H
+            LXI SP,0200H
+            EI
+            HLT
+    ").unwrap();
+
+    let engines: [Box<dyn CPU8080>; 2] = [Box::new(Interpreter8080::new()), Box::new(BlockCache8080::new())];
+    for mut cpu in engines {
+        let mut bus = Box::new(TestCPMBus::new(""));
+        bus.write_buffer(program.origin, program.bytes.clone());
+        let bus: Arc<RwLock<Box<dyn Bus8080>>> = Arc::new(RwLock::new(bus));
+        cpu.set_bus(Arc::clone(&bus));
+        cpu.force_jump(0x0100);
+
+        // LXI, EI and HLT take 21 cycles, the other 79 round up to whole idle steps.
+        let run = cpu.run_for_cycles(100).unwrap();
+        assert_eq!(run, RunInfo { cycles: 21 + 80, exit: RunExit::Halted });
+
+        // Waking up costs the instruction the interrupt supplies, RST 1 here.
+        bus.write().unwrap().push_interrupt(0xCF);
+        let info = cpu.step().unwrap();
+        assert_eq!((info.opcode, info.cycles), (0xCF, 11));
+        assert_eq!((cpu.registers().pc, cpu.registers().halting), (0x0008, false));
+        assert_eq!(bus.read().unwrap().read_w(0x01FE), 0x0105);
+
+        let run = cpu.run_for_cycles(100).unwrap();
+        assert_eq!(run, RunInfo { cycles: 10, exit: RunExit::Stopped });
+    }
+}
+
+#[test]
+fn test_interrupt_delivers_call_from_device()
+{
+    let program = assemble("
+            ORG 0100H
+            LXI SP,0200H
+            EI
+    WAIT:   JMP WAIT
+            ORG 0300H
+            OUT 0
+    ").unwrap();
+
+    let engines: [Box<dyn CPU8080>; 2] = [Box::new(Interpreter8080::new()), Box::new(BlockCache8080::new())];
+    for mut cpu in engines {
+        let mut bus = Box::new(TestCPMBus::new(""));
+        bus.write_buffer(program.origin, program.bytes.clone());
+        let bus: Arc<RwLock<Box<dyn Bus8080>>> = Arc::new(RwLock::new(bus));
+        cpu.set_bus(Arc::clone(&bus));
+        cpu.force_jump(0x0100);
+        cpu.run_for_cycles(100).unwrap();
+
+        // CALL 0300H over three INTA cycles, the operands come from the device and not from memory at PC.
+        for byte in [0xCD, 0x00, 0x03] {
+            bus.write().unwrap().push_interrupt(byte);
+        }
+        let pc = cpu.registers().pc;
+        let info = cpu.step().unwrap();
+        assert_eq!((info.opcode, info.cycles), (0xCD, 17));
+        assert_eq!(cpu.registers().pc, 0x0300);
+        assert_eq!(bus.read().unwrap().read_w(cpu.registers().sp), pc);
+        assert!(!bus.read().unwrap().has_interrupt());
+        assert!(!cpu.registers().interrupts);
+
+        cpu.run().unwrap();
+        assert_eq!(cpu.registers().pc, 0x0302);
+    }
+}
+
+// A bus that only implements get_interrupt() still gets RST through the default acknowledge.
+#[test]
+fn test_default_acknowledge_delivers_rst()
+{
+    struct RstBus
+    {
+        ram: Vec<u8>,
+        pending: Option<u8>
+    }
+
+    impl Bus8080 for RstBus
+    {
+        fn read_b(&self, a: u16) -> u8 {
+            self.ram[a as usize]
+        }
+
+        fn read_w(&self, a: u16) -> u16 {
+            u16::from_le_bytes([self.read_b(a), self.read_b(a.wrapping_add(1))])
+        }
+
+        fn has_interrupt(&self) -> bool {
+            self.pending.is_some()
+        }
+
+        fn get_interrupt(&mut self) -> u8 {
+            self.pending.take().unwrap_or(0xFF)
+        }
+
+        fn push_interrupt(&mut self, b: u8) {
+            self.pending = Some(b);
+        }
+
+        fn write_b(&mut self, a: u16, b: u8) {
+            self.ram[a as usize] = b;
+        }
+
+        fn write_w(&mut self, a: u16, w: u16) {
+            self.write_b(a, w as u8);
+            self.write_b(a.wrapping_add(1), (w >> 8) as u8);
+        }
+
+        fn in_b(&mut self, _: &mut Registers, _: u8) -> u8 {
+            0xFF
+        }
+
+        fn out_b(&mut self, regs: &mut Registers, _: u8, _: u8) {
+            regs.running = false;
+        }
+
+        fn write_buffer(&mut self, a: u16, data: Vec<u8>) {
+            self.ram[a as usize..a as usize + data.len()].copy_from_slice(&data);
+        }
+    }
+
+    let mut cpu = Interpreter8080::with_bus(RstBus { ram: vec![0; 0x10000], pending: None });
+    cpu.bus_mut().write_buffer(0x0000, vec![0x31, 0x00, 0x02, 0xFB, 0x76]);
+    cpu.run_for_cycles(100).unwrap();
+    cpu.bus_mut().push_interrupt(0xD7);
+
+    let info = cpu.step().unwrap();
+    assert_eq!((info.opcode, info.cycles), (0xD7, 11));
+    assert_eq!(cpu.registers().pc, 0x0010);
+    assert_eq!(cpu.bus().read_w(0x01FE), 0x0005);
+    assert!(!cpu.registers().interrupts);
+}
+
+// EI; RET is the usual end of a handler, the interrupt must not get in before the RET.
+#[test]
+fn test_ei_takes_effect_after_next_instruction()
+{
+    let program = assemble("
+            ORG 0038H
+            OUT 0
+            ORG 0100H
+            LXI SP,0200H
+            CALL SUB
+    DONE:   JMP DONE
+    SUB:    EI
+            RET
+    ").unwrap();
+
+    let engines: [Box<dyn CPU8080>; 2] = [Box::new(Interpreter8080::new()), Box::new(BlockCache8080::new())];
+    for mut cpu in engines {
+        let mut bus = Box::new(TestCPMBus::new(""));
+        bus.write_buffer(program.origin, program.bytes.clone());
+        bus.push_interrupt(0xFF);
+        let bus: Arc<RwLock<Box<dyn Bus8080>>> = Arc::new(RwLock::new(bus));
+        cpu.set_bus(Arc::clone(&bus));
+        cpu.force_jump(0x0100);
+
+        // Interrupts are disabled out of reset, so the request waits for EI.
+        let opcodes: Vec<u8> = (0..3).map(|_| cpu.step().unwrap().opcode).collect();
+        assert_eq!(opcodes, [0x31, 0xCD, 0xFB]);
+        assert!(cpu.registers().interrupts && bus.read().unwrap().has_interrupt());
+
+        let info = cpu.step().unwrap();
+        assert_eq!((info.opcode, cpu.registers().pc), (0xC9, 0x0106));
+        let info = cpu.step().unwrap();
+        assert_eq!((info.opcode, cpu.registers().pc), (0xFF, 0x0038));
+        assert_eq!(bus.read().unwrap().read_w(cpu.registers().sp), 0x0106);
+    }
+
+    // Same through run(), where the block cache has EI and RET in one block.
+    let engines: [Box<dyn CPU8080>; 2] = [Box::new(Interpreter8080::new()), Box::new(BlockCache8080::new())];
+    for mut cpu in engines {
+        let mut bus = Box::new(TestCPMBus::new(""));
+        bus.write_buffer(program.origin, program.bytes.clone());
+        bus.push_interrupt(0xFF);
+        let bus: Arc<RwLock<Box<dyn Bus8080>>> = Arc::new(RwLock::new(bus));
+        cpu.set_bus(Arc::clone(&bus));
+        cpu.force_jump(0x0100);
+
+        cpu.run().unwrap();
+        assert_eq!(cpu.registers().pc, 0x003A);
+        assert_eq!(bus.read().unwrap().read_w(cpu.registers().sp), 0x0106);
+    }
+}
+
+// A second request already queued waits for the handler's own EI, and then for the RET after it.
+#[test]
+fn test_queued_interrupt_waits_for_handler_ei()
+{
+    let program = assemble("
+            ORG 0008H
+            INR B
+            EI
+            RET
+            ORG 0100H
+            LXI SP,0200H
+            EI
+    WAIT:   JMP WAIT
+    ").unwrap();
+
+    let engines: [Box<dyn CPU8080>; 2] = [Box::new(Interpreter8080::new()), Box::new(BlockCache8080::new())];
+    for mut cpu in engines {
+        let mut bus = Box::new(TestCPMBus::new(""));
+        bus.write_buffer(program.origin, program.bytes.clone());
+        bus.push_interrupt(0xCF);
+        bus.push_interrupt(0xCF);
+        let bus: Arc<RwLock<Box<dyn Bus8080>>> = Arc::new(RwLock::new(bus));
+        cpu.set_bus(Arc::clone(&bus));
+        cpu.force_jump(0x0100);
+
+        let opcodes: Vec<u8> = (0..4).map(|_| cpu.step().unwrap().opcode).collect();
+        assert_eq!(opcodes, [0x31, 0xFB, 0xC3, 0xCF]);
+        assert_eq!((cpu.registers().pc, cpu.registers().sp), (0x0008, 0x01FE));
+        assert!(!cpu.registers().interrupts && !cpu.registers().interrupt_delay);
+        assert!(bus.read().unwrap().has_interrupt());
+
+        // The handler runs to its RET with the second request left pending.
+        let opcodes: Vec<u8> = (0..3).map(|_| cpu.step().unwrap().opcode).collect();
+        assert_eq!(opcodes, [0x04, 0xFB, 0xC9]);
+        assert_eq!((cpu.registers().pc, cpu.registers().sp, cpu.registers().b), (0x0104, 0x0200, 1));
+        assert!(bus.read().unwrap().has_interrupt());
+
+        let info = cpu.step().unwrap();
+        assert_eq!((info.opcode, cpu.registers().pc), (0xCF, 0x0008));
+        assert_eq!(bus.read().unwrap().read_w(cpu.registers().sp), 0x0104);
+        assert!(!bus.read().unwrap().has_interrupt());
+    }
+}
+
+#[test]
+fn test_reset()
+{
+    assert!(!Registers::new().interrupts);
+
+    let program = assemble("
+            ORG 0100H
+            MVI A,42H
+            EI
+            HLT
+    ").unwrap();
+
+    let engines: [Box<dyn CPU8080>; 2] = [Box::new(Interpreter8080::new()), Box::new(BlockCache8080::new())];
+    for mut cpu in engines {
+        let mut bus = Box::new(TestCPMBus::new(""));
+        bus.write_buffer(program.origin, program.bytes.clone());
+        let bus: Arc<RwLock<Box<dyn Bus8080>>> = Arc::new(RwLock::new(bus));
+        cpu.set_bus(Arc::clone(&bus));
+        cpu.force_jump(0x0100);
+        cpu.run_for_cycles(100).unwrap();
+        assert!(cpu.registers().halting && cpu.registers().interrupts);
+
+        // RESET leaves the halt with interrupts off and PC at 0, the other registers are not touched.
+        let cycles = cpu.get_executed_cycles();
+        cpu.reset();
+        let registers = cpu.registers();
+        assert_eq!((registers.pc, registers.a), (0x0000, 0x42));
+        assert!(!registers.halting && !registers.interrupts && registers.running);
+        assert_eq!(cpu.get_executed_cycles(), cycles);
+
+        // A request now waits, the CPU runs the OUT 0 the test BIOS has at 0000H instead.
+        bus.write().unwrap().push_interrupt(0xCF);
+        let info = cpu.step().unwrap();
+        assert_eq!((info.opcode, cpu.registers().pc), (0xD3, 0x0002));
+        assert!(bus.read().unwrap().has_interrupt());
+    }
 }
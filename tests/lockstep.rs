@@ -0,0 +1,158 @@
+mod buses;
+
+use std::{fs, sync::{Arc, RwLock}};
+
+use buses::TestCPMBus;
+use r8080::{asm::{assemble, assemble_for}, cpu::{BlockCache8080, CpuError, Interpreter8080, Model, Registers, StepInfo, CPU8080, FLAG_CARRY}, lockstep::{BusEvent, Lockstep, Mismatch}, Bus8080};
+
+const TST8080: &str = "MICROCOSM ASSOCIATES 8080/8085 CPU DIAGNOSTIC\x0D\x0A VERSION 1.0  (C) 1980\x0D\x0A\x0D\x0A CPU IS OPERATIONAL";
+
+const PROGRAM: &str = "
+        ORG 0100H
+        LXI SP,0200H
+        STC
+        CMC
+        PUSH B
+        OUT 0
+";
+
+// An interpreter with a bug planted after every step.
+struct Broken
+{
+    cpu: Interpreter8080,
+    bug: fn(&mut Interpreter8080, StepInfo)
+}
+
+impl CPU8080 for Broken
+{
+    fn get_executed_cycles(&mut self) -> u64 {
+        self.cpu.get_executed_cycles()
+    }
+
+    fn set_executed_cycles(&mut self, cycles: u64) {
+        self.cpu.set_executed_cycles(cycles)
+    }
+
+    fn force_jump(&mut self, a: u16) {
+        self.cpu.force_jump(a)
+    }
+
+    fn registers(&self) -> &Registers {
+        self.cpu.registers()
+    }
+
+    fn registers_mut(&mut self) -> &mut Registers {
+        self.cpu.registers_mut()
+    }
+
+    fn set_bus(&mut self, b: Arc<RwLock<Box<dyn Bus8080>>>) {
+        self.cpu.set_bus(b)
+    }
+
+    fn get_bus(&self) -> Arc<RwLock<Box<dyn Bus8080>>> {
+        self.cpu.get_bus()
+    }
+
+    fn stop(&mut self) {
+        self.cpu.stop()
+    }
+
+    fn is_running(&mut self) -> bool {
+        self.cpu.is_running()
+    }
+
+    fn step(&mut self) -> Result<StepInfo, CpuError> {
+        let info = self.cpu.step()?;
+        (self.bug)(&mut self.cpu, info);
+        Ok(info)
+    }
+
+    fn run(&mut self) -> Result<(), CpuError> {
+        while self.cpu.is_running() {
+            self.step()?;
+        }
+        Ok(())
+    }
+}
+
+fn interpreter(program: &[u8], origin: u16, expected_output: &'static str) -> Interpreter8080 {
+    let mut bus = Box::new(TestCPMBus::new(expected_output));
+    bus.write_buffer(origin, program.to_vec());
+    let mut cpu = Interpreter8080::new();
+    cpu.force_jump(origin);
+    cpu.set_bus(Arc::new(RwLock::new(bus)));
+    cpu
+}
+
+fn broken(bug: fn(&mut Interpreter8080, StepInfo)) -> Lockstep {
+    let program = assemble(PROGRAM).unwrap();
+    let reference = interpreter(&program.bytes, program.origin, "");
+    let candidate = Broken { cpu: interpreter(&program.bytes, program.origin, ""), bug };
+    Lockstep::new(Box::new(reference), Box::new(candidate))
+}
+
+#[test]
+fn test_block_cache_agrees_with_interpreter()
+{
+    let program = fs::read("test_roms/TST8080.COM").unwrap();
+    let reference = interpreter(&program, 0x0100, TST8080);
+    let mut candidate = BlockCache8080::new();
+    candidate.set_bus(interpreter(&program, 0x0100, TST8080).get_bus());
+    candidate.force_jump(0x0100);
+
+    let mut lockstep = Lockstep::new(Box::new(reference), Box::new(candidate));
+    let executed = lockstep.run(u64::MAX).unwrap();
+    assert!(executed > 0);
+    assert_eq!(lockstep.executed(), executed);
+
+    let (reference, candidate) = lockstep.into_inner();
+    assert_eq!(reference.registers(), candidate.registers());
+}
+
+#[test]
+fn test_reports_flag_divergence()
+{
+    let mut lockstep = broken(|cpu, info| if info.opcode == 0x3F { cpu.registers_mut().f ^= FLAG_CARRY; });
+    lockstep.set_trace_length(2);
+
+    let divergence = lockstep.run(u64::MAX).unwrap_err();
+    assert_eq!(divergence.index, 2);
+    assert_eq!(divergence.line.address, 0x0104);
+    assert_eq!(divergence.line.text, "CMC");
+    assert!(matches!(divergence.mismatches[..], [Mismatch::Flags { reference, candidate }] if reference ^ candidate == FLAG_CARRY));
+    assert_eq!(divergence.trace.iter().map(|entry| entry.index).collect::<Vec<_>>(), [1, 2]);
+    assert!(divergence.to_string().contains("differ in CY"));
+}
+
+#[test]
+fn test_reports_write_divergence()
+{
+    let mut lockstep = broken(|cpu, info| if info.opcode == 0xC5 { cpu.get_bus().write().unwrap().write_b(0x01FE, 0xFF); });
+
+    let divergence = lockstep.run(u64::MAX).unwrap_err();
+    assert_eq!(divergence.line.text, "PUSH B");
+    let [Mismatch::BusEvents { reference, candidate }] = &divergence.mismatches[..] else {
+        panic!("unexpected mismatches {:?}", divergence.mismatches);
+    };
+    assert_eq!(reference.len(), 2);
+    assert_eq!(candidate[..2], reference[..]);
+    assert_eq!(candidate[2], BusEvent::Write { address: 0x01FE, value: 0xFF });
+}
+
+// The trace is disassembled for the reference's model, RIM is not a NOP here.
+#[test]
+fn test_trace_uses_reference_model()
+{
+    let program = assemble_for("
+        ORG 0100H
+        RIM
+        OUT 0
+    ", Model::Intel8085).unwrap();
+    let mut reference = interpreter(&program.bytes, program.origin, "");
+    reference.set_model(Model::Intel8085);
+    let mut candidate = Broken { cpu: interpreter(&program.bytes, program.origin, ""), bug: |cpu, _| cpu.registers_mut().a ^= 0x80 };
+    candidate.cpu.set_model(Model::Intel8085);
+
+    let divergence = Lockstep::new(Box::new(reference), Box::new(candidate)).run(u64::MAX).unwrap_err();
+    assert_eq!(divergence.line.text, "RIM");
+}
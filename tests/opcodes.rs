@@ -0,0 +1,52 @@
+mod buses;
+
+use std::sync::{Arc, RwLock};
+
+use buses::TestCPMBus;
+use r8080::{asm::assemble, cpu::{Access, Instruction8080, Interpreter8080, CPU8080, FLAGS_ALL, FLAG_CARRY, FLAG_ZERO, OPCODES}, Bus8080};
+
+#[test]
+fn test_table_matches_decoder()
+{
+    for (opcode, info) in OPCODES.iter().enumerate() {
+        let (instruction, length) = Instruction8080::decode(&[opcode as u8, 0x00, 0x00]).unwrap();
+        let text = instruction.to_string();
+
+        assert_eq!(info.length as usize, length, "length of 0x{:02X}", opcode);
+        assert_eq!(text.split(' ').next().unwrap(), info.mnemonic, "mnemonic of 0x{:02X}", opcode);
+        assert!(info.cycles <= info.cycles_taken, "cycles of 0x{:02X}", opcode);
+    }
+
+    assert_eq!((OPCODES[0xCC].cycles, OPCODES[0xCC].cycles_taken, OPCODES[0xCC].flags_read), (11, 17, FLAG_ZERO));
+    assert_eq!((OPCODES[0x8E].mnemonic, OPCODES[0x8E].operands, OPCODES[0x8E].access), ("ADC", "M", Access::MemoryRead));
+    assert_eq!((OPCODES[0x8E].flags_read, OPCODES[0x8E].flags_written), (FLAG_CARRY, FLAGS_ALL));
+    assert!(!OPCODES[0xDD].documented);
+}
+
+#[test]
+fn test_interpreter_charges_table_cycles()
+{
+    let program = assemble("
+        ORG 0008H
+        CZ 0            ; 11, not taken
+        CNZ 0010H       ; 17
+        ORG 0010H
+        LXI H,0018H     ; 10
+        PCHL            ; 5
+        ORG 0018H
+        RNZ             ; 11
+        ORG 0100H
+        MOV A,M         ; 7
+        RST 1           ; 11
+    ").unwrap();
+
+    let mut bus = Box::new(TestCPMBus::new(""));
+    bus.write_buffer(program.origin, program.bytes);
+
+    let mut cpu = Interpreter8080::new();
+    cpu.force_jump(0x100);
+    cpu.set_bus(Arc::new(RwLock::new(bus)));
+
+    let cycles: Vec<u32> = (0..7).map(|_| cpu.step().unwrap().cycles).collect();
+    assert_eq!(cycles, [7, 11, 11, 17, 10, 5, 11]);
+}
@@ -0,0 +1,359 @@
+use std::sync::{Arc, RwLock};
+
+use r8080::{asm::assemble, cpu::{BlockCache8080, Interpreter8080, Registers, CPU8080}, pic::Pic8259, Bus8080};
+
+const PIC_PORT: u8 = 0x20;
+
+// Flat RAM with the 8259A at ports 20H / 21H, OUT 0FFH stops. push_interrupt pulses an IR line.
+struct PicBus
+{
+    ram: Vec<u8>,
+    pic: Pic8259
+}
+
+impl Bus8080 for PicBus
+{
+    fn read_b(&self, a: u16) -> u8 {
+        self.ram[a as usize]
+    }
+
+    fn read_w(&self, a: u16) -> u16 {
+        u16::from_le_bytes([self.read_b(a), self.read_b(a.wrapping_add(1))])
+    }
+
+    fn has_interrupt(&self) -> bool {
+        self.pic.has_interrupt()
+    }
+
+    fn get_interrupt(&mut self) -> u8 {
+        self.pic.acknowledge(0)
+    }
+
+    fn push_interrupt(&mut self, b: u8) {
+        self.pic.request(b);
+    }
+
+    fn write_b(&mut self, a: u16, b: u8) {
+        self.ram[a as usize] = b;
+    }
+
+    fn write_w(&mut self, a: u16, w: u16) {
+        self.write_b(a, w as u8);
+        self.write_b(a.wrapping_add(1), (w >> 8) as u8);
+    }
+
+    fn in_b(&mut self, _: &mut Registers, b: u8) -> u8 {
+        if b & 0xFE == PIC_PORT { self.pic.read(b & 1 != 0) } else { 0xFF }
+    }
+
+    fn out_b(&mut self, regs: &mut Registers, b: u8, a: u8) {
+        match b {
+            0xFF => regs.running = false,
+            _ if b & 0xFE == PIC_PORT => self.pic.write(b & 1 != 0, a),
+            _ => { }
+        }
+    }
+
+    fn write_buffer(&mut self, a: u16, data: Vec<u8>) {
+        self.ram[a as usize..a as usize + data.len()].copy_from_slice(&data);
+    }
+
+    fn acknowledge_interrupt(&mut self, cycle: u8) -> u8 {
+        self.pic.acknowledge(cycle)
+    }
+}
+
+// Reads the whole CALL an acknowledge supplies.
+fn acknowledge(pic: &mut Pic8259) -> (u8, u16) {
+    let opcode = pic.acknowledge(0);
+    let low = pic.acknowledge(1);
+    let high = pic.acknowledge(2);
+    (opcode, u16::from_le_bytes([low, high]))
+}
+
+#[test]
+fn test_fixed_priority_masking_and_eoi()
+{
+    // Single, interval 4, no ICW4, vectors from 1000H.
+    let mut pic = Pic8259::new();
+    pic.write(false, 0x16);
+    pic.write(true, 0x10);
+    assert!(!pic.has_interrupt());
+    pic.write(true, 0x04);
+
+    for level in [5, 2, 3] {
+        pic.request(level);
+    }
+    assert_eq!(pic.irr(), 0x2C);
+    assert!(pic.has_interrupt());
+
+    // IR2 is masked, so IR3 goes first.
+    assert_eq!(acknowledge(&mut pic), (0xCD, 0x100C));
+    assert_eq!((pic.irr(), pic.isr()), (0x24, 0x08));
+    assert!(!pic.has_interrupt());
+
+    // OCW3 switches reads at A0 = 0 from IRR to ISR.
+    assert_eq!(pic.read(false), 0x24);
+    pic.write(false, 0x0B);
+    assert_eq!(pic.read(false), 0x08);
+    assert_eq!(pic.read(true), 0x04);
+
+    // A non-specific EOI lets IR5 through, unmasking IR2 then nests it above IR5.
+    pic.write(false, 0x20);
+    assert_eq!(acknowledge(&mut pic), (0xCD, 0x1014));
+    pic.write(true, 0x00);
+    assert!(pic.has_interrupt());
+    assert_eq!(acknowledge(&mut pic), (0xCD, 0x1008));
+    assert_eq!(pic.isr(), 0x24);
+
+    // A specific EOI for IR5 leaves IR2 in service.
+    pic.write(false, 0x65);
+    assert_eq!(pic.isr(), 0x04);
+    pic.write(false, 0x20);
+    assert_eq!(pic.isr(), 0x00);
+
+    // No request left at INTA answers for IR7 without putting it in service.
+    assert_eq!(acknowledge(&mut pic), (0xCD, 0x101C));
+    assert_eq!(pic.isr(), 0x00);
+}
+
+#[test]
+fn test_rotation_auto_eoi_and_poll()
+{
+    // Single, interval 8, ICW4 with automatic EOI, vectors from 2040H (A7 and A6 from ICW1).
+    let mut pic = Pic8259::new();
+    pic.write(false, 0x53);
+    pic.write(true, 0x20);
+    pic.write(true, 0x02);
+    assert_eq!(pic.imr(), 0x00);
+    assert_eq!(pic.vector(4), 0x2060);
+
+    // Rotate in automatic EOI mode: a served level drops to the lowest priority.
+    pic.write(false, 0x80);
+    pic.request(1);
+    pic.request(4);
+    assert_eq!(acknowledge(&mut pic), (0xCD, 0x2048));
+    assert_eq!(pic.isr(), 0x00);
+    pic.request(1);
+    assert_eq!(acknowledge(&mut pic), (0xCD, 0x2060));
+    assert_eq!(acknowledge(&mut pic), (0xCD, 0x2048));
+
+    // Set priority makes IR5 the lowest, so IR6 beats IR0.
+    pic.write(false, 0xC5);
+    pic.request(0);
+    pic.request(6);
+
+    // A poll command answers the next read with the level and puts it in service.
+    pic.write(false, 0x0C);
+    assert_eq!(pic.read(false), 0x86);
+    assert_eq!((pic.irr(), pic.isr()), (0x01, 0x40));
+    pic.write(false, 0x20);
+    pic.write(false, 0x0C);
+    assert_eq!(pic.read(false), 0x80);
+    pic.write(false, 0x0C);
+    assert_eq!(pic.read(false), 0x00);
+}
+
+#[test]
+fn test_edge_and_level_triggering()
+{
+    let mut pic = Pic8259::new();
+    pic.write(false, 0x16);
+    pic.write(true, 0x00);
+
+    // Edge triggered: a line held high requests once, dropping it before INTA withdraws the request.
+    pic.set_line(2, true);
+    assert_eq!(acknowledge(&mut pic).1, 0x0008);
+    pic.write(false, 0x20);
+    assert!(!pic.has_interrupt());
+    pic.set_line(2, false);
+    pic.set_line(3, true);
+    pic.set_line(3, false);
+    assert!(!pic.has_interrupt());
+
+    // Level triggered: the request comes back after EOI for as long as the line is high.
+    pic.write(false, 0x1E);
+    pic.write(true, 0x00);
+    pic.write(true, 0x00);
+    pic.set_line(2, true);
+    assert_eq!(acknowledge(&mut pic).1, 0x0008);
+    pic.write(false, 0x20);
+    assert!(pic.has_interrupt());
+    pic.set_line(2, false);
+    assert!(!pic.has_interrupt());
+
+    let mut restored = Pic8259::new();
+    restored.load_state(&pic.save_state()).unwrap();
+    assert_eq!(restored, pic);
+    assert!(restored.load_state(&[0; 3]).is_err());
+}
+
+#[test]
+fn test_pic_drives_cpu()
+{
+    let program = assemble("
+            ORG 0100H
+            LXI SP,0200H
+            MVI A,16H       ; ICW1: single, interval 4.
+            OUT 20H
+            MVI A,10H       ; ICW2: vectors from 1000H.
+            OUT 21H
+            XRA A           ; OCW1: nothing masked.
+            OUT 21H
+            EI
+    WAIT:   LDA COUNT
+            CPI 2
+            JNZ WAIT
+            OUT 0FFH
+
+    IR1:    PUSH PSW
+            MVI A,1
+            JMP LOG
+    IR3:    PUSH PSW
+            MVI A,3
+    LOG:    PUSH H
+            LHLD NEXT
+            MOV M,A
+            INX H
+            SHLD NEXT
+            LXI H,COUNT
+            INR M
+            POP H
+            MVI A,20H       ; Non-specific EOI.
+            OUT 20H
+            POP PSW
+            EI
+    EXIT:   RET
+
+    COUNT:  DB 0
+    NEXT:   DW ORDER
+    ORDER:  DS 2
+
+            ORG 1004H       ; Four bytes per level.
+            JMP IR1
+            ORG 100CH
+            JMP IR3
+    ").unwrap();
+    let (order, exit) = (program.symbols["ORDER"], program.symbols["EXIT"]);
+
+    let engines: [Box<dyn CPU8080>; 2] = [Box::new(Interpreter8080::new()), Box::new(BlockCache8080::new())];
+    for mut cpu in engines {
+        let mut bus = PicBus { ram: vec![0; 0x10000], pic: Pic8259::new() };
+        bus.write_buffer(program.origin, program.bytes.clone());
+        let bus: Arc<RwLock<Box<dyn Bus8080>>> = Arc::new(RwLock::new(Box::new(bus)));
+        cpu.set_bus(Arc::clone(&bus));
+        cpu.force_jump(0x0100);
+
+        // Both lines go up together once the PIC is programmed, IR1 has the higher priority.
+        cpu.run_until(&mut |registers| registers.interrupts).unwrap();
+        bus.write().unwrap().push_interrupt(3);
+        bus.write().unwrap().push_interrupt(1);
+
+        // The instruction after EI runs first. The acknowledge then disables interrupts until the handler's EI.
+        assert_eq!(cpu.step().unwrap().opcode, 0x3A);
+        let info = cpu.step().unwrap();
+        assert_eq!((info.opcode, cpu.registers().pc), (0xCD, 0x1004));
+        assert!(!cpu.registers().interrupts);
+        cpu.run_until(&mut |registers| registers.interrupts).unwrap();
+        assert_eq!((cpu.registers().pc, cpu.registers().sp), (exit, 0x01FE));
+        assert_eq!(bus.read().unwrap().read_b(order), 1);
+        cpu.run().unwrap();
+
+        let bus = bus.read().unwrap();
+        assert_eq!((bus.read_b(order), bus.read_b(order + 1)), (1, 3));
+        assert!(!bus.has_interrupt());
+    }
+}
+
+// Requests raised while a handler runs: IR5 is masked, IR3 is below IR1 in service, IR1 comes again and IR0
+// is above it. The PIC passes IR0 on, but the CPU holds everything off until the handler's EI and RET.
+#[test]
+fn test_pic_requests_during_handler()
+{
+    let program = assemble("
+            ORG 0100H
+            LXI SP,0200H
+            MVI A,16H       ; ICW1: single, interval 4.
+            OUT 20H
+            MVI A,10H       ; ICW2: vectors from 1000H.
+            OUT 21H
+            MVI A,20H       ; OCW1: IR5 masked.
+            OUT 21H
+            EI
+    WAIT:   LDA COUNT
+            CPI 4
+            JNZ WAIT
+            OUT 0FFH
+
+    IR0:    PUSH PSW
+            MVI A,0
+            JMP LOG
+    IR1:    PUSH PSW
+            MVI A,1
+            JMP LOG
+    IR3:    PUSH PSW
+            MVI A,3
+            JMP LOG
+    IR5:    PUSH PSW
+            MVI A,5
+    LOG:    PUSH H
+            LHLD NEXT
+            MOV M,A
+            INX H
+            SHLD NEXT
+            LXI H,COUNT
+            INR M
+            POP H
+            MVI A,20H       ; Non-specific EOI.
+            OUT 20H
+            POP PSW
+            EI
+    EXIT:   RET
+
+    COUNT:  DB 0
+    NEXT:   DW ORDER
+    ORDER:  DS 4
+
+            ORG 1000H       ; Four bytes per level.
+            JMP IR0
+            ORG 1004H
+            JMP IR1
+            ORG 100CH
+            JMP IR3
+            ORG 1014H
+            JMP IR5
+    ").unwrap();
+    let (order, exit) = (program.symbols["ORDER"], program.symbols["EXIT"]);
+
+    let engines: [Box<dyn CPU8080>; 2] = [Box::new(Interpreter8080::new()), Box::new(BlockCache8080::new())];
+    for mut cpu in engines {
+        let mut bus = PicBus { ram: vec![0; 0x10000], pic: Pic8259::new() };
+        bus.write_buffer(program.origin, program.bytes.clone());
+        let bus: Arc<RwLock<Box<dyn Bus8080>>> = Arc::new(RwLock::new(Box::new(bus)));
+        cpu.set_bus(Arc::clone(&bus));
+        cpu.force_jump(0x0100);
+
+        cpu.run_until(&mut |registers| registers.interrupts).unwrap();
+        bus.write().unwrap().push_interrupt(1);
+        assert_eq!(cpu.step().unwrap().opcode, 0x3A);
+        let info = cpu.step().unwrap();
+        assert_eq!((info.opcode, cpu.registers().pc), (0xCD, 0x1004));
+
+        for level in [5, 3, 1, 0] {
+            bus.write().unwrap().push_interrupt(level);
+        }
+        assert!(bus.read().unwrap().has_interrupt());
+        assert!(!cpu.registers().interrupts);
+
+        // No nesting: the first handler reaches its RET with only its own return address pushed.
+        cpu.run_until(&mut |registers| registers.interrupts).unwrap();
+        assert_eq!((cpu.registers().pc, cpu.registers().sp), (exit, 0x01FE));
+        assert_eq!(bus.read().unwrap().read_b(order), 1);
+
+        // Then IR0, IR1 again and IR3 in priority order, IR5 stays masked.
+        cpu.run().unwrap();
+        let bus = bus.read().unwrap();
+        assert_eq!((0..4).map(|index| bus.read_b(order + index)).collect::<Vec<u8>>(), [1, 0, 1, 3]);
+        assert!(!bus.has_interrupt());
+    }
+}
@@ -0,0 +1,140 @@
+mod buses;
+
+use std::sync::{Arc, RwLock};
+
+use buses::TestCPMBus;
+use r8080::{asm::assemble, cpu::{Interpreter8080, Pins8085, RegistersZ80, CPU8080}, state::{SaveState, StateError, STATE_VERSION}, Bus8080};
+
+fn machine(program: &str) -> Box<dyn CPU8080> {
+    let program = assemble(program).unwrap();
+    let mut bus = Box::new(TestCPMBus::new(""));
+    bus.write_buffer(program.origin, program.bytes);
+
+    let mut cpu = Box::new(Interpreter8080::new()) as Box<dyn CPU8080>;
+    cpu.force_jump(program.origin);
+    cpu.set_bus(Arc::new(RwLock::new(bus)));
+    cpu
+}
+
+const PROGRAM: &str = "
+        ORG 0100H
+        LXI SP,0200H
+        MVI B,10
+        LXI H,0180H
+LOOP:   MOV M,B
+        INX H
+        DCR B
+        JNZ LOOP
+        OUT 0
+";
+
+#[test]
+fn test_restored_state_runs_to_the_same_result()
+{
+    let mut cpu = machine(PROGRAM);
+    for _ in 0..12 {
+        cpu.step().unwrap();
+    }
+    let bytes = SaveState::capture(cpu.as_mut()).to_bytes();
+    cpu.run().unwrap();
+
+    // An empty machine picks up everything, the program included, from the state.
+    let mut restored = machine("ORG 0100H");
+    SaveState::from_bytes(&bytes).unwrap().restore(restored.as_mut()).unwrap();
+    assert!(restored.registers().running);
+    restored.run().unwrap();
+
+    assert_eq!(restored.registers(), cpu.registers());
+    assert_eq!(restored.get_executed_cycles(), cpu.get_executed_cycles());
+    let memory = |cpu: &dyn CPU8080| (0x0180..0x018A).map(|address| cpu.get_bus().read().unwrap().read_b(address)).collect::<Vec<u8>>();
+    assert_eq!(memory(restored.as_ref()), memory(cpu.as_ref()));
+    assert_eq!(memory(cpu.as_ref()), [10, 9, 8, 7, 6, 5, 4, 3, 2, 1]);
+}
+
+#[test]
+fn test_state_files_round_trip()
+{
+    let mut cpu = machine(PROGRAM);
+    cpu.run().unwrap();
+    let state = SaveState::capture(cpu.as_mut());
+
+    let path = std::env::temp_dir().join(format!("r8080-state-{}.sav", std::process::id()));
+    state.save_file(&path).unwrap();
+    let loaded = SaveState::load_file(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.unwrap(), state);
+}
+
+#[test]
+fn test_bad_states_are_rejected()
+{
+    let mut cpu = machine(PROGRAM);
+    let bytes = SaveState::capture(cpu.as_mut()).to_bytes();
+
+    assert!(matches!(SaveState::from_bytes(b"NOPE, NOT A STATE"), Err(StateError::BadMagic)));
+
+    let mut future = bytes.clone();
+    future[4..6].copy_from_slice(&(STATE_VERSION + 1).to_le_bytes());
+    assert!(matches!(SaveState::from_bytes(&future), Err(StateError::UnsupportedVersion { version }) if version == STATE_VERSION + 1));
+
+    assert!(matches!(SaveState::from_bytes(&bytes[..bytes.len() - 1]), Err(StateError::Truncated { .. })));
+
+    let mut state = SaveState::from_bytes(&bytes).unwrap();
+    state.bus.truncate(0x100);
+    state.registers.pc = 0x1234;
+    assert!(matches!(state.restore(cpu.as_mut()), Err(StateError::InvalidBusState { .. })));
+    assert_eq!(cpu.registers().pc, 0x0100);
+}
+
+// A version 1 state, from before the 8085 pins and the Z80 registers were saved.
+#[test]
+fn test_version_1_states_still_load()
+{
+    let mut bytes = b"R80S".to_vec();
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&0x0123u16.to_le_bytes());
+    bytes.extend_from_slice(&0x0200u16.to_le_bytes());
+    bytes.extend_from_slice(&[0x11, 0x22, 0x33, 0x44, 0x55, 0x02, 0x66, 0x77]);
+    bytes.push(0x05);
+    bytes.extend_from_slice(&1234u64.to_le_bytes());
+    bytes.extend_from_slice(&3u32.to_le_bytes());
+    bytes.extend_from_slice(&[0xAA, 0xBB, 0xCC]);
+
+    let state = SaveState::from_bytes(&bytes).unwrap();
+    let registers = &state.registers;
+    assert_eq!((registers.pc, registers.sp), (0x0123, 0x0200));
+    assert_eq!([registers.a, registers.b, registers.c, registers.d, registers.e, registers.f, registers.h, registers.l], [0x11, 0x22, 0x33, 0x44, 0x55, 0x02, 0x66, 0x77]);
+    assert!(registers.interrupts && registers.running && !registers.halting);
+    assert_eq!(registers.pins, Pins8085::new());
+    assert_eq!(registers.pins.masks, 0x07);
+    assert_eq!(registers.z80, RegistersZ80::new());
+    assert_eq!(state.cycles, 1234);
+    assert_eq!(state.bus, [0xAA, 0xBB, 0xCC]);
+
+    assert!(matches!(SaveState::from_bytes(&bytes[..30]), Err(StateError::Truncated { needed: 31, .. })));
+}
+
+// A version 2 state has the 8085 pins but not the Z80 registers.
+#[test]
+fn test_version_2_states_still_load()
+{
+    let mut bytes = b"R80S".to_vec();
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&0x0123u16.to_le_bytes());
+    bytes.extend_from_slice(&0x0200u16.to_le_bytes());
+    bytes.extend_from_slice(&[0x11, 0x22, 0x33, 0x44, 0x55, 0x02, 0x66, 0x77]);
+    bytes.push(0x04);
+    bytes.extend_from_slice(&[0x02, 0x01]);
+    bytes.extend_from_slice(&1234u64.to_le_bytes());
+    bytes.extend_from_slice(&1u32.to_le_bytes());
+    bytes.push(0xAA);
+
+    let state = SaveState::from_bytes(&bytes).unwrap();
+    let registers = &state.registers;
+    assert_eq!((registers.pc, registers.sp), (0x0123, 0x0200));
+    assert_eq!((registers.pins.masks, registers.pins.lines), (0x02, 0x01));
+    assert!(!registers.pins.rst75 && !registers.pins.trap && !registers.pins.serial_out);
+    assert_eq!(registers.z80, RegistersZ80::new());
+    assert_eq!(state.cycles, 1234);
+    assert_eq!(state.bus, [0xAA]);
+}
@@ -0,0 +1,169 @@
+// Just enough JSON for the test vector files: no escapes beyond the simple ones, numbers read as f64.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value
+{
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>)
+}
+
+impl Value
+{
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Self::Object(fields) => fields.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Self::Array(items) => Some(items),
+            _ => None
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Self::Number(number) if *number >= 0.0 && number.fract() == 0.0 => Some(*number as u64),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(text) => Some(text),
+            _ => None
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser { bytes: text.as_bytes(), at: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.at != parser.bytes.len() {
+        return Err(format!("trailing data at byte {}", parser.at));
+    }
+    Ok(value)
+}
+
+struct Parser<'a>
+{
+    bytes: &'a [u8],
+    at: usize
+}
+
+impl Parser<'_>
+{
+    fn skip_whitespace(&mut self) {
+        while self.bytes.get(self.at).is_some_and(|byte| byte.is_ascii_whitespace()) {
+            self.at += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.bytes.get(self.at).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        if self.peek() != Some(byte) {
+            return Err(format!("expected '{}' at byte {}", byte as char, self.at));
+        }
+        self.at += 1;
+        Ok(())
+    }
+
+    fn keyword(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        if !self.bytes[self.at..].starts_with(word.as_bytes()) {
+            return Err(format!("unexpected input at byte {}", self.at));
+        }
+        self.at += word.len();
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b't') => self.keyword("true", Value::Bool(true)),
+            Some(b'f') => self.keyword("false", Value::Bool(false)),
+            Some(b'n') => self.keyword("null", Value::Null),
+            Some(_) => self.number(),
+            None => Err("unexpected end of input".to_string())
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.expect(b'{')?;
+        let mut fields = Vec::new();
+        if self.peek() == Some(b'}') {
+            self.at += 1;
+            return Ok(Value::Object(fields));
+        }
+        loop {
+            let key = self.string()?;
+            self.expect(b':')?;
+            fields.push((key, self.value()?));
+            match self.peek() {
+                Some(b',') => self.at += 1,
+                _ => break
+            }
+        }
+        self.expect(b'}')?;
+        Ok(Value::Object(fields))
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        if self.peek() == Some(b']') {
+            self.at += 1;
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            match self.peek() {
+                Some(b',') => self.at += 1,
+                _ => break
+            }
+        }
+        self.expect(b']')?;
+        Ok(Value::Array(items))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut result = String::new();
+        loop {
+            let byte = *self.bytes.get(self.at).ok_or("unterminated string")?;
+            self.at += 1;
+            match byte {
+                b'"' => return Ok(result),
+                b'\\' => {
+                    let escaped = *self.bytes.get(self.at).ok_or("unterminated string")?;
+                    self.at += 1;
+                    result.push(match escaped {
+                        b'n' => '\n', b't' => '\t', b'r' => '\r',
+                        other => other as char
+                    });
+                }
+                _ => result.push(byte as char)
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.at;
+        while self.bytes.get(self.at).is_some_and(|byte| matches!(byte, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+            self.at += 1;
+        }
+        let text = std::str::from_utf8(&self.bytes[start..self.at]).unwrap();
+        text.parse().map(Value::Number).map_err(|_| format!("bad number '{}' at byte {}", text, start))
+    }
+}
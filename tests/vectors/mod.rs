@@ -0,0 +1,227 @@
+mod json;
+
+use std::{collections::{BTreeMap, BTreeSet, VecDeque}, fmt, fs, path::Path, sync::{Arc, RwLock}};
+
+use json::Value;
+use r8080::{cpu::{Interpreter8080, Registers, CPU8080, OPCODES, FLAG_CARRY, FLAG_HALF_CARRY, FLAG_PARITY, FLAG_SIGN, FLAG_ZERO}, Bus8080};
+
+// Flat RAM, IN answers from the reads the vector lists.
+struct VectorBus
+{
+    ram: Vec<u8>,
+    inputs: VecDeque<u8>
+}
+
+impl Bus8080 for VectorBus
+{
+    fn read_b(&self, a: u16) -> u8 {
+        self.ram[a as usize]
+    }
+
+    fn read_w(&self, a: u16) -> u16 {
+        u16::from_le_bytes([self.read_b(a), self.read_b(a.wrapping_add(1))])
+    }
+
+    fn has_interrupt(&self) -> bool {
+        false
+    }
+
+    fn get_interrupt(&mut self) -> u8 {
+        0x00
+    }
+
+    fn push_interrupt(&mut self, _: u8) {
+
+    }
+
+    fn write_b(&mut self, a: u16, b: u8) {
+        self.ram[a as usize] = b;
+    }
+
+    fn write_w(&mut self, a: u16, w: u16) {
+        self.write_b(a, w as u8);
+        self.write_b(a.wrapping_add(1), (w >> 8) as u8);
+    }
+
+    fn in_b(&mut self, _: &mut Registers, _: u8) -> u8 {
+        self.inputs.pop_front().unwrap_or(0xFF)
+    }
+
+    fn out_b(&mut self, _: &mut Registers, _: u8, _: u8) {
+
+    }
+
+    fn write_buffer(&mut self, a: u16, data: Vec<u8>) {
+        for (offset, byte) in data.into_iter().enumerate() {
+            self.write_b(a.wrapping_add(offset as u16), byte);
+        }
+    }
+}
+
+// What went wrong for one opcode, over all of its failing vectors.
+#[derive(Debug, Default)]
+pub struct OpcodeFailures
+{
+    pub tests: usize,
+    // Every flag bit that was wrong at least once.
+    pub flags: u8,
+    pub registers: BTreeSet<&'static str>,
+    pub ram: bool,
+    pub cycles: bool,
+    pub first: String
+}
+
+#[derive(Debug, Default)]
+pub struct Report
+{
+    pub passed: usize,
+    pub failures: BTreeMap<u8, OpcodeFailures>
+}
+
+impl Report
+{
+    pub fn merge(&mut self, other: Report) {
+        self.passed += other.passed;
+        for (opcode, failures) in other.failures {
+            let entry = self.failures.entry(opcode).or_default();
+            if entry.tests == 0 {
+                entry.first = failures.first;
+            }
+            entry.tests += failures.tests;
+            entry.flags |= failures.flags;
+            entry.registers.extend(failures.registers);
+            entry.ram |= failures.ram;
+            entry.cycles |= failures.cycles;
+        }
+    }
+}
+
+impl fmt::Display for Report
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let failed: usize = self.failures.values().map(|failures| failures.tests).sum();
+        writeln!(f, "{} passed, {} failed", self.passed, failed)?;
+        for (opcode, failures) in &self.failures {
+            let mut wrong: Vec<String> = Vec::new();
+            if failures.flags != 0 {
+                wrong.push(format!("flags {}", flag_names(failures.flags)));
+            }
+            wrong.extend(failures.registers.iter().map(|register| register.to_string()));
+            if failures.ram {
+                wrong.push("ram".to_string());
+            }
+            if failures.cycles {
+                wrong.push("cycles".to_string());
+            }
+            let info = &OPCODES[*opcode as usize];
+            let mnemonic = format!("{} {}", info.mnemonic, info.operands);
+            writeln!(f, "  {:02X} {:<12} {} failed, wrong {} (first: {})", opcode, mnemonic.trim_end(), failures.tests, wrong.join(", "), failures.first)?;
+        }
+        Ok(())
+    }
+}
+
+fn flag_names(flags: u8) -> String {
+    let names = [(FLAG_SIGN, "S"), (FLAG_ZERO, "Z"), (FLAG_HALF_CARRY, "AC"), (FLAG_PARITY, "P"), (FLAG_CARRY, "CY")];
+    let mut result: Vec<&str> = names.iter().filter(|(flag, _)| flags & flag != 0).map(|(_, name)| *name).collect();
+    if flags & !(FLAG_SIGN | FLAG_ZERO | FLAG_HALF_CARRY | FLAG_PARITY | FLAG_CARRY) != 0 {
+        result.push("unused");
+    }
+    result.join(" ")
+}
+
+fn number(state: &Value, key: &str) -> Result<u64, String> {
+    state.get(key).and_then(Value::as_u64).ok_or_else(|| format!("missing '{}'", key))
+}
+
+fn pairs(value: Option<&Value>) -> Vec<(u64, u64)> {
+    value.and_then(Value::as_array).unwrap_or(&[]).iter()
+        .filter_map(|pair| Some((pair.as_array()?.first()?.as_u64()?, pair.as_array()?.get(1)?.as_u64()?)))
+        .collect()
+}
+
+fn load_registers(state: &Value) -> Result<Registers, String> {
+    let byte = |key: &str| number(state, key).map(|value| value as u8);
+    let mut registers = Registers::new();
+    registers.pc = number(state, "pc")? as u16;
+    registers.sp = number(state, "sp")? as u16;
+    (registers.a, registers.b, registers.c, registers.d) = (byte("a")?, byte("b")?, byte("c")?, byte("d")?);
+    (registers.e, registers.f, registers.h, registers.l) = (byte("e")?, byte("f")?, byte("h")?, byte("l")?);
+    // The vectors do not model interrupts.
+    registers.interrupts = false;
+    Ok(registers)
+}
+
+// Runs one vector, adds it to the report as a pass or under its opcode.
+fn run_vector(vector: &Value, report: &mut Report) -> Result<(), String> {
+    let name = vector.get("name").and_then(Value::as_str).unwrap_or("?").to_string();
+    let (initial, expected) = vector.get("initial").zip(vector.get("final")).ok_or_else(|| format!("{}: no initial or final state", name))?;
+
+    let mut bus = VectorBus { ram: vec![0; 0x10000], inputs: VecDeque::new() };
+    for (address, value) in pairs(initial.get("ram")) {
+        bus.ram[address as usize] = value as u8;
+    }
+    for port in vector.get("ports").and_then(Value::as_array).unwrap_or(&[]) {
+        let port = port.as_array().unwrap_or(&[]);
+        if port.get(2).and_then(Value::as_str) == Some("r") {
+            bus.inputs.extend(port.get(1).and_then(Value::as_u64).map(|value| value as u8));
+        }
+    }
+
+    let registers = load_registers(initial)?;
+    let opcode = bus.ram[registers.pc as usize];
+    let bus: Arc<RwLock<Box<dyn Bus8080>>> = Arc::new(RwLock::new(Box::new(bus)));
+    let mut cpu = Interpreter8080::new();
+    cpu.set_bus(Arc::clone(&bus));
+    *cpu.registers_mut() = registers;
+    let cycles = cpu.step().map_err(|error| format!("{}: {}", name, error))?.cycles;
+
+    let wanted = load_registers(expected)?;
+    let got = cpu.registers();
+    let mut failures = OpcodeFailures { flags: got.f ^ wanted.f, ..Default::default() };
+    let fields = [
+        ("pc", got.pc, wanted.pc), ("sp", got.sp, wanted.sp),
+        ("a", got.a as u16, wanted.a as u16), ("b", got.b as u16, wanted.b as u16), ("c", got.c as u16, wanted.c as u16),
+        ("d", got.d as u16, wanted.d as u16), ("e", got.e as u16, wanted.e as u16), ("h", got.h as u16, wanted.h as u16),
+        ("l", got.l as u16, wanted.l as u16)
+    ];
+    failures.registers = fields.iter().filter(|(_, got, wanted)| got != wanted).map(|(name, _, _)| *name).collect();
+    let bus = bus.read().unwrap();
+    failures.ram = pairs(expected.get("ram")).iter().any(|(address, value)| bus.read_b(*address as u16) != *value as u8);
+    failures.cycles = vector.get("cycles").and_then(Value::as_array).is_some_and(|expected| expected.len() as u32 != cycles);
+
+    if failures.flags == 0 && failures.registers.is_empty() && !failures.ram && !failures.cycles {
+        report.passed += 1;
+    } else {
+        failures.tests = 1;
+        failures.first = name;
+        report.merge(Report { passed: 0, failures: BTreeMap::from([(opcode, failures)]) });
+    }
+    Ok(())
+}
+
+// A JSON array of vectors in the single step test layout.
+pub fn run_json(text: &str) -> Result<Report, String> {
+    let vectors = json::parse(text)?;
+    let mut report = Report::default();
+    for vector in vectors.as_array().ok_or("expected an array of test vectors")? {
+        run_vector(vector, &mut report)?;
+    }
+    Ok(report)
+}
+
+// Every .json file in the directory, None if there is no such directory.
+pub fn run_directory(directory: &Path) -> Option<Report> {
+    let mut files: Vec<_> = fs::read_dir(directory).ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+        .collect();
+    files.sort();
+
+    let mut report = Report::default();
+    for file in files {
+        let text = fs::read_to_string(&file).unwrap_or_else(|error| panic!("{}: {}", file.display(), error));
+        report.merge(run_json(&text).unwrap_or_else(|error| panic!("{}: {}", file.display(), error)));
+    }
+    Some(report)
+}
@@ -0,0 +1,353 @@
+mod buses;
+
+use std::{fs, sync::{Arc, RwLock}};
+
+use buses::TestCPMBus;
+use r8080::{cpu::{InterpreterZ80, Registers, RunExit, CPU8080, FLAG_CARRY, FLAG_HALF_CARRY, FLAG_PARITY, FLAG_SIGN, FLAG_SUBTRACT, FLAG_X3, FLAG_X5, FLAG_ZERO, LINE_NMI}, state::SaveState, Bus8080};
+
+// Flat RAM with an interrupting device and the NMI line, OUT 0FFH stops. With a CP/M style BIOS loaded,
+// OUT 00H stops too and OUT 01H is the BDOS console output.
+struct Z80Bus
+{
+    ram: Vec<u8>,
+    vector: Option<u8>,
+    lines: u8,
+    output: String
+}
+
+impl Bus8080 for Z80Bus
+{
+    fn read_b(&self, a: u16) -> u8 {
+        self.ram[a as usize]
+    }
+
+    fn read_w(&self, a: u16) -> u16 {
+        u16::from_le_bytes([self.read_b(a), self.read_b(a.wrapping_add(1))])
+    }
+
+    fn has_interrupt(&self) -> bool {
+        self.vector.is_some()
+    }
+
+    fn get_interrupt(&mut self) -> u8 {
+        self.vector.take().unwrap_or(0xFF)
+    }
+
+    fn push_interrupt(&mut self, b: u8) {
+        self.vector = Some(b);
+    }
+
+    fn write_b(&mut self, a: u16, b: u8) {
+        self.ram[a as usize] = b;
+    }
+
+    fn write_w(&mut self, a: u16, w: u16) {
+        self.write_b(a, w as u8);
+        self.write_b(a.wrapping_add(1), (w >> 8) as u8);
+    }
+
+    fn in_b(&mut self, _: &mut Registers, _: u8) -> u8 {
+        0xFF
+    }
+
+    fn out_b(&mut self, regs: &mut Registers, b: u8, _: u8) {
+        match (b, regs.c) {
+            (0x00 | 0xFF, _) => regs.running = false,
+            (0x01, 0x02) => self.output.push(regs.e as char),
+            (0x01, 0x09) => {
+                let mut address = ((regs.d as u16) << 8) | regs.e as u16;
+                while self.ram[address as usize] != b'$' {
+                    self.output.push(self.ram[address as usize] as char);
+                    address += 1;
+                }
+            }
+            _ => { }
+        }
+    }
+
+    fn write_buffer(&mut self, a: u16, data: Vec<u8>) {
+        self.ram[a as usize..a as usize + data.len()].copy_from_slice(&data);
+    }
+
+    fn interrupt_lines(&self) -> u8 {
+        self.lines
+    }
+}
+
+// Loads `program` at 0100H, where it starts.
+fn cpu(program: &[u8]) -> InterpreterZ80<Z80Bus> {
+    let mut bus = Z80Bus { ram: vec![0; 0x10000], vector: None, lines: 0, output: String::new() };
+    bus.write_buffer(0x0100, program.to_vec());
+    let mut cpu = InterpreterZ80::with_bus(bus);
+    cpu.force_jump(0x0100);
+    cpu
+}
+
+// The exercisers are not shipped with the other test ROMs, so their tests only run with --ignored and fail without them.
+fn read_exerciser(name: &str) -> Vec<u8> {
+    [name.to_uppercase(), name.to_lowercase()].iter()
+        .find_map(|name| fs::read(format!("test_roms/{}", name)).ok())
+        .unwrap_or_else(|| panic!("[EROR]: test_roms/{} not found.", name))
+}
+
+fn run_exerciser(name: &str) {
+    let rom = read_exerciser(name);
+    let mut cpu = cpu(&rom);
+    // OUT 00H at 0000H, OUT 01H and RET at 0005H.
+    cpu.bus_mut().write_buffer(0x0000, vec![0xD3, 0x00, 0x00, 0x00, 0x00, 0xD3, 0x01, 0xC9]);
+    cpu.run().unwrap();
+
+    let output = &cpu.bus().output;
+    println!("{}", output);
+    assert!(!output.contains("ERROR"));
+    assert!(output.contains("Tests complete"));
+}
+
+#[test]
+#[ignore = "needs ZEXDOC.COM in test_roms"]
+fn test_zexdoc_com()
+{
+    run_exerciser("ZEXDOC.COM");
+}
+
+#[test]
+#[ignore = "needs ZEXALL.COM in test_roms"]
+fn test_zexall_com()
+{
+    run_exerciser("ZEXALL.COM");
+}
+
+#[test]
+fn test_cputest_com_detects_z80()
+{
+    let mut bus = Box::new(TestCPMBus::new("\x00\x00\x00\x00\x00\x00\x0D\x0ADIAGNOSTICS II V1.2 - CPU TEST\x0D\x0ACOPYRIGHT (C) 1981 - SUPERSOFT ASSOCIATES\x0D\x0A\x0AABCDEFGHIJKLMNOPQRSTUVWXYZ\x0D\x0ACPU IS Z80\x0D\x0ABEGIN TIMING TEST\x0D\x0A\x07\x07END TIMING TEST\x0D\x0ACPU TESTS OK\x0D\x0A"));
+    bus.write_buffer(0x0100, fs::read("test_roms/CPUTEST.COM").unwrap());
+
+    let mut cpu = Box::new(InterpreterZ80::new()) as Box<dyn CPU8080>;
+    cpu.force_jump(0x100);
+    cpu.set_bus(Arc::new(RwLock::new(bus)));
+    cpu.run().unwrap();
+}
+
+#[test]
+fn test_index_registers_and_alternates()
+{
+    let mut cpu = cpu(&[
+        0x31, 0x00, 0x02,           // LD SP,0200H
+        0xDD, 0x21, 0x00, 0x03,     // LD IX,0300H
+        0xDD, 0x36, 0x05, 0x42,     // LD (IX+5),42H
+        0xDD, 0x7E, 0x05,           // LD A,(IX+5)
+        0xDD, 0x34, 0x05,           // INC (IX+5)
+        0xFD, 0x21, 0x10, 0x03,     // LD IY,0310H
+        0xFD, 0x46, 0xF5,           // LD B,(IY-11)
+        0x26, 0x12,                 // LD H,12H
+        0xDD, 0x74, 0x00,           // LD (IX+0),H
+        0xDD, 0xCB, 0x05, 0xFE,     // SET 7,(IX+5)
+        0xDD, 0x23,                 // INC IX
+        0x08,                       // EX AF,AF'
+        0xDD, 0x7C,                 // LD A,IXH
+        0xD9,                       // EXX
+        0xDD, 0xE5,                 // PUSH IX
+        0xFD, 0xE1,                 // POP IY
+        0xD3, 0xFF,                 // OUT (0FFH),A
+    ]);
+    cpu.run().unwrap();
+
+    let registers = cpu.registers();
+    assert_eq!((cpu.bus().read_b(0x0300), cpu.bus().read_b(0x0305)), (0x12, 0xC3));
+    assert_eq!((registers.a, registers.b, registers.h), (0x03, 0x00, 0x00));
+    assert_eq!((registers.z80.af >> 8, registers.z80.bc >> 8, registers.z80.hl >> 8), (0x42, 0x43, 0x12));
+    assert_eq!((registers.z80.ix, registers.z80.iy), (0x0301, 0x0301));
+    // Prefixed instructions are two opcode fetches.
+    assert_eq!(registers.z80.r, 29);
+    assert_eq!(cpu.get_executed_cycles(), 233);
+}
+
+#[test]
+fn test_block_transfer_and_search()
+{
+    let mut cpu = cpu(&[
+        0x21, 0x00, 0x03,           // LD HL,0300H
+        0x11, 0x00, 0x04,           // LD DE,0400H
+        0x01, 0x04, 0x00,           // LD BC,4
+        0xED, 0xB0,                 // LDIR
+        0x21, 0x00, 0x03,           // LD HL,0300H
+        0x01, 0x04, 0x00,           // LD BC,4
+        0x3E, 0x33,                 // LD A,33H
+        0xED, 0xB1,                 // CPIR
+        0xD3, 0xFF,                 // OUT (0FFH),A
+    ]);
+    cpu.bus_mut().write_buffer(0x0300, vec![0x11, 0x22, 0x33, 0x44]);
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.bus().ram[0x0400..0x0404], [0x11, 0x22, 0x33, 0x44]);
+    let registers = cpu.registers();
+    assert_eq!((registers.h, registers.l, registers.b, registers.c, registers.d, registers.e), (0x03, 0x03, 0x00, 0x01, 0x04, 0x04));
+    // Found with one byte left: Z, P/V and N.
+    assert_eq!(registers.f & 0x46, 0x46);
+    assert_eq!(cpu.get_executed_cycles(), 205);
+}
+
+#[test]
+fn test_z80_flags()
+{
+    let mut cpu = cpu(&[
+        0x3E, 0x7F,                 // LD A,7FH
+        0xC6, 0x01,                 // ADD A,1
+        0xD6, 0x81,                 // SUB 81H
+        0x27,                       // DAA
+        0xED, 0x44,                 // NEG
+        0xFE, 0x28,                 // CP 28H
+        0xCB, 0x7F,                 // BIT 7,A
+        0x37,                       // SCF
+    ]);
+    let mut flags = Vec::new();
+    for _ in 0..7 {
+        cpu.step().unwrap();
+        flags.push((cpu.registers().a, cpu.registers().f));
+    }
+
+    // Overflow in P/V, N after subtractions, DAA going the other way and X3 / X5 copying the result
+    // (the operand for CP, A for SCF).
+    assert_eq!(flags, [(0x7F, 0x02), (0x80, 0x94), (0xFF, 0xBB), (0x99, 0x8F), (0x67, 0x33), (0x67, 0x3A), (0x67, 0x74)]);
+    cpu.step().unwrap();
+    assert_eq!(cpu.registers().f, 0x65);
+}
+
+// Runs the instruction at 0100H from the given A and F, returns A and F.
+fn flags_after(cpu: &mut InterpreterZ80<Z80Bus>, a: u8, f: u8) -> (u8, u8) {
+    cpu.force_jump(0x0100);
+    (cpu.registers_mut().a, cpu.registers_mut().f) = (a, f);
+    cpu.step().unwrap();
+    (cpu.registers().a, cpu.registers().f)
+}
+
+// S, Z, X5, X3 and parity of a result.
+fn sz53p(result: u8) -> u8 {
+    let parity = if result.count_ones() & 1 == 0 { FLAG_PARITY } else { 0 };
+    result & (FLAG_SIGN | FLAG_X5 | FLAG_X3) | if result == 0 { FLAG_ZERO } else { 0 } | parity
+}
+
+// The undocumented flags the exercisers check, against the tables of "The Undocumented Z80 Documented"
+// (S. Young) rather than the formulas in z80_alu, for every A and incoming N, H and C.
+#[test]
+fn test_z80_flags_match_reference_tables()
+{
+    let inputs = || (0..=0xFFu8).flat_map(|a| (0..8).map(move |bits| (a, bits)));
+    let input_flags = |bits: u8| [FLAG_SUBTRACT, FLAG_HALF_CARRY, FLAG_CARRY].iter().enumerate()
+        .filter(|(index, _)| bits & 1 << index != 0).fold(0, |flags, (_, flag)| flags | flag);
+
+    // DAA: the correction from C, the high nibble, H and the low nibble, then the new C and H.
+    let mut daa = cpu(&[0x27]);
+    for (a, bits) in inputs() {
+        let f = input_flags(bits);
+        let (n, h, c) = (f & FLAG_SUBTRACT != 0, f & FLAG_HALF_CARRY != 0, f & FLAG_CARRY != 0);
+        let (high, low) = (a >> 4, a & 0x0F);
+        let diff = match (c, high, h, low) {
+            (false, 0x0..=0x9, false, 0x0..=0x9) => 0x00,
+            (false, 0x0..=0x9, true, 0x0..=0x9) => 0x06,
+            (false, 0x0..=0x8, _, 0xA..=0xF) => 0x06,
+            (false, 0xA..=0xF, false, 0x0..=0x9) => 0x60,
+            (true, _, false, 0x0..=0x9) => 0x60,
+            _ => 0x66
+        };
+        let carry = c || matches!((high, low), (0x9..=0xF, 0xA..=0xF) | (0xA..=0xF, 0x0..=0x9));
+        let half = if n { h && low <= 5 } else { low >= 0xA };
+        let result = if n { a.wrapping_sub(diff) } else { a.wrapping_add(diff) };
+        let expected = sz53p(result) | if half { FLAG_HALF_CARRY } else { 0 } | f & FLAG_SUBTRACT | if carry { FLAG_CARRY } else { 0 };
+        assert_eq!(flags_after(&mut daa, a, f), (result, expected), "DAA with A = {:02X}, F = {:02X}", a, f);
+    }
+
+    // SCF and CCF: S, Z and P/V are kept, X5 and X3 come from A, CCF moves the old C into H.
+    for (opcode, complement) in [(0x37, false), (0x3F, true)] {
+        let mut cpu = cpu(&[opcode]);
+        for (a, bits) in inputs() {
+            let f = input_flags(bits) | FLAG_SIGN | FLAG_ZERO | FLAG_PARITY;
+            let carry = f & FLAG_CARRY != 0;
+            let (half, carry) = if complement { (carry, !carry) } else { (false, true) };
+            let expected = FLAG_SIGN | FLAG_ZERO | FLAG_PARITY | a & (FLAG_X5 | FLAG_X3)
+                | if half { FLAG_HALF_CARRY } else { 0 } | if carry { FLAG_CARRY } else { 0 };
+            assert_eq!(flags_after(&mut cpu, a, f), (a, expected), "{:02X} with A = {:02X}, F = {:02X}", opcode, a, f);
+        }
+    }
+
+    // BIT n,A: Z and P/V when the bit is clear, S only for a set bit 7, H set, N clear, C kept, X5 and X3 from A.
+    for bit in 0..8 {
+        let mut cpu = cpu(&[0xCB, 0x47 | bit << 3]);
+        for (a, bits) in inputs() {
+            let f = input_flags(bits);
+            let set = a & 1 << bit != 0;
+            let expected = f & FLAG_CARRY | FLAG_HALF_CARRY | a & (FLAG_X5 | FLAG_X3)
+                | if set { 0 } else { FLAG_ZERO | FLAG_PARITY } | if set && bit == 7 { FLAG_SIGN } else { 0 };
+            assert_eq!(flags_after(&mut cpu, a, f), (a, expected), "BIT {},A with A = {:02X}, F = {:02X}", bit, a, f);
+        }
+    }
+}
+
+#[test]
+fn test_z80_timings()
+{
+    let mut cpu = cpu(&[
+        0x06, 0x02,                 // LD B,2
+        0x10, 0xFE,                 // DJNZ $
+        0xAF,                       // XOR A
+        0x20, 0xFE,                 // JR NZ,$
+        0x28, 0x00,                 // JR Z,$+2
+        0xCB, 0x46,                 // BIT 0,(HL)
+        0xDD, 0xE9,                 // JP (IX)
+    ]);
+    let cycles = (0..8).map(|_| cpu.step().unwrap().cycles).collect::<Vec<u32>>();
+    assert_eq!(cycles, [7, 13, 8, 4, 7, 12, 12, 8]);
+    assert_eq!(cpu.registers().pc, 0x0000);
+}
+
+#[test]
+fn test_interrupt_modes()
+{
+    // IM 1 restarts at 0038H, IM 2 calls through the table entry at I * 256 + the byte from the device.
+    let im1 = [0x31, 0x00, 0x02, 0xED, 0x56, 0xFB, 0x18, 0xFE];
+    let im2 = [0x31, 0x00, 0x02, 0x3E, 0x03, 0xED, 0x47, 0xED, 0x5E, 0xFB, 0x18, 0xFE];
+    for (program, vector, handler) in [(&im1[..], 0xFF, 0x0038), (&im2[..], 0x10, 0x0050)] {
+        let mut cpu = cpu(program);
+        cpu.bus_mut().write_buffer(0x0310, vec![0x50, 0x00]);
+        cpu.bus_mut().write_buffer(handler, vec![0xD3, 0xFF]);
+        cpu.bus_mut().vector = Some(vector);
+        cpu.run().unwrap();
+
+        // EI lets the JR run once first.
+        let registers = cpu.registers();
+        assert_eq!(registers.pc, handler + 2);
+        assert_eq!(cpu.bus().read_w(registers.sp), 0x0100 + program.len() as u16 - 2);
+        assert!(!registers.interrupts && !registers.z80.iff2);
+    }
+}
+
+#[test]
+fn test_nmi_wakes_halt_and_retn_restores()
+{
+    let mut cpu = cpu(&[
+        0x31, 0x00, 0x02,           // LD SP,0200H
+        0xFB,                       // EI
+        0x76,                       // HALT
+    ]);
+    cpu.bus_mut().write_buffer(0x0066, vec![0xED, 0x45]);   // RETN
+    for _ in 0..3 {
+        cpu.step().unwrap();
+    }
+    assert_eq!(cpu.run_for_cycles(100).unwrap().exit, RunExit::Halted);
+
+    cpu.bus_mut().lines = LINE_NMI;
+    let info = cpu.step().unwrap();
+    assert_eq!((info.opcode, info.cycles, cpu.registers().pc), (0xCD, 11, 0x0066));
+    assert!(!cpu.registers().interrupts && cpu.registers().z80.iff2 && !cpu.registers().halting);
+
+    let state = SaveState { registers: *cpu.registers(), cycles: cpu.get_executed_cycles(), bus: Vec::new() };
+    assert_eq!(SaveState::from_bytes(&state.to_bytes()).unwrap(), state);
+
+    // RETN brings IFF1 back, and the line staying high does not interrupt again.
+    cpu.step().unwrap();
+    assert_eq!(cpu.registers().pc, 0x0105);
+    assert!(cpu.registers().interrupts);
+    cpu.step().unwrap();
+    assert_eq!(cpu.registers().pc, 0x0106);
+}